@@ -0,0 +1,185 @@
+use serde_json::json;
+use std::sync::atomic::AtomicBool;
+
+use crate::cutoff::{self, CutoffReport};
+use crate::hires::{self, BitUsage, HiResReport};
+use crate::loudness::{self, format_db, LoudnessReport};
+use crate::settings::AudioSource;
+use crate::utils;
+
+/// Clipping events listed in the text output.
+const MAX_LISTED_EVENTS: usize = 10;
+
+/// Options for the `analyze` subcommand.
+pub struct AnalyzeArgs {
+    pub inputs: Vec<String>,
+    /// Print the results of all files as a JSON array instead of text.
+    pub json: bool,
+}
+
+/// Results of the analysis of a single file.
+struct FileAnalysis {
+    /// `None` for silent files.
+    cutoff: Option<CutoffReport>,
+    hires: HiResReport,
+    loudness: LoudnessReport,
+}
+
+/// Analyzes every input file and prints a report for each of them.
+/// Returns an error if any file could not be analyzed.
+pub fn run(args: &AnalyzeArgs) -> Result<(), String> {
+    let mut failed = 0;
+    let mut results = Vec::new();
+
+    for (i, input) in args.inputs.iter().enumerate() {
+        let analysis = analyze_file(input);
+        if analysis.is_err() {
+            failed += 1;
+        }
+        if args.json {
+            results.push(analysis_json(input, &analysis));
+            continue;
+        }
+
+        if i > 0 {
+            println!();
+        }
+        println!("{}", input);
+        match analysis {
+            Ok(analysis) => {
+                print_loudness(&analysis.loudness);
+                match &analysis.cutoff {
+                    Some(report) => print_cutoff(report),
+                    None => println!("  Cutoff:   not analyzed, the file is silent"),
+                }
+                print_hires(&analysis.hires);
+            }
+            Err(e) => eprintln!("  error: {}", e),
+        }
+    }
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&results)
+            .map_err(|e| format!("failed to write JSON: {}", e))?;
+        println!("{}", json);
+    }
+
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} of {} files could not be analyzed",
+            failed,
+            args.inputs.len()
+        ))
+    }
+}
+
+fn analyze_file(input: &str) -> Result<FileAnalysis, String> {
+    let audio_info = utils::get_audio_info(input, &AudioSource::default())
+        .ok_or_else(|| format!("failed to read audio info from '{}'", input))?;
+    let cancel_token = AtomicBool::new(false);
+    let loudness = loudness::measure_loudness(input, &audio_info, &cancel_token)
+        .ok_or("failed to measure the loudness")?;
+    // The cutoff and the hi-res check share one pass over the file
+    let mut bits = BitUsage::default();
+    let power = cutoff::average_power(input, &audio_info, &cancel_token, |samples| {
+        bits.add(samples)
+    })
+    .ok_or("failed to analyze the spectrum")?;
+    let cutoff = cutoff::analyze_spectrum(&power, audio_info.sample_rate as f32 / 2.0);
+    let hires = hires::analyze_spectrum(&power, &bits, &audio_info);
+    Ok(FileAnalysis {
+        cutoff,
+        hires,
+        loudness,
+    })
+}
+
+fn analysis_json(input: &str, analysis: &Result<FileAnalysis, String>) -> serde_json::Value {
+    match analysis {
+        Ok(analysis) => json!({
+            "file": input,
+            "loudness": analysis.loudness,
+            "cutoff": analysis.cutoff.map(|report| json!({
+                "cutoff_hz": report.cutoff_hz,
+                "nyquist_hz": report.nyquist_hz,
+                "drop_db": report.drop_db,
+                "lossy_confidence": report.confidence,
+                "likely_source": report.likely_source(),
+                "verdict": report.verdict(),
+            })),
+            "hires": json!({
+                "sample_rate": analysis.hires.sample_rate,
+                "bits_per_sample": analysis.hires.bits_per_sample,
+                "bandwidth_hz": analysis.hires.bandwidth_hz,
+                "upsampled_from": analysis.hires.upsampled_from,
+                "effective_bits": analysis.hires.effective_bits,
+                "verdict": analysis.hires.verdict(),
+            }),
+        }),
+        Err(e) => json!({ "file": input, "error": e }),
+    }
+}
+
+fn print_loudness(report: &LoudnessReport) {
+    let lufs = |value: Option<f32>| value.map_or("-".to_string(), |v| format!("{:.1}", v));
+    println!(
+        "  Loudness: {} LUFS integrated, LRA {} LU, short-term max {} LUFS",
+        lufs(report.integrated_lufs),
+        lufs(report.loudness_range_lu),
+        lufs(report.short_term_max_lufs)
+    );
+    println!(
+        "  Peak:     {} dBFS sample, {} dBTP true",
+        format_db(report.sample_peak_dbfs),
+        format_db(report.true_peak_dbtp)
+    );
+    let rms: Vec<String> = report
+        .channels
+        .iter()
+        .map(|channel| format!("{} {} dBFS", channel.name, format_db(channel.rms_dbfs)))
+        .collect();
+    println!("  RMS:      {}", rms.join(", "));
+
+    let clipping = &report.clipping;
+    println!("  Clipping: {}", clipping.summary());
+    for event in clipping.events.iter().take(MAX_LISTED_EVENTS) {
+        println!("            {}", event.describe());
+    }
+    let total = clipping.clipped_events + clipping.true_peak_overs;
+    if total > MAX_LISTED_EVENTS {
+        println!("            ... and {} more", total - MAX_LISTED_EVENTS);
+    }
+}
+
+fn print_cutoff(report: &CutoffReport) {
+    match report.cutoff_hz {
+        Some(cutoff_hz) => println!(
+            "  Cutoff:   {:.1} kHz (Nyquist {:.2} kHz, drop {:.0} dB)",
+            cutoff_hz / 1000.0,
+            report.nyquist_hz / 1000.0,
+            report.drop_db
+        ),
+        None => println!(
+            "  Cutoff:   none (Nyquist {:.2} kHz)",
+            report.nyquist_hz / 1000.0
+        ),
+    }
+    println!("  Verdict:  {}", report.verdict());
+}
+
+fn print_hires(report: &HiResReport) {
+    let bits = report
+        .effective_bits
+        .map_or("-".to_string(), |bits| format!("{}-bit", bits));
+    let bandwidth = report
+        .bandwidth_hz
+        .map_or("full".to_string(), |hz| format!("{:.1} kHz", hz / 1000.0));
+    println!(
+        "  Hi-res:   {} (content up to {}, {} used)",
+        report.verdict(),
+        bandwidth,
+        bits
+    );
+}
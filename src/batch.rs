@@ -0,0 +1,245 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use crate::cli::{self, RenderOptions};
+
+/// File extensions processed by default in batch mode.
+pub const AUDIO_EXTENSIONS: [&str; 18] = [
+    "flac", "wav", "mp3", "m4a", "aac", "ogg", "oga", "opus", "wv", "ape", "aif", "aiff", "alac",
+    "wma", "dsf", "dff", "tta", "mka",
+];
+
+/// Options for the `batch` subcommand.
+pub struct BatchArgs {
+    pub input_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub extensions: Vec<String>,
+    pub pattern: Option<String>,
+    pub jobs: usize,
+    pub report: Option<PathBuf>,
+    pub options: RenderOptions,
+}
+
+struct BatchResult {
+    input: PathBuf,
+    output: PathBuf,
+    error: Option<String>,
+}
+
+/// Renders every matching file below `args.input_dir` into `args.output_dir`.
+/// Returns an error if the directory cannot be read or any file failed.
+pub fn run(args: &BatchArgs) -> Result<(), String> {
+    let start = Instant::now();
+
+    let mut files = Vec::new();
+    let skip_dir = fs::canonicalize(&args.output_dir).ok();
+    collect_files(&args.input_dir, args, skip_dir.as_deref(), &mut files)
+        .map_err(|e| format!("failed to read '{}': {}", args.input_dir.display(), e))?;
+    files.sort();
+
+    if files.is_empty() {
+        return Err(format!(
+            "no matching audio files found in '{}'",
+            args.input_dir.display()
+        ));
+    }
+
+    let total = files.len();
+    let jobs = args.jobs.min(total);
+    println!("Processing {} files with {} workers...", total, jobs);
+
+    let queue = Arc::new(Mutex::new(files.into_iter()));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers: Vec<_> = (0..jobs)
+        .map(|_| {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let input_dir = args.input_dir.clone();
+            let output_dir = args.output_dir.clone();
+            let options = args.options.clone();
+            thread::spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                let Some(input) = next else {
+                    break;
+                };
+                let output = output_path(&input, &input_dir, &output_dir, options.extension());
+                let error = render_one(&input, &output, &options).err();
+                if sender
+                    .send(BatchResult {
+                        input,
+                        output,
+                        error,
+                    })
+                    .is_err()
+                {
+                    break;
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut results = Vec::with_capacity(total);
+    for result in receiver {
+        let status = if result.error.is_some() { "FAIL" } else { "OK" };
+        println!(
+            "[{}/{}] {:4} {}",
+            results.len() + 1,
+            total,
+            status,
+            result.input.display()
+        );
+        if let Some(e) = &result.error {
+            eprintln!("       {}", e);
+        }
+        results.push(result);
+    }
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    results.sort_by(|a, b| a.input.cmp(&b.input));
+    let failed: Vec<&BatchResult> = results.iter().filter(|r| r.error.is_some()).collect();
+
+    println!(
+        "\nDone in {:.1?}: {} succeeded, {} failed.",
+        start.elapsed(),
+        results.len() - failed.len(),
+        failed.len()
+    );
+    for result in &failed {
+        println!("  {}", result.input.display());
+    }
+
+    if let Some(report) = &args.report {
+        write_report(report, &results)
+            .map_err(|e| format!("failed to write report '{}': {}", report.display(), e))?;
+        println!("Report saved to {}", report.display());
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} of {} files failed", failed.len(), total))
+    }
+}
+
+fn collect_files(
+    dir: &Path,
+    args: &BatchArgs,
+    skip_dir: Option<&Path>,
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            // Don't descend into the output directory if it's inside the input one
+            if skip_dir.is_none() || fs::canonicalize(&path).ok().as_deref() != skip_dir {
+                collect_files(&path, args, skip_dir, files)?;
+            }
+        } else if is_match(&path, args) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_match(path: &Path, args: &BatchArgs) -> bool {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    if !args.extensions.contains(&extension) {
+        return false;
+    }
+
+    match &args.pattern {
+        Some(pattern) => {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            wildcard_match(&pattern.to_lowercase(), &name.to_lowercase())
+        }
+        None => true,
+    }
+}
+
+/// Matches `text` against a simple glob pattern supporting `*` and `?`.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Mirrors the location of `input` below `input_dir` into `output_dir`, appending `extension`.
+fn output_path(input: &Path, input_dir: &Path, output_dir: &Path, extension: &str) -> PathBuf {
+    let relative = input.strip_prefix(input_dir).unwrap_or(input);
+    let mut output = output_dir.join(relative);
+    let mut file_name = output.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    output.set_file_name(file_name);
+    output
+}
+
+fn render_one(input: &Path, output: &Path, options: &RenderOptions) -> Result<(), String> {
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("failed to create '{}': {}", parent.display(), e))?;
+    }
+    cli::render_file(&input.to_string_lossy(), output, options)
+}
+
+fn write_report(path: &Path, results: &[BatchResult]) -> std::io::Result<()> {
+    let mut content = String::from("status,input,output,error\n");
+    for result in results {
+        let status = if result.error.is_some() {
+            "failed"
+        } else {
+            "ok"
+        };
+        content.push_str(&format!(
+            "{},{},{},{}\n",
+            status,
+            csv_field(&result.input.to_string_lossy()),
+            csv_field(&result.output.to_string_lossy()),
+            csv_field(result.error.as_deref().unwrap_or(""))
+        ));
+    }
+    fs::write(path, content)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
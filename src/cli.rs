@@ -0,0 +1,911 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use crate::analyze::{self, AnalyzeArgs};
+use crate::batch::{self, BatchArgs};
+use crate::diff;
+use crate::export::{self, DataFormat, ImageFormat};
+use crate::settings::{
+    AppSettings, AudioSource, Difference, DifferenceMode, FrequencyScale, SpectogramWinFunc,
+    SpectrogramColorScheme, SpectrogramEngine, SpectrogramScale, DB_LIMIT_BOUNDS, DB_RANGE_BOUNDS,
+    FFT_SIZES, MAX_OVERLAP,
+};
+use crate::spectrum::{self, SpectrumRequest};
+use crate::utils;
+
+const USAGE: &str = "Usage:
+  spek-rs [FILE...]                       Open files in the GUI
+  spek-rs render <FILE> [OPTIONS]         Render a spectrogram to an image without a window
+  spek-rs diff <FILE> <FILE> [OPTIONS]    Render the difference between two files
+  spek-rs batch <DIR> -o <DIR> [OPTIONS]  Render every audio file in a directory tree
+  spek-rs analyze <FILE...> [--json]      Measure the loudness and detect the frequency
+                                          cutoff of lossy transcodes
+  spek-rs streams <FILE...>               List the audio streams and channels of files
+  spek-rs spectrum <FILE> [OPTIONS]       Plot the average spectrum with peak hold, or
+                                          write it as CSV
+
+GUI options:
+      --new-instance        Open a new window instead of the already running one
+
+Render options:
+  -o, --output <FILE>       Output image path (default: <input name>.png), a .csv, .npy
+                            or .raw (little-endian f32) path writes the levels in dBFS
+                            of every time slice and frequency bin instead, described by
+                            a .json file next to it
+      --format <NAME>       Image format: png, jpeg, webp (lossless), tiff, or png16 for
+                            a 16-bit grayscale PNG of the levels before the palette,
+                            without legend (default: follows the output extension)
+      --quality <1-100>     JPEG quality (default: 90)
+
+Spectrum options:
+  -o, --output <FILE>       Output image or .csv path (default: <input name>-spectrum.png),
+                            -W and -H set the size of the plot, --from and --to the part
+                            of the file averaged, --format and --quality as for render
+
+Diff options:
+  -o, --output <FILE>       Output image path (default: <first input name>-diff.png),
+                            --format and --quality as for render
+  -m, --mode <NAME>         delta: level of the first file relative to the second in dB
+                            (default), signal: spectrogram of the first minus the second
+  -r, --range <DB>          Largest delta shown by the palette (default: 30)
+      --align               Compensate the delay of the second file, e.g. encoder delay
+      --offset <SECONDS>    Delay of the second file against the first one
+
+Batch options:
+  -o, --output <DIR>        Output directory, the input folder structure is mirrored
+      --format <NAME>       Image format of the outputs, as for render (default: png)
+      --quality <1-100>     JPEG quality (default: 90)
+  -e, --ext <LIST>          Comma separated file extensions (default: common audio formats)
+      --glob <PATTERN>      Only process file names matching the pattern, e.g. '*live*'
+  -j, --jobs <N>            Number of parallel workers (default: number of CPUs)
+      --report <FILE>       Write a CSV summary of all processed files
+
+Spectrogram options (render, diff, batch and spectrum):
+  -W, --width <PX>          Spectrogram width in pixels (default: 500)
+  -H, --height <PX>         Spectrogram height in pixels (default: 320)
+  -p, --palette <NAME>      Color palette, e.g. intensity, magma, viridis
+  -f, --win-func <NAME>     Window function, e.g. hann, blackman, kaiser
+  -a, --scale <NAME>        Intensity scale: lin, sqrt, cbrt, log, 4thrt, 5thrt
+  -g, --gain <VALUE>        Scale gain (default: 1)
+  -t, --saturation <VALUE>  Color saturation (default: 1)
+  -c, --split-channels      Draw each channel separately
+      --stream <N>          Audio stream to analyze, counted from 1 (default: 1)
+      --channels <LIST>     Comma separated channels of the stream, e.g. 4 for LFE of 5.1
+      --hide-channels <LIST>
+                            Comma separated channels of the stream to leave out
+      --mid-side            Draw the mid (L+R) and side (L-R) signals of the first two
+                            channels instead of the channels themselves
+      --waveform            Draw the peak waveform under the spectrogram, one lane
+                            per channel with --split-channels (custom legend only)
+      --phase-correlation   Draw the phase correlation of the first two channels
+                            under the spectrogram (custom legend only)
+      --engine <NAME>       Spectrogram engine: ffmpeg (default) or native
+      --freq-scale <NAME>   Frequency axis: lin (default), log, mel or bark,
+                            mel and bark always use the native engine
+      --fft-size <N>        FFT size from 256 to 65536 (default: follows the height),
+                            uses the native engine
+      --overlap <PERCENT>   Overlap of the FFT frames averaged into one column
+                            (default: 0), uses the native engine
+      --db-range <DB>       Dynamic range of the levels shown (default: 120)
+      --db-limit <DB>       Upper level limit in dBFS (default: 0)
+      --from <TIME>         Start of the rendered part, in seconds or m:ss (default: 0)
+      --to <TIME>           End of the rendered part (default: end of the file)
+      --min-freq <HZ>       Lowest frequency shown (default: 0)
+      --max-freq <HZ>       Highest frequency shown (default: half the sample rate)
+      --detect-cutoff       Mark the detected frequency cutoff of lossy sources
+      --loudness-legend     Print the integrated loudness and true peak into the legend
+      --clip-markers        Mark clipped samples and true peak overs under the time scale
+      --ffmpeg-legend       Use the legend generated by ffmpeg instead of the custom one
+      --horizontal          Horizontal orientation (only with --ffmpeg-legend)
+      --no-legend           Render the spectrogram without any legend
+
+  -h, --help                Print this help
+  -V, --version             Print version";
+
+/// Spectrogram options shared by the rendering subcommands.
+#[derive(Clone)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    pub settings: AppSettings,
+    /// Zero based channels of the stream left out of the spectrogram.
+    pub hidden_channels: Vec<usize>,
+    /// Part of the file rendered, in seconds, `None` for its start or end.
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+    /// Image format, `None` follows the extension of the output.
+    pub format: Option<ImageFormat>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        let settings = AppSettings::default();
+        Self {
+            width: settings.resolution[0],
+            height: settings.resolution[1],
+            settings,
+            hidden_channels: Vec::new(),
+            from: None,
+            to: None,
+            format: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Applies a single rendering option, taking its value from `iter` if needed.
+    /// Returns `Ok(false)` if `arg` is not a rendering option.
+    fn parse_arg(
+        &mut self,
+        arg: &str,
+        iter: &mut std::slice::Iter<String>,
+    ) -> Result<bool, String> {
+        match arg {
+            "-W" | "--width" => self.width = parse_number(arg, &required_value(arg, iter)?)?,
+            "-H" | "--height" => self.height = parse_number(arg, &required_value(arg, iter)?)?,
+            "-p" | "--palette" => {
+                self.settings.color_scheme = parse_named(
+                    &required_value(arg, iter)?,
+                    &SpectrogramColorScheme::VALUES,
+                    SpectrogramColorScheme::as_str,
+                    "palette",
+                )?
+            }
+            "-f" | "--win-func" => {
+                self.settings.win_func = parse_named(
+                    &required_value(arg, iter)?,
+                    &SpectogramWinFunc::VALUES,
+                    SpectogramWinFunc::as_str,
+                    "window function",
+                )?
+            }
+            "-a" | "--scale" => {
+                self.settings.scale = parse_named(
+                    &required_value(arg, iter)?,
+                    &SpectrogramScale::VALUES,
+                    SpectrogramScale::as_str,
+                    "scale",
+                )?
+            }
+            "-g" | "--gain" => self.settings.gain = parse_number(arg, &required_value(arg, iter)?)?,
+            "-t" | "--saturation" => {
+                self.settings.saturation = parse_number(arg, &required_value(arg, iter)?)?
+            }
+            "-c" | "--split-channels" => self.settings.split_channels = true,
+            "--stream" => {
+                let stream: usize = parse_number(arg, &required_value(arg, iter)?)?;
+                if stream == 0 {
+                    return Err("streams are counted from 1".to_string());
+                }
+                self.settings.source.stream = stream - 1;
+            }
+            "--channels" => {
+                self.settings.source.channels = parse_channels(arg, &required_value(arg, iter)?)?
+            }
+            "--hide-channels" => {
+                self.hidden_channels = parse_channels(arg, &required_value(arg, iter)?)?
+            }
+            "--mid-side" => {
+                self.settings.source.mid_side = true;
+                self.settings.split_channels = true;
+            }
+            "--waveform" => self.settings.waveform = true,
+            "--phase-correlation" => self.settings.phase_correlation = true,
+            "--engine" => {
+                self.settings.engine = parse_named(
+                    &required_value(arg, iter)?,
+                    &SpectrogramEngine::VALUES,
+                    SpectrogramEngine::as_str,
+                    "engine",
+                )?
+            }
+            "--freq-scale" => {
+                self.settings.frequency_scale = parse_named(
+                    &required_value(arg, iter)?,
+                    &FrequencyScale::VALUES,
+                    FrequencyScale::as_str,
+                    "frequency scale",
+                )?
+            }
+            "--fft-size" => {
+                self.settings.fft_size = Some(parse_number(arg, &required_value(arg, iter)?)?)
+            }
+            "--overlap" => {
+                let percent: f32 = parse_number(arg, &required_value(arg, iter)?)?;
+                self.settings.overlap = percent / 100.0;
+            }
+            "--db-range" => {
+                self.settings.db_range = parse_number(arg, &required_value(arg, iter)?)?
+            }
+            "--db-limit" => {
+                self.settings.db_limit = parse_number(arg, &required_value(arg, iter)?)?
+            }
+            "--from" => self.from = Some(parse_time_arg(arg, &required_value(arg, iter)?)?),
+            "--to" => self.to = Some(parse_time_arg(arg, &required_value(arg, iter)?)?),
+            "--min-freq" => {
+                self.settings.min_frequency = Some(parse_number(arg, &required_value(arg, iter)?)?)
+            }
+            "--max-freq" => {
+                self.settings.max_frequency = Some(parse_number(arg, &required_value(arg, iter)?)?)
+            }
+            "--detect-cutoff" => self.settings.detect_cutoff = true,
+            "--loudness-legend" => self.settings.loudness_legend = true,
+            "--clip-markers" => self.settings.clip_markers = true,
+            "--ffmpeg-legend" => self.settings.custom_legend = false,
+            "--horizontal" => self.settings.horizontal = true,
+            "--no-legend" => self.settings.legend = false,
+            "--format" => {
+                let value = required_value(arg, iter)?.to_ascii_lowercase();
+                let value = match value.as_str() {
+                    "jpg" => "jpeg",
+                    "tif" => "tiff",
+                    value => value,
+                };
+                self.format = Some(parse_named(
+                    value,
+                    &ImageFormat::VALUES,
+                    ImageFormat::as_str,
+                    "image format",
+                )?)
+            }
+            "--quality" => {
+                self.settings.jpeg_quality = parse_number(arg, &required_value(arg, iter)?)?
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.width < 100 || self.height < 100 {
+            return Err("width and height must be at least 100 px".to_string());
+        }
+        if let Some(to) = self.to {
+            match self.from {
+                Some(from) if to <= from => {
+                    return Err("--to must be later than --from".to_string())
+                }
+                None if to <= 0.0 => return Err("--to must be greater than 0".to_string()),
+                _ => {}
+            }
+        }
+        let min_frequency = self.settings.min_frequency.unwrap_or(0.0);
+        if min_frequency < 0.0 {
+            return Err("--min-freq can't be negative".to_string());
+        }
+        if self
+            .settings
+            .max_frequency
+            .is_some_and(|max| max <= min_frequency)
+        {
+            return Err("--max-freq must be higher than --min-freq".to_string());
+        }
+        if let Some(fft_size) = self.settings.fft_size {
+            if !FFT_SIZES.contains(&fft_size) {
+                return Err("--fft-size must be a power of two from 256 to 65536".to_string());
+            }
+        }
+        if !(0.0..=MAX_OVERLAP).contains(&self.settings.overlap) {
+            return Err(format!(
+                "--overlap must be from 0 to {:.0}",
+                MAX_OVERLAP * 100.0
+            ));
+        }
+        if !(1..=100).contains(&self.settings.jpeg_quality) {
+            return Err("--quality must be from 1 to 100".to_string());
+        }
+        if !DB_RANGE_BOUNDS.contains(&self.settings.db_range) {
+            return Err(format!(
+                "--db-range must be from {} to {}",
+                DB_RANGE_BOUNDS.start(),
+                DB_RANGE_BOUNDS.end()
+            ));
+        }
+        if !DB_LIMIT_BOUNDS.contains(&self.settings.db_limit) {
+            return Err(format!(
+                "--db-limit must be from {} to {}",
+                DB_LIMIT_BOUNDS.start(),
+                DB_LIMIT_BOUNDS.end()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Extension of new output files, PNG unless a format is set.
+    pub fn extension(&self) -> &'static str {
+        self.format.map_or("png", |format| format.extensions()[0])
+    }
+
+    /// Image format of `output`, the one set or else the one of its extension.
+    fn image_format(&self, output: &Path) -> ImageFormat {
+        self.format
+            .or_else(|| ImageFormat::from_path(output))
+            .unwrap_or(ImageFormat::Png)
+    }
+}
+
+/// Options for the `render` subcommand.
+pub struct RenderArgs {
+    pub input: String,
+    pub output: PathBuf,
+    pub options: RenderOptions,
+}
+
+/// Options for the `diff` subcommand.
+pub struct DiffArgs {
+    pub input: String,
+    pub difference: Difference,
+    pub output: PathBuf,
+    pub options: RenderOptions,
+}
+
+enum Command {
+    Help,
+    Version,
+    Render(RenderArgs),
+    Diff(DiffArgs),
+    Batch(BatchArgs),
+    Analyze(AnalyzeArgs),
+    Streams(Vec<String>),
+    Spectrum(RenderArgs),
+}
+
+/// Runs the command line interface if the arguments ask for it.
+/// Returns the process exit code, or `None` if the GUI should be started instead.
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = match parse(args)? {
+        Ok(command) => command,
+        Err(e) => {
+            attach_console();
+            eprintln!("error: {}\nRun 'spek-rs --help' for usage.", e);
+            return Some(2);
+        }
+    };
+
+    attach_console();
+    let result = match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            return Some(0);
+        }
+        Command::Version => {
+            println!("spek-rs v{}", env!("CARGO_PKG_VERSION"));
+            return Some(0);
+        }
+        Command::Render(render_args) => check_ffmpeg()
+            .and_then(|_| {
+                render_file(
+                    &render_args.input,
+                    &render_args.output,
+                    &render_args.options,
+                )
+            })
+            .map(|_| {
+                let options = &render_args.options;
+                let saved = if options.format.is_none()
+                    && DataFormat::from_path(&render_args.output).is_some()
+                {
+                    "Data"
+                } else {
+                    "Image"
+                };
+                println!("{} saved to {}", saved, render_args.output.display())
+            }),
+        Command::Diff(diff_args) => check_ffmpeg()
+            .and_then(|_| render_difference(&diff_args))
+            .map(|_| println!("Image saved to {}", diff_args.output.display())),
+        Command::Batch(batch_args) => check_ffmpeg().and_then(|_| batch::run(&batch_args)),
+        Command::Analyze(analyze_args) => check_ffmpeg().and_then(|_| analyze::run(&analyze_args)),
+        Command::Streams(inputs) => check_ffmpeg().and_then(|_| list_streams(&inputs)),
+        Command::Spectrum(spectrum_args) => check_ffmpeg()
+            .and_then(|_| save_spectrum(&spectrum_args))
+            .map(|_| println!("Spectrum saved to {}", spectrum_args.output.display())),
+    };
+
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            Some(1)
+        }
+    }
+}
+
+fn parse(args: &[String]) -> Option<Result<Command, String>> {
+    match args.get(1).map(String::as_str) {
+        Some("-h") | Some("--help") => Some(Ok(Command::Help)),
+        Some("-V") | Some("--version") => Some(Ok(Command::Version)),
+        Some("render") | Some("diff") | Some("batch") | Some("analyze") | Some("streams")
+        | Some("spectrum")
+            if args[2..].iter().any(|a| a == "-h" || a == "--help") =>
+        {
+            Some(Ok(Command::Help))
+        }
+        Some("render") => Some(parse_render(&args[2..], "").map(Command::Render)),
+        Some("diff") => Some(parse_diff(&args[2..]).map(Command::Diff)),
+        Some("batch") => Some(parse_batch(&args[2..]).map(Command::Batch)),
+        Some("analyze") => Some(parse_analyze(&args[2..]).map(Command::Analyze)),
+        Some("streams") => Some(parse_inputs(&args[2..]).map(Command::Streams)),
+        Some("spectrum") => Some(parse_render(&args[2..], "-spectrum").map(Command::Spectrum)),
+        _ => None,
+    }
+}
+
+/// Parses the arguments of `render` and `spectrum`, the default output is the input name
+/// followed by `suffix`.
+fn parse_render(args: &[String], suffix: &str) -> Result<RenderArgs, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut options = RenderOptions::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if options.parse_arg(arg, &mut iter)? {
+            continue;
+        }
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(required_value(arg, &mut iter)?)),
+            _ if is_option(arg) => return Err(format!("unknown option '{}'", arg)),
+            _ if input.is_none() => input = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let input = input.ok_or("missing input file")?;
+    options.validate()?;
+
+    let output = output.unwrap_or_else(|| {
+        let stem = Path::new(&input)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("spectrogram");
+        PathBuf::from(format!("{}{}.{}", stem, suffix, options.extension()))
+    });
+
+    Ok(RenderArgs {
+        input,
+        output,
+        options,
+    })
+}
+
+fn parse_diff(args: &[String]) -> Result<DiffArgs, String> {
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut mode = DifferenceMode::Delta;
+    let mut range_db = Difference::DEFAULT_RANGE_DB;
+    let mut align = false;
+    let mut offset = 0.0;
+    let mut options = RenderOptions::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if options.parse_arg(arg, &mut iter)? {
+            continue;
+        }
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(required_value(arg, &mut iter)?)),
+            "-m" | "--mode" => {
+                mode = parse_named(
+                    &required_value(arg, &mut iter)?,
+                    &DifferenceMode::VALUES,
+                    DifferenceMode::as_str,
+                    "mode",
+                )?
+            }
+            "-r" | "--range" => range_db = parse_number(arg, &required_value(arg, &mut iter)?)?,
+            "--align" => align = true,
+            "--offset" => offset = parse_number(arg, &required_value(arg, &mut iter)?)?,
+            _ if is_option(arg) => return Err(format!("unknown option '{}'", arg)),
+            _ if inputs.len() < 2 => inputs.push(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let [input, other]: [String; 2] = inputs
+        .try_into()
+        .map_err(|_| "diff needs two input files")?;
+    if range_db <= 0.0 {
+        return Err("--range must be greater than 0".to_string());
+    }
+    if align && offset != 0.0 {
+        return Err("--align and --offset can't be used together".to_string());
+    }
+    options.validate()?;
+
+    let output = output.unwrap_or_else(|| {
+        let stem = Path::new(&input)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("spectrogram");
+        PathBuf::from(format!("{}-diff.{}", stem, options.extension()))
+    });
+
+    Ok(DiffArgs {
+        input,
+        difference: Difference {
+            mode,
+            align,
+            offset,
+            range_db,
+            ..Difference::new(other)
+        },
+        output,
+        options,
+    })
+}
+
+fn parse_batch(args: &[String]) -> Result<BatchArgs, String> {
+    let mut input_dir = None;
+    let mut output_dir = None;
+    let mut extensions = None;
+    let mut pattern = None;
+    let mut jobs = None;
+    let mut report = None;
+    let mut options = RenderOptions::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if options.parse_arg(arg, &mut iter)? {
+            continue;
+        }
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output_dir = Some(PathBuf::from(required_value(arg, &mut iter)?));
+            }
+            "-e" | "--ext" => {
+                extensions = Some(
+                    required_value(arg, &mut iter)?
+                        .split(',')
+                        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+                        .filter(|e| !e.is_empty())
+                        .collect(),
+                );
+            }
+            "--glob" => pattern = Some(required_value(arg, &mut iter)?),
+            "-j" | "--jobs" => jobs = Some(parse_number(arg, &required_value(arg, &mut iter)?)?),
+            "--report" => report = Some(PathBuf::from(required_value(arg, &mut iter)?)),
+            _ if is_option(arg) => return Err(format!("unknown option '{}'", arg)),
+            _ if input_dir.is_none() => input_dir = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let input_dir = input_dir.ok_or("missing input directory")?;
+    let output_dir = output_dir.ok_or("missing output directory (--output)")?;
+    options.validate()?;
+
+    let jobs = jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    if jobs == 0 {
+        return Err("--jobs must be at least 1".to_string());
+    }
+
+    let extensions = extensions.unwrap_or_else(|| {
+        batch::AUDIO_EXTENSIONS
+            .iter()
+            .map(|e| e.to_string())
+            .collect()
+    });
+
+    Ok(BatchArgs {
+        input_dir,
+        output_dir,
+        extensions,
+        pattern,
+        jobs,
+        report,
+        options,
+    })
+}
+
+fn parse_analyze(args: &[String]) -> Result<AnalyzeArgs, String> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let rest: Vec<String> = args
+        .iter()
+        .filter(|arg| *arg != "--json")
+        .cloned()
+        .collect();
+    Ok(AnalyzeArgs {
+        inputs: parse_inputs(&rest)?,
+        json,
+    })
+}
+
+/// Input files of the subcommands without options.
+fn parse_inputs(args: &[String]) -> Result<Vec<String>, String> {
+    let mut inputs = Vec::new();
+    for arg in args {
+        if is_option(arg) {
+            return Err(format!("unknown option '{}'", arg));
+        }
+        inputs.push(arg.clone());
+    }
+
+    if inputs.is_empty() {
+        return Err("missing input file".to_string());
+    }
+    Ok(inputs)
+}
+
+/// Prints the audio streams of every input file with their channels.
+fn list_streams(inputs: &[String]) -> Result<(), String> {
+    let mut failed = 0;
+    for (i, input) in inputs.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", input);
+        match utils::get_audio_streams(input) {
+            Some(streams) if !streams.is_empty() => {
+                for (index, stream) in streams.iter().enumerate() {
+                    println!("  Stream {}: {}", index + 1, stream.description());
+                    let channels: Vec<String> = stream
+                        .channel_names()
+                        .iter()
+                        .enumerate()
+                        .map(|(c, name)| format!("{} {}", c + 1, name))
+                        .collect();
+                    println!("    Channels: {}", channels.join(", "));
+                }
+            }
+            Some(_) => {
+                eprintln!("  error: no audio streams");
+                failed += 1;
+            }
+            None => {
+                eprintln!("  error: failed to read streams from '{}'", input);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} of {} files could not be read",
+            failed,
+            inputs.len()
+        ))
+    }
+}
+
+/// Renders a single file to disk using the same pipeline as the GUI.
+pub fn render_file(input: &str, output: &Path, options: &RenderOptions) -> Result<(), String> {
+    let (settings, audio_info) = resolve_settings(input, options)?;
+    if options.format.is_none() && DataFormat::from_path(output).is_some() {
+        let sidecar = export::export_data(
+            input,
+            &settings,
+            &audio_info,
+            options.width,
+            options.height,
+            output,
+        )?;
+        println!("Description saved to {}", sidecar.display());
+        return Ok(());
+    }
+    let format = options.image_format(output);
+    if format == ImageFormat::GrayPng16 {
+        if settings.difference.is_some() {
+            return Err("png16 is not available for differences".to_string());
+        }
+        return export::save_intensity(
+            input,
+            &settings,
+            &audio_info,
+            options.width,
+            options.height,
+            output,
+        );
+    }
+    let image = utils::render_spectrogram(
+        input,
+        &settings,
+        Some(audio_info),
+        options.width,
+        options.height,
+        Arc::new(AtomicBool::new(false)),
+    )
+    .ok_or_else(|| format!("failed to generate spectrogram for '{}'", input))?;
+
+    let rgba_image =
+        utils::color_image_to_rgba_image(&image).ok_or("failed to convert spectrogram image")?;
+    export::save_image(&rgba_image, output, format, settings.jpeg_quality)
+}
+
+/// Saves the average spectrum of the input as a plot, or as CSV if the output ends in `.csv`.
+fn save_spectrum(args: &RenderArgs) -> Result<(), String> {
+    let (settings, audio_info) = resolve_settings(&args.input, &args.options)?;
+    let request = SpectrumRequest::new(&settings, &audio_info);
+    let spectrum =
+        spectrum::average_spectrum(&args.input, &audio_info, request, &AtomicBool::new(false))
+            .ok_or_else(|| format!("failed to measure the spectrum of '{}'", args.input))?;
+
+    let is_csv = args
+        .output
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    if is_csv && args.options.format.is_none() {
+        return spectrum
+            .write_csv(&args.output)
+            .map_err(|e| format!("failed to save '{}': {}", args.output.display(), e));
+    }
+    let format = args.options.image_format(&args.output);
+    if format == ImageFormat::GrayPng16 {
+        return Err("png16 is only available for spectrograms".to_string());
+    }
+    let plot = utils::draw_spectrum_plot(
+        &args.input,
+        &settings,
+        &spectrum,
+        args.options.width,
+        args.options.height,
+    );
+    export::save_image(&plot, &args.output, format, settings.jpeg_quality)
+}
+
+/// Settings of the rendering options applied to `input`: the channels, the frequency band
+/// and the part of the file.
+fn resolve_settings(
+    input: &str,
+    options: &RenderOptions,
+) -> Result<(AppSettings, utils::AudioInfo), String> {
+    let mut settings = options.settings.clone();
+    settings.source = resolve_source(input, options)?;
+    let audio_info = utils::get_audio_info(input, &settings.source)
+        .ok_or_else(|| format!("failed to read audio info from '{}'", input))?;
+    settings.view = settings.band_view(settings.view, audio_info.sample_rate as f32 / 2.0);
+    if options.from.is_some() || options.to.is_some() {
+        let from = options.from.unwrap_or(0.0);
+        if from >= audio_info.duration {
+            return Err(format!(
+                "--from is past the end of '{}' ({:.1} s)",
+                input, audio_info.duration
+            ));
+        }
+        let to = options
+            .to
+            .map_or(audio_info.duration, |to| to.min(audio_info.duration));
+        settings.view.time = Some((from, to));
+    }
+    Ok((settings, audio_info))
+}
+
+/// Renders the difference of two files, aligning them first if asked to.
+fn render_difference(args: &DiffArgs) -> Result<(), String> {
+    let mut options = args.options.clone();
+    let mut difference = args.difference.clone();
+    if difference.align {
+        let source = resolve_source(&args.input, &options)?;
+        let audio_info = utils::get_audio_info(&args.input, &source)
+            .ok_or_else(|| format!("failed to read audio info from '{}'", args.input))?;
+        difference.offset = diff::estimate_offset(&args.input, &audio_info, &difference.path)
+            .ok_or("failed to align the files")?;
+        println!(
+            "Second file is delayed by {:.1} ms",
+            difference.offset * 1000.0
+        );
+    }
+    options.settings.difference = Some(difference);
+    render_file(&args.input, &args.output, &options)
+}
+
+/// Applies `--hide-channels` to the selected source, which needs the channel count of the stream.
+fn resolve_source(input: &str, options: &RenderOptions) -> Result<AudioSource, String> {
+    let source = &options.settings.source;
+    if options.hidden_channels.is_empty() {
+        return Ok(source.clone());
+    }
+    let stream = AudioSource {
+        stream: source.stream,
+        ..Default::default()
+    };
+    let total = utils::get_audio_info(input, &stream)
+        .ok_or_else(|| format!("failed to read audio info from '{}'", input))?
+        .channels as usize;
+
+    let mut resolved = source.clone();
+    for &channel in &options.hidden_channels {
+        if channel >= total {
+            return Err(format!(
+                "cannot hide channel {}, '{}' has {} channels",
+                channel + 1,
+                input,
+                total
+            ));
+        }
+        resolved = resolved
+            .without_channel(channel, total)
+            .ok_or("cannot hide every channel")?;
+    }
+    Ok(resolved)
+}
+
+fn check_ffmpeg() -> Result<(), String> {
+    if utils::ffmpeg_available() {
+        Ok(())
+    } else {
+        Err(format!(
+            "FFmpeg not found. Install it or place it in: {}",
+            utils::get_ffmpeg_paths().directory.display()
+        ))
+    }
+}
+
+fn parse_time_arg(option: &str, value: &str) -> Result<f64, String> {
+    utils::parse_time(value).ok_or_else(|| {
+        format!(
+            "invalid time '{}' for {}, use seconds or m:ss",
+            value, option
+        )
+    })
+}
+
+/// Parses a comma separated list of channels counted from 1 into zero based indices.
+fn parse_channels(option: &str, value: &str) -> Result<Vec<usize>, String> {
+    value
+        .split(',')
+        .map(|c| match parse_number::<usize>(option, c.trim())? {
+            0 => Err("channels are counted from 1".to_string()),
+            c => Ok(c - 1),
+        })
+        .collect()
+}
+
+fn is_option(arg: &str) -> bool {
+    arg.starts_with('-') && arg.len() > 1
+}
+
+fn required_value(option: &str, iter: &mut std::slice::Iter<String>) -> Result<String, String> {
+    iter.next()
+        .cloned()
+        .ok_or_else(|| format!("missing value for '{}'", option))
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", value, option))
+}
+
+fn parse_named<T: Copy>(
+    value: &str,
+    values: &[T],
+    as_str: fn(&T) -> &'static str,
+    what: &str,
+) -> Result<T, String> {
+    values
+        .iter()
+        .find(|v| as_str(v).eq_ignore_ascii_case(value))
+        .copied()
+        .ok_or_else(|| {
+            let names: Vec<&str> = values.iter().map(as_str).collect();
+            format!(
+                "unknown {} '{}', expected one of: {}",
+                what,
+                value,
+                names.join(", ")
+            )
+        })
+}
+
+/// Release builds on Windows have no console, so reattach to the parent one for CLI output.
+#[cfg(windows)]
+fn attach_console() {
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
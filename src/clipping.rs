@@ -0,0 +1,310 @@
+use serde::Serialize;
+
+use crate::utils;
+
+/// Samples at or above this level count as full scale.
+const FULL_SCALE: f32 = 0.9999;
+/// Shortest run of consecutive full scale samples that counts as clipping.
+const MIN_CLIP_RUN: usize = 3;
+/// Interpolated peaks above this level are true peak overs (0 dBTP).
+const TRUE_PEAK_LIMIT: f32 = 1.0;
+/// Clips or overs closer together than this are reported as a single event, in seconds.
+const MERGE_GAP: f64 = 0.05;
+/// Most events that are listed, the counts include all of them.
+const MAX_EVENTS: usize = 1000;
+
+/// Kind of a clipping event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipKind {
+    /// Consecutive full scale samples.
+    Clipped,
+    /// Interpolated peak above 0 dBTP.
+    TruePeakOver,
+}
+
+impl ClipKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClipKind::Clipped => "clipped",
+            ClipKind::TruePeakOver => "true peak over",
+        }
+    }
+}
+
+impl std::fmt::Display for ClipKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A stretch of clipped samples or true peak overs in one channel.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipEvent {
+    pub kind: ClipKind,
+    pub channel: String,
+    /// Start in seconds from the beginning of the file.
+    pub time: f64,
+    pub duration: f64,
+    /// Highest sample or true peak of the event.
+    pub peak_db: f32,
+}
+
+impl ClipEvent {
+    /// One line description, e.g. "1:02.345 FL clipped, 12 ms".
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "{} {} {}",
+            utils::format_time(self.time),
+            self.channel,
+            self.kind
+        );
+        if self.kind == ClipKind::TruePeakOver {
+            text.push_str(&format!(" ({:+.1} dBTP)", self.peak_db));
+        }
+        if self.duration >= 0.001 {
+            text.push_str(&format!(", {:.0} ms", self.duration * 1000.0));
+        }
+        text
+    }
+}
+
+/// Clipping and true peak overs found in a file.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClippingReport {
+    pub clipped_events: usize,
+    pub clipped_samples: u64,
+    pub true_peak_overs: usize,
+    /// Events sorted by time, at most `MAX_EVENTS`.
+    pub events: Vec<ClipEvent>,
+}
+
+impl ClippingReport {
+    pub fn is_clean(&self) -> bool {
+        self.clipped_events == 0 && self.true_peak_overs == 0
+    }
+
+    /// Short summary, e.g. "3 clipped (57 samples), 12 true peak overs".
+    pub fn summary(&self) -> String {
+        if self.is_clean() {
+            return "no clipping".to_string();
+        }
+        format!(
+            "{} clipped ({} samples), {} true peak {}",
+            self.clipped_events,
+            self.clipped_samples,
+            self.true_peak_overs,
+            if self.true_peak_overs == 1 {
+                "over"
+            } else {
+                "overs"
+            }
+        )
+    }
+}
+
+/// Event that is still growing, in frames.
+#[derive(Clone, Copy)]
+struct OpenEvent {
+    start: u64,
+    last: u64,
+    peak: f32,
+}
+
+/// Finds runs of full scale samples and true peak overs, frame by frame.
+pub struct ClipDetector {
+    names: Vec<String>,
+    sample_rate: f64,
+    merge_gap: u64,
+    /// Length of the current run of full scale samples of every channel.
+    runs: Vec<usize>,
+    open_clips: Vec<Option<OpenEvent>>,
+    open_overs: Vec<Option<OpenEvent>>,
+    clipped_samples: u64,
+    clipped_events: usize,
+    true_peak_overs: usize,
+    events: Vec<ClipEvent>,
+    frame: u64,
+}
+
+impl ClipDetector {
+    pub fn new(names: &[String], sample_rate: u32) -> Self {
+        let channels = names.len();
+        Self {
+            names: names.to_vec(),
+            sample_rate: sample_rate as f64,
+            merge_gap: (MERGE_GAP * sample_rate as f64) as u64,
+            runs: vec![0; channels],
+            open_clips: vec![None; channels],
+            open_overs: vec![None; channels],
+            clipped_samples: 0,
+            clipped_events: 0,
+            true_peak_overs: 0,
+            events: Vec::new(),
+            frame: 0,
+        }
+    }
+
+    /// Checks one frame, `true_peaks` are the interpolated peaks of every channel since
+    /// the previous frame.
+    pub fn process(&mut self, frame: &[f32], true_peaks: &[f32]) {
+        for c in 0..self.names.len() {
+            let level = frame[c].abs();
+            if level >= FULL_SCALE {
+                self.runs[c] += 1;
+                let run = self.runs[c];
+                if run >= MIN_CLIP_RUN {
+                    // The whole run counts once it's long enough
+                    let counted = if run == MIN_CLIP_RUN { run } else { 1 };
+                    self.clipped_samples += counted as u64;
+                    let start = self.frame + 1 - counted as u64;
+                    self.hit(ClipKind::Clipped, c, start, level);
+                }
+            } else {
+                self.runs[c] = 0;
+            }
+
+            let true_peak = true_peaks[c];
+            if true_peak > TRUE_PEAK_LIMIT {
+                self.hit(ClipKind::TruePeakOver, c, self.frame, true_peak);
+            }
+        }
+        self.frame += 1;
+    }
+
+    fn hit(&mut self, kind: ClipKind, channel: usize, start: u64, level: f32) {
+        let frame = self.frame;
+        let open = match kind {
+            ClipKind::Clipped => &mut self.open_clips[channel],
+            ClipKind::TruePeakOver => &mut self.open_overs[channel],
+        };
+        match open {
+            Some(event) if start <= event.last + self.merge_gap => {
+                event.last = frame;
+                event.peak = event.peak.max(level);
+            }
+            _ => {
+                let closed = open.replace(OpenEvent {
+                    start,
+                    last: frame,
+                    peak: level,
+                });
+                if let Some(closed) = closed {
+                    self.close(kind, channel, closed);
+                }
+            }
+        }
+    }
+
+    fn close(&mut self, kind: ClipKind, channel: usize, event: OpenEvent) {
+        match kind {
+            ClipKind::Clipped => self.clipped_events += 1,
+            ClipKind::TruePeakOver => self.true_peak_overs += 1,
+        }
+        if self.events.len() < MAX_EVENTS {
+            self.events.push(ClipEvent {
+                kind,
+                channel: self.names[channel].clone(),
+                time: event.start as f64 / self.sample_rate,
+                duration: (event.last + 1 - event.start) as f64 / self.sample_rate,
+                peak_db: 20.0 * event.peak.log10(),
+            });
+        }
+    }
+
+    pub fn report(mut self) -> ClippingReport {
+        for channel in 0..self.names.len() {
+            if let Some(event) = self.open_clips[channel].take() {
+                self.close(ClipKind::Clipped, channel, event);
+            }
+            if let Some(event) = self.open_overs[channel].take() {
+                self.close(ClipKind::TruePeakOver, channel, event);
+            }
+        }
+        self.events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        ClippingReport {
+            clipped_events: self.clipped_events,
+            clipped_samples: self.clipped_samples,
+            true_peak_overs: self.true_peak_overs,
+            events: self.events,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every frame is a millisecond.
+    const SAMPLE_RATE: u32 = 1000;
+
+    /// Runs mono `samples` through a detector, without true peak overs.
+    fn detect(samples: &[f32]) -> ClippingReport {
+        let mut detector = ClipDetector::new(&["FC".to_string()], SAMPLE_RATE);
+        for &sample in samples {
+            detector.process(&[sample], &[sample.abs().min(TRUE_PEAK_LIMIT)]);
+        }
+        detector.report()
+    }
+
+    /// Silence with full scale runs of the given length starting at the given frames.
+    fn runs(length: usize, runs: &[(usize, usize)]) -> Vec<f32> {
+        let mut samples = vec![0.0; length];
+        for &(start, len) in runs {
+            samples[start..start + len].fill(1.0);
+        }
+        samples
+    }
+
+    #[test]
+    fn two_full_scale_samples_are_not_clipping() {
+        let report = detect(&runs(100, &[(10, 2), (50, 2)]));
+        assert!(report.is_clean());
+        assert_eq!(report.clipped_samples, 0);
+    }
+
+    #[test]
+    fn three_full_scale_samples_are_clipping() {
+        let report = detect(&runs(100, &[(10, 3)]));
+        assert_eq!(report.clipped_events, 1);
+        assert_eq!(report.clipped_samples, 3);
+        let event = &report.events[0];
+        assert_eq!(event.kind, ClipKind::Clipped);
+        assert!((event.time - 0.010).abs() < 1e-9);
+        assert!((event.duration - 0.003).abs() < 1e-9);
+    }
+
+    #[test]
+    fn close_runs_are_merged() {
+        // 40 ms apart, then one more run 200 ms later
+        let report = detect(&runs(1000, &[(100, 5), (145, 4), (400, 3)]));
+        assert_eq!(report.clipped_events, 2);
+        assert_eq!(report.clipped_samples, 12);
+        let merged = &report.events[0];
+        assert!((merged.time - 0.100).abs() < 1e-9);
+        assert!((merged.duration - 0.049).abs() < 1e-9);
+        assert!((report.events[1].time - 0.400).abs() < 1e-9);
+    }
+
+    #[test]
+    fn events_are_capped() {
+        let starts: Vec<(usize, usize)> = (0..MAX_EVENTS + 10).map(|i| (i * 100, 3)).collect();
+        let report = detect(&runs((MAX_EVENTS + 10) * 100, &starts));
+        assert_eq!(report.clipped_events, MAX_EVENTS + 10);
+        assert_eq!(report.clipped_samples, 3 * (MAX_EVENTS + 10) as u64);
+        assert_eq!(report.events.len(), MAX_EVENTS);
+    }
+
+    #[test]
+    fn true_peak_overs_are_counted() {
+        let mut detector = ClipDetector::new(&["FC".to_string()], SAMPLE_RATE);
+        for frame in 0..100 {
+            let true_peak = if frame == 20 { 1.12 } else { 0.5 };
+            detector.process(&[0.5], &[true_peak]);
+        }
+        let report = detector.report();
+        assert_eq!(report.true_peak_overs, 1);
+        assert_eq!(report.clipped_events, 0);
+        assert!((report.events[0].peak_db - 20.0 * 1.12f32.log10()).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,265 @@
+use std::sync::atomic::AtomicBool;
+
+use crate::settings::SpectogramWinFunc;
+use crate::stft::{self, StftAnalyzer};
+use crate::utils::AudioInfo;
+
+const FFT_SIZE: usize = 8192;
+/// Width of the bands the averaged spectrum is smoothed into.
+const BAND_HZ: f32 = 100.0;
+/// Width of the windows compared on both sides of a candidate cutoff.
+const EDGE_HZ: f32 = 500.0;
+/// Maximum number of time slices averaged, each slice averages all FFT frames inside it.
+const MAX_SLICES: usize = 512;
+/// Files quieter than this are not analyzed.
+const SILENCE_DB: f32 = -90.0;
+/// Lossy encoders don't keep anything above this frequency.
+const MAX_LOSSY_CUTOFF_HZ: f32 = 21000.0;
+
+/// Result of the effective bandwidth analysis of a file.
+#[derive(Debug, Clone, Copy)]
+pub struct CutoffReport {
+    /// Frequency above which the spectrum drops to the noise floor, `None` if there is no such shelf.
+    pub cutoff_hz: Option<f32>,
+    pub nyquist_hz: f32,
+    /// Level difference between the content below the cutoff and the shelf above it.
+    pub drop_db: f32,
+    /// Confidence from 0.0 to 1.0 that the file was transcoded from a lossy source.
+    pub confidence: f32,
+}
+
+impl CutoffReport {
+    pub fn is_lossy(&self) -> bool {
+        self.confidence >= 0.5
+    }
+
+    /// Encoder settings that typically produce the detected cutoff.
+    pub fn likely_source(&self) -> Option<&'static str> {
+        if !self.is_lossy() {
+            return None;
+        }
+        let cutoff = self.cutoff_hz?;
+        Some(match cutoff {
+            c if c < 11500.0 => "MP3 64 kbps or lower",
+            c if c < 15000.0 => "MP3 96-112 kbps",
+            c if c < 16500.0 => "MP3 128 kbps",
+            c if c < 17500.0 => "MP3 160 kbps",
+            c if c < 19000.0 => "MP3 192 kbps or AAC",
+            c if c < 19800.0 => "MP3 VBR V0-V2 or 256 kbps",
+            _ => "MP3 320 kbps or AAC 256+ kbps",
+        })
+    }
+
+    /// Short human readable summary, e.g. "likely lossy (MP3 128 kbps), 93% confidence".
+    pub fn verdict(&self) -> String {
+        if let Some(source) = self.likely_source() {
+            format!(
+                "likely lossy ({}), {:.0}% confidence",
+                source,
+                self.confidence * 100.0
+            )
+        } else if self.cutoff_hz.is_some() {
+            format!(
+                "probably lossless, {:.0}% lossy confidence",
+                self.confidence * 100.0
+            )
+        } else {
+            "full bandwidth".to_string()
+        }
+    }
+}
+
+/// Estimates the effective bandwidth of `input_path` from its average spectrum.
+/// Returns `Ok(None)` if the file is silent, and an error if it could not be decoded
+/// or the analysis was cancelled.
+pub fn detect_cutoff(
+    input_path: &str,
+    audio_info: &AudioInfo,
+    cancel_token: &AtomicBool,
+) -> Result<Option<CutoffReport>, String> {
+    let power = average_power(input_path, audio_info, cancel_token, |_| {})
+        .ok_or("failed to analyze the spectrum")?;
+    Ok(analyze_spectrum(
+        &power,
+        audio_info.sample_rate as f32 / 2.0,
+    ))
+}
+
+/// Power spectrum of `input_path` averaged over the whole file, `FFT_SIZE / 2` bins up to Nyquist.
+/// `inspect` sees every chunk of decoded interleaved samples.
+/// Returns `None` if the file could not be decoded or analysis was cancelled.
+pub fn average_power(
+    input_path: &str,
+    audio_info: &AudioInfo,
+    cancel_token: &AtomicBool,
+    inspect: impl FnMut(&[f32]),
+) -> Option<Vec<f64>> {
+    let mut analyzer = StftAnalyzer::new(FFT_SIZE, SpectogramWinFunc::Hann);
+    let total = ((audio_info.duration * audio_info.sample_rate as f64) as usize).max(1);
+    let slices = (total / FFT_SIZE).clamp(1, MAX_SLICES);
+
+    let mut power = vec![0.0f64; analyzer.bins()];
+    let mut count = 0;
+    let finished = stft::for_each_column_inspecting(
+        input_path,
+        audio_info,
+        None,
+        &mut analyzer,
+        slices,
+        cancel_token,
+        inspect,
+        |_, spectra| {
+            count += spectra.len();
+            for spectrum in spectra {
+                for (p, a) in power.iter_mut().zip(spectrum) {
+                    *p += (a * a) as f64;
+                }
+            }
+        },
+    );
+    if !finished || count == 0 {
+        return None;
+    }
+
+    for p in power.iter_mut() {
+        *p /= count as f64;
+    }
+    Some(power)
+}
+
+/// Finds the steepest drop of the averaged power spectrum that is followed by a flat shelf.
+pub fn analyze_spectrum(power: &[f64], nyquist_hz: f32) -> Option<CutoffReport> {
+    let bin_hz = nyquist_hz / power.len() as f32;
+    let bins_per_band = ((BAND_HZ / bin_hz).round() as usize).max(1);
+    let bands: Vec<f32> = power
+        .chunks(bins_per_band)
+        .map(|chunk| {
+            let mean = chunk.iter().sum::<f64>() / chunk.len() as f64;
+            10.0 * (mean.max(1e-20)).log10() as f32
+        })
+        .collect();
+    let band_hz = bin_hz * bins_per_band as f32;
+    let edge = ((EDGE_HZ / band_hz).round() as usize).max(1);
+
+    let reference = bands[..bands.len().min((10000.0 / band_hz) as usize).max(1)]
+        .iter()
+        .fold(f32::MIN, |a, &b| a.max(b));
+    if reference < SILENCE_DB {
+        return None;
+    }
+
+    let mean = |from: usize, to: usize| bands[from..to].iter().sum::<f32>() / (to - from) as f32;
+
+    // Don't look for a cutoff below 2 kHz
+    let first = ((2000.0 / band_hz) as usize).max(edge);
+    let mut best: Option<(usize, f32)> = None;
+    for b in first..bands.len().saturating_sub(edge) {
+        let drop = mean(b - edge, b) - mean(b, b + edge);
+        if best.is_none_or(|(_, best_drop)| drop > best_drop) {
+            best = Some((b, drop));
+        }
+    }
+
+    let full_bandwidth = CutoffReport {
+        cutoff_hz: None,
+        nyquist_hz,
+        drop_db: 0.0,
+        confidence: 0.0,
+    };
+    let Some((band, local_drop)) = best else {
+        return Some(full_bandwidth);
+    };
+    if local_drop < 15.0 {
+        return Some(full_bandwidth);
+    }
+
+    let below = mean(band - edge, band);
+    let shelf_peak = bands[band + edge..].iter().fold(f32::MIN, |a, &b| a.max(b));
+    let shelf = mean(band, bands.len());
+    let drop_db = below - shelf;
+
+    // Place the cutoff on the last bin still above the middle of the drop
+    let threshold = (below + shelf) / 2.0;
+    let from_bin = (band - edge) * bins_per_band;
+    let to_bin = ((band + edge) * bins_per_band).min(power.len());
+    let cutoff_bin = (from_bin..to_bin)
+        .rev()
+        .find(|&i| 10.0 * (power[i].max(1e-20)).log10() as f32 >= threshold)
+        .unwrap_or(band * bins_per_band);
+    let cutoff_hz = cutoff_bin as f32 * bin_hz;
+
+    let steepness = ((local_drop - 20.0) / 30.0).clamp(0.0, 1.0);
+    let flatness = ((below - shelf_peak - 10.0) / 20.0).clamp(0.0, 1.0);
+    // Music fills the spectrum below the cutoff, isolated tones don't say anything about the source
+    let content = &bands[first.min(band)..band];
+    let filled = content.iter().filter(|&&b| b > shelf_peak + 10.0).count();
+    let density = (filled as f32 / content.len().max(1) as f32 / 0.8).clamp(0.0, 1.0);
+    let confidence = if cutoff_hz <= MAX_LOSSY_CUTOFF_HZ && cutoff_hz < nyquist_hz * 0.95 {
+        steepness * flatness * density
+    } else {
+        0.0
+    };
+
+    Some(CutoffReport {
+        cutoff_hz: Some(cutoff_hz),
+        nyquist_hz,
+        drop_db,
+        confidence,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NYQUIST: f32 = 22050.0;
+    const BINS: usize = FFT_SIZE / 2;
+
+    /// Averaged power of a file whose content falls off slowly with frequency and stops
+    /// at `cutoff_hz`, above which only a noise floor at -120 dB is left.
+    fn shelf_spectrum(cutoff_hz: f32) -> Vec<f64> {
+        (0..BINS)
+            .map(|i| {
+                let hz = i as f32 * NYQUIST / BINS as f32;
+                let db = if hz < cutoff_hz {
+                    -20.0 - hz / 2000.0
+                } else {
+                    -120.0
+                };
+                10f64.powf(db as f64 / 10.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn brick_wall_at_16_khz() {
+        let report = analyze_spectrum(&shelf_spectrum(16000.0), NYQUIST).unwrap();
+        let cutoff = report.cutoff_hz.unwrap();
+        assert!((cutoff - 16000.0).abs() < 100.0, "cutoff at {} Hz", cutoff);
+        assert!(report.confidence > 0.9, "confidence {}", report.confidence);
+        assert!(report.drop_db > 80.0);
+        assert_eq!(report.likely_source(), Some("MP3 128 kbps"));
+    }
+
+    #[test]
+    fn brick_wall_at_19_5_khz() {
+        let report = analyze_spectrum(&shelf_spectrum(19500.0), NYQUIST).unwrap();
+        let cutoff = report.cutoff_hz.unwrap();
+        assert!((cutoff - 19500.0).abs() < 100.0, "cutoff at {} Hz", cutoff);
+        assert!(report.is_lossy());
+        assert_eq!(report.likely_source(), Some("MP3 VBR V0-V2 or 256 kbps"));
+    }
+
+    #[test]
+    fn full_band_has_no_cutoff() {
+        let report = analyze_spectrum(&shelf_spectrum(f32::INFINITY), NYQUIST).unwrap();
+        assert_eq!(report.cutoff_hz, None);
+        assert!(!report.is_lossy());
+        assert_eq!(report.verdict(), "full bandwidth");
+    }
+
+    #[test]
+    fn silence_has_no_verdict() {
+        assert!(analyze_spectrum(&vec![0.0; BINS], NYQUIST).is_none());
+    }
+}
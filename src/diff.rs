@@ -0,0 +1,376 @@
+use eframe::egui::{Color32, ColorImage};
+use realfft::RealFftPlanner;
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::palettes;
+use crate::pcm::PcmDecoder;
+use crate::settings::{AppSettings, AudioSource, Difference, DifferenceMode};
+use crate::stft::{self, RowLayout, StftAnalyzer};
+use crate::utils::{self, AudioInfo};
+
+/// Seconds from the start of both files that are cross-correlated to align them.
+const ALIGN_EXCERPT: f64 = 10.0;
+/// Largest delay between the files the alignment looks for, in seconds.
+const MAX_OFFSET: f64 = 1.0;
+
+/// Finds how many seconds `other_path` is delayed against `input_path`, e.g. by the
+/// encoder delay of a lossy transcode, by cross-correlating the start of both files.
+pub fn estimate_offset(input_path: &str, audio_info: &AudioInfo, other_path: &str) -> Option<f64> {
+    let sample_rate = audio_info.sample_rate;
+    let first = decode_excerpt(input_path, &audio_info.source, sample_rate)?;
+    let second = decode_excerpt(other_path, &AudioSource::default(), sample_rate)?;
+    if first.is_empty() || second.is_empty() {
+        eprintln!("Failed to align, one of the files has no audio.");
+        return None;
+    }
+    correlation_offset(&first, &second, sample_rate)
+}
+
+/// Delay of `second` against `first` in seconds where their cross-correlation peaks,
+/// at most `MAX_OFFSET` either way.
+fn correlation_offset(first: &[f32], second: &[f32], sample_rate: u32) -> Option<f64> {
+    let size = (first.len() + second.len()).next_power_of_two();
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(size);
+    let inverse = planner.plan_fft_inverse(size);
+
+    let mut spectra = Vec::new();
+    for samples in [first, second] {
+        let mut input = forward.make_input_vec();
+        input[..samples.len()].copy_from_slice(samples);
+        let mut spectrum = forward.make_output_vec();
+        forward.process(&mut input, &mut spectrum).ok()?;
+        spectra.push(spectrum);
+    }
+
+    // conj(A) * B transforms back to the correlation of first[n] with second[n + lag]
+    let mut product: Vec<_> = spectra[0]
+        .iter()
+        .zip(&spectra[1])
+        .map(|(a, b)| a.conj() * b)
+        .collect();
+    if let Some(last) = product.last_mut() {
+        last.im = 0.0;
+    }
+    product[0].im = 0.0;
+    let mut correlation = inverse.make_output_vec();
+    inverse.process(&mut product, &mut correlation).ok()?;
+
+    let max_lag = ((MAX_OFFSET * sample_rate as f64) as usize).min(size / 2 - 1);
+    let lag_at = |lag: isize| correlation[lag.rem_euclid(size as isize) as usize];
+    let best = (-(max_lag as isize)..=max_lag as isize)
+        .max_by(|&a, &b| lag_at(a).total_cmp(&lag_at(b)))?;
+    Some(best as f64 / sample_rate as f64)
+}
+
+/// Decodes the first seconds of a file as mono.
+fn decode_excerpt(path: &str, source: &AudioSource, sample_rate: u32) -> Option<Vec<f32>> {
+    let mut decoder = PcmDecoder::spawn(path, source, 1, sample_rate, Some((0.0, ALIGN_EXCERPT)))?;
+    let mut samples = Vec::new();
+    loop {
+        match decoder.read(&mut samples) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Failed to read ffmpeg stdout: {}", e);
+                decoder.kill();
+                return None;
+            }
+        }
+    }
+    decoder.finish().then_some(samples)
+}
+
+/// Maps a level difference to the diverging palette, 0.5 being no difference.
+pub fn delta_value(delta_db: f32, range_db: f32) -> f32 {
+    0.5 + (delta_db / range_db.max(f32::EPSILON)).clamp(-1.0, 1.0) / 2.0
+}
+
+/// Inverse of `delta_value`.
+pub fn delta_from_value(value: f32, range_db: f32) -> f32 {
+    (value.clamp(0.0, 1.0) - 0.5) * 2.0 * range_db
+}
+
+/// Generates the spectrogram of `input_path` compared against `settings.difference`.
+/// Both files are decoded side by side, so the native engine is always used.
+pub fn generate_difference(
+    input_path: &str,
+    settings: &AppSettings,
+    width: u32,
+    height: u32,
+    cancel_token: Arc<AtomicBool>,
+) -> Option<ColorImage> {
+    let difference = settings.difference.as_ref()?;
+    let start = Instant::now();
+    println!(
+        "Generating difference spectrogram for: {} and {}",
+        input_path, difference.path
+    );
+    log::debug!("{:#?}", settings);
+
+    let audio_info = match utils::get_audio_info(input_path, &settings.source) {
+        Some(info) if info.duration > 0.0 => info,
+        _ => {
+            eprintln!("Failed to get valid audio info.");
+            return None;
+        }
+    };
+    let time_range = settings.view.time_range(audio_info.duration);
+    let mut pair = AlignedPair::spawn(input_path, &audio_info, difference, time_range)?;
+
+    let layout = RowLayout::new(settings, &audio_info, height);
+    let mut analyzer =
+        StftAnalyzer::new(layout.fft_size, settings.win_func).with_overlap(settings.overlap);
+    let channels = pair.channels;
+    let total =
+        (((time_range.1 - time_range.0) * audio_info.sample_rate as f64).round() as usize).max(1);
+
+    let mut image = ColorImage::new(
+        [width as usize, height as usize],
+        vec![Color32::BLACK; (width * height) as usize],
+    );
+    let mut combined = vec![0.0f32; analyzer.bins()];
+    let mut combined_other = vec![0.0f32; analyzer.bins()];
+    // Bins below the level range count as silence
+    let floor = 10f32.powf((settings.db_limit - settings.db_range) / 20.0);
+
+    let result = match difference.mode {
+        DifferenceMode::Delta => stft::analyze_columns(
+            channels * 2,
+            total,
+            |out| pair.read(out, false),
+            &mut analyzer,
+            width as usize,
+            &cancel_token,
+            |x, spectra| {
+                let (first, second) = spectra.split_at(channels);
+                for lane in 0..layout.lanes {
+                    let first =
+                        stft::lane_spectrum(first, lane, settings.split_channels, &mut combined);
+                    let second = stft::lane_spectrum(
+                        second,
+                        lane,
+                        settings.split_channels,
+                        &mut combined_other,
+                    );
+                    for row in 0..layout.lane_height {
+                        let a = layout.row_amplitude(first, row).max(floor);
+                        let b = layout.row_amplitude(second, row).max(floor);
+                        let value = delta_value(20.0 * (a / b).log10(), difference.range_db);
+                        let color =
+                            palettes::color_at(palettes::DIVERGING, value, settings.saturation);
+                        let y = lane * layout.lane_height + row;
+                        image[(x, y)] = Color32::from_rgb(color[0], color[1], color[2]);
+                    }
+                }
+            },
+        ),
+        DifferenceMode::Signal => stft::analyze_columns(
+            channels,
+            total,
+            |out| pair.read(out, true),
+            &mut analyzer,
+            width as usize,
+            &cancel_token,
+            |x, spectra| {
+                stft::draw_column(&mut image, x, spectra, &layout, settings, &mut combined)
+            },
+        ),
+    };
+
+    let finished = match result {
+        Some(true) => pair.finish(),
+        // The streams may be slightly longer than the duration reported by ffprobe
+        Some(false) => {
+            pair.kill();
+            true
+        }
+        None => {
+            pair.kill();
+            false
+        }
+    };
+    if !finished {
+        return None;
+    }
+
+    println!("Difference spectrogram generated in {:?}.", start.elapsed());
+    Some(image)
+}
+
+/// Decodes two files side by side with the same channel count and sample rate,
+/// the second one shifted by the offset of the difference.
+struct AlignedPair {
+    first: PcmDecoder,
+    second: PcmDecoder,
+    channels: usize,
+    /// Silent frames still to insert before the second file, when it starts later.
+    padding: usize,
+    pending: Vec<f32>,
+    second_ended: bool,
+}
+
+impl AlignedPair {
+    fn spawn(
+        input_path: &str,
+        audio_info: &AudioInfo,
+        difference: &Difference,
+        (start, end): (f64, f64),
+    ) -> Option<Self> {
+        let sample_rate = audio_info.sample_rate;
+        let first = PcmDecoder::spawn(
+            input_path,
+            &audio_info.source,
+            audio_info.channels,
+            sample_rate,
+            Some((start, end)),
+        )?;
+
+        let second_start = start + difference.offset;
+        let second_end = end + difference.offset;
+        if second_end <= 0.0 {
+            eprintln!(
+                "The files don't overlap with an offset of {:.3} s.",
+                difference.offset
+            );
+            let mut first = first;
+            first.kill();
+            return None;
+        }
+        let padding = (-second_start * sample_rate as f64).round().max(0.0) as usize;
+        let Some(second) = PcmDecoder::spawn(
+            &difference.path,
+            &AudioSource::default(),
+            audio_info.channels,
+            sample_rate,
+            Some((second_start.max(0.0), second_end)),
+        ) else {
+            let mut first = first;
+            first.kill();
+            return None;
+        };
+
+        Some(Self {
+            channels: first.channels,
+            first,
+            second,
+            padding,
+            pending: Vec::new(),
+            second_ended: false,
+        })
+    }
+
+    /// Appends the next frames of both files to `out`, the channels of the second file
+    /// following those of the first one, or with `subtract` only the first minus the second.
+    fn read(&mut self, out: &mut Vec<f32>, subtract: bool) -> io::Result<usize> {
+        let mut first = Vec::new();
+        let frames = self.first.read(&mut first)?;
+        let needed = frames * self.channels;
+
+        while self.pending.len() < needed && !self.second_ended {
+            if self.padding > 0 {
+                let silence = self.padding.min(frames);
+                self.pending
+                    .resize(self.pending.len() + silence * self.channels, 0.0);
+                self.padding -= silence;
+            } else if self.second.read(&mut self.pending)? == 0 {
+                self.second_ended = true;
+            }
+        }
+        // The rest of the first file is compared with silence if the second one is shorter
+        let available = self.pending.len().min(needed);
+        let mut second: Vec<f32> = self.pending.drain(..available).collect();
+        second.resize(needed, 0.0);
+
+        for (a, b) in first
+            .chunks_exact(self.channels)
+            .zip(second.chunks_exact(self.channels))
+        {
+            if subtract {
+                out.extend(a.iter().zip(b).map(|(a, b)| a - b));
+            } else {
+                out.extend_from_slice(a);
+                out.extend_from_slice(b);
+            }
+        }
+        Ok(frames)
+    }
+
+    fn kill(&mut self) {
+        self.first.kill();
+        self.second.kill();
+    }
+
+    fn finish(mut self) -> bool {
+        if !self.second_ended {
+            self.second.kill();
+            return self.first.finish();
+        }
+        let first = self.first.finish();
+        self.second.finish() && first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    /// Deterministic noise, it correlates with itself only at zero lag.
+    fn noise(len: usize) -> Vec<f32> {
+        let mut state = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    fn delayed(samples: &[f32], delay: usize) -> Vec<f32> {
+        let mut delayed = vec![0.0; delay];
+        delayed.extend_from_slice(samples);
+        delayed
+    }
+
+    #[test]
+    fn offset_of_a_delayed_copy() {
+        let first = noise(SAMPLE_RATE as usize);
+        // The second file starts 123 samples later, so `AlignedPair` reads it from
+        // `start + offset` with a positive offset
+        let second = delayed(&first, 123);
+        let offset = correlation_offset(&first, &second, SAMPLE_RATE).unwrap();
+        assert_eq!(offset, 123.0 / SAMPLE_RATE as f64);
+
+        let offset = correlation_offset(&second, &first, SAMPLE_RATE).unwrap();
+        assert_eq!(offset, -123.0 / SAMPLE_RATE as f64);
+    }
+
+    #[test]
+    fn identical_files_have_no_offset() {
+        let samples = noise(SAMPLE_RATE as usize);
+        assert_eq!(
+            correlation_offset(&samples, &samples, SAMPLE_RATE),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn delta_value_round_trips() {
+        for range_db in [6.0, 20.0, 48.0] {
+            for delta_db in [-range_db, -3.5, 0.0, 1.25, range_db] {
+                let value = delta_value(delta_db, range_db);
+                assert!((0.0..=1.0).contains(&value));
+                assert!((delta_from_value(value, range_db) - delta_db).abs() < 1e-4);
+            }
+        }
+        assert_eq!(delta_value(0.0, 20.0), 0.5);
+        // Differences beyond the range saturate the palette
+        assert_eq!(delta_value(100.0, 20.0), 1.0);
+        assert_eq!(delta_from_value(delta_value(-100.0, 20.0), 20.0), -20.0);
+    }
+}
@@ -0,0 +1,524 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, RgbaImage};
+use serde_json::json;
+use std::fs;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use crate::settings::AppSettings;
+use crate::spectrum::FLOOR_DB;
+use crate::stft::{self, RowLayout, StftAnalyzer};
+use crate::utils::AudioInfo;
+
+/// JPEG quality unless one is set.
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Formats a spectrogram image can be saved in.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    /// Lossless WebP.
+    WebP,
+    Tiff,
+    /// 16-bit grayscale PNG of the levels before the palette, without legend.
+    GrayPng16,
+}
+
+impl ImageFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::WebP => "webp",
+            ImageFormat::Tiff => "tiff",
+            ImageFormat::GrayPng16 => "png16",
+        }
+    }
+    pub const VALUES: [Self; 5] = [
+        Self::Png,
+        Self::Jpeg,
+        Self::WebP,
+        Self::Tiff,
+        Self::GrayPng16,
+    ];
+    /// Formats that keep the colors of the spectrogram and its legend.
+    pub const COLOR: [Self; 4] = [Self::Png, Self::Jpeg, Self::WebP, Self::Tiff];
+
+    /// File extensions of the format, the first one is used for new files.
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ImageFormat::Png | ImageFormat::GrayPng16 => &["png"],
+            ImageFormat::Jpeg => &["jpg", "jpeg"],
+            ImageFormat::WebP => &["webp"],
+            ImageFormat::Tiff => &["tif", "tiff"],
+        }
+    }
+
+    /// Color image format of `path` by its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::COLOR
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+}
+
+impl std::fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageFormat::Png => write!(f, "PNG Image"),
+            ImageFormat::Jpeg => write!(f, "JPEG Image"),
+            ImageFormat::WebP => write!(f, "WebP Image"),
+            ImageFormat::Tiff => write!(f, "TIFF Image"),
+            ImageFormat::GrayPng16 => write!(f, "16-bit Grayscale PNG"),
+        }
+    }
+}
+
+/// Saves `image` to `path` as `format`, `quality` from 1 to 100 applies to JPEG.
+/// JPEG has no alpha channel, so the image is made opaque. The 16-bit levels are not
+/// in the image, they are saved with `save_intensity`.
+pub fn save_image(
+    image: &RgbaImage,
+    path: &Path,
+    format: ImageFormat,
+    quality: u8,
+) -> Result<(), String> {
+    let save_error = |e: String| format!("failed to save '{}': {}", path.display(), e);
+    let result = match format {
+        ImageFormat::Png => image.save_with_format(path, image::ImageFormat::Png),
+        ImageFormat::GrayPng16 => {
+            return Err(save_error(format!(
+                "{} is not a color image format",
+                format
+            )))
+        }
+        ImageFormat::Tiff => image.save_with_format(path, image::ImageFormat::Tiff),
+        ImageFormat::Jpeg | ImageFormat::WebP => {
+            let file = fs::File::create(path).map_err(|e| save_error(e.to_string()))?;
+            let writer = BufWriter::new(file);
+            if format == ImageFormat::Jpeg {
+                let rgb = DynamicImage::ImageRgba8(image.clone()).into_rgb8();
+                rgb.write_with_encoder(JpegEncoder::new_with_quality(writer, quality))
+            } else {
+                image.write_with_encoder(WebPEncoder::new_lossless(writer))
+            }
+        }
+    };
+    result.map_err(|e| save_error(e.to_string()))
+}
+
+/// Saves the levels of the spectrogram of `input_path` before the palette is applied as a
+/// 16-bit grayscale PNG of `width` x `height` pixels, without legend.
+pub fn save_intensity(
+    input_path: &str,
+    settings: &AppSettings,
+    audio_info: &AudioInfo,
+    width: u32,
+    height: u32,
+    path: &Path,
+) -> Result<(), String> {
+    let image = stft::generate_intensity(
+        input_path,
+        settings,
+        audio_info,
+        width,
+        height,
+        &AtomicBool::new(false),
+    )
+    .ok_or_else(|| format!("failed to analyze '{}'", input_path))?;
+    image
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| format!("failed to save '{}': {}", path.display(), e))
+}
+
+/// Formats the magnitudes of a spectrogram can be exported in.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum DataFormat {
+    Csv,
+    /// NumPy array, readable with `numpy.load`.
+    Npy,
+    /// Little-endian f32 values without a header, described by the JSON sidecar.
+    Raw,
+}
+
+impl DataFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataFormat::Csv => "csv",
+            DataFormat::Npy => "npy",
+            DataFormat::Raw => "raw",
+        }
+    }
+    pub const VALUES: [Self; 3] = [Self::Csv, Self::Npy, Self::Raw];
+
+    /// Format of `path` by its extension, `None` for images.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "f32" => Some(DataFormat::Raw),
+            extension => Self::VALUES
+                .into_iter()
+                .find(|format| format.as_str() == extension),
+        }
+    }
+}
+
+impl std::fmt::Display for DataFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataFormat::Csv => write!(f, "CSV Table"),
+            DataFormat::Npy => write!(f, "NumPy Array"),
+            DataFormat::Raw => write!(f, "Raw f32"),
+        }
+    }
+}
+
+/// Levels of a spectrogram in dBFS, by channel lane, time slice and frequency bin.
+pub struct SpectrogramData {
+    /// Row-major values, the frequency bins of one time slice are next to each other.
+    pub values: Vec<f32>,
+    /// Channel names of the lanes, a single "all" lane unless the channels are split.
+    pub lanes: Vec<String>,
+    /// Center of every time slice in seconds from the start of the file.
+    pub times: Vec<f64>,
+    /// Center frequency of every bin in Hz.
+    pub frequencies: Vec<f32>,
+    pub sample_rate: u32,
+    pub fft_size: usize,
+}
+
+impl SpectrogramData {
+    /// Dimensions of `values`, the lane is left out if there is only one.
+    pub fn shape(&self) -> Vec<usize> {
+        let mut shape = vec![self.times.len(), self.frequencies.len()];
+        if self.lanes.len() > 1 {
+            shape.insert(0, self.lanes.len());
+        }
+        shape
+    }
+
+    pub fn write(&self, path: &Path, format: DataFormat) -> io::Result<()> {
+        let content = match format {
+            DataFormat::Csv => self.to_csv().into_bytes(),
+            DataFormat::Npy => {
+                let mut content = npy_header(&self.shape());
+                content.extend(self.values.iter().flat_map(|v| v.to_le_bytes()));
+                content
+            }
+            DataFormat::Raw => self.values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        };
+        fs::write(path, content)
+    }
+
+    /// One row per time slice with the level of every bin, the header holds the frequencies.
+    fn to_csv(&self) -> String {
+        let split = self.lanes.len() > 1;
+        let mut content = String::from(if split { "channel,time_s" } else { "time_s" });
+        for frequency in &self.frequencies {
+            content.push_str(&format!(",{:.2}", frequency));
+        }
+        content.push('\n');
+
+        let mut rows = self.values.chunks(self.frequencies.len().max(1));
+        for lane in &self.lanes {
+            for time in &self.times {
+                if split {
+                    content.push_str(&format!("{},", lane));
+                }
+                content.push_str(&format!("{:.6}", time));
+                for value in rows.next().unwrap_or_default() {
+                    content.push_str(&format!(",{:.2}", value));
+                }
+                content.push('\n');
+            }
+        }
+        content
+    }
+}
+
+/// Header of a version 1.0 `.npy` file with little-endian f32 values of `shape`.
+fn npy_header(shape: &[usize]) -> Vec<u8> {
+    let dimensions: Vec<String> = shape.iter().map(|d| d.to_string()).collect();
+    // A single dimension needs a trailing comma to be a tuple
+    let shape = if dimensions.len() == 1 {
+        format!("({},)", dimensions[0])
+    } else {
+        format!("({})", dimensions.join(", "))
+    };
+    let mut dict = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}",
+        shape
+    );
+    // Magic, version and length take 10 bytes, the data starts 64 byte aligned
+    let padding = 63 - (10 + dict.len()) % 64;
+    dict.push_str(&" ".repeat(padding));
+    dict.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend((dict.len() as u16).to_le_bytes());
+    header.extend(dict.into_bytes());
+    header
+}
+
+/// Computes the levels of the spectrogram of `width` time slices that the native engine
+/// would draw `height` pixels high: the same time range, FFT size, window and overlap,
+/// with every bin of the visible frequency band.
+/// Returns `None` if decoding failed or was cancelled.
+pub fn spectrogram_data(
+    input_path: &str,
+    settings: &AppSettings,
+    audio_info: &AudioInfo,
+    width: u32,
+    height: u32,
+    cancel_token: &AtomicBool,
+) -> Option<SpectrogramData> {
+    let layout = RowLayout::new(settings, audio_info, height);
+    let mut analyzer =
+        StftAnalyzer::new(layout.fft_size, settings.win_func).with_overlap(settings.overlap);
+    let bin_hz = audio_info.sample_rate as f32 / layout.fft_size as f32;
+    let (min_freq, max_freq) = settings
+        .frequency_axis(audio_info.sample_rate as f32 / 2.0)
+        .range;
+    let first = ((min_freq / bin_hz).ceil() as usize).min(analyzer.bins() - 1);
+    let last = ((max_freq / bin_hz).floor() as usize).clamp(first, analyzer.bins() - 1);
+    let bins = last - first + 1;
+
+    let columns = width as usize;
+    let time_range = settings.view.time_range(audio_info.duration);
+    let mut values = vec![FLOOR_DB; layout.lanes * columns * bins];
+    let mut combined = vec![0.0f32; analyzer.bins()];
+    let finished = stft::for_each_column(
+        input_path,
+        audio_info,
+        settings.view.time.map(|_| time_range),
+        &mut analyzer,
+        columns,
+        cancel_token,
+        |x, spectra| {
+            for lane in 0..layout.lanes {
+                let spectrum =
+                    stft::lane_spectrum(spectra, lane, settings.split_channels, &mut combined);
+                let offset = (lane * columns + x) * bins;
+                for (value, amplitude) in values[offset..offset + bins]
+                    .iter_mut()
+                    .zip(&spectrum[first..=last])
+                {
+                    *value = (20.0 * amplitude.log10()).max(FLOOR_DB);
+                }
+            }
+        },
+    );
+    if !finished {
+        return None;
+    }
+
+    let (start, end) = time_range;
+    let slice = (end - start) / columns as f64;
+    Some(SpectrogramData {
+        values,
+        lanes: if settings.split_channels {
+            audio_info.channel_names.clone()
+        } else {
+            vec!["all".to_string()]
+        },
+        times: (0..columns)
+            .map(|x| start + (x as f64 + 0.5) * slice)
+            .collect(),
+        frequencies: (first..=last).map(|bin| bin as f32 * bin_hz).collect(),
+        sample_rate: audio_info.sample_rate,
+        fft_size: layout.fft_size,
+    })
+}
+
+/// A rendered spectrogram whose levels can be exported as data or 16-bit intensity image.
+pub struct DataSource {
+    pub input_path: String,
+    pub settings: AppSettings,
+    pub audio_info: AudioInfo,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DataSource {
+    pub fn export(&self, path: &Path) -> Result<PathBuf, String> {
+        export_data(
+            &self.input_path,
+            &self.settings,
+            &self.audio_info,
+            self.width,
+            self.height,
+            path,
+        )
+    }
+
+    pub fn save_intensity(&self, path: &Path) -> Result<(), String> {
+        save_intensity(
+            &self.input_path,
+            &self.settings,
+            &self.audio_info,
+            self.width,
+            self.height,
+            path,
+        )
+    }
+}
+
+/// Writes the levels of the spectrogram of `input_path` to `path` in the format of its
+/// extension, and a JSON sidecar describing them next to it. Returns the sidecar path.
+pub fn export_data(
+    input_path: &str,
+    settings: &AppSettings,
+    audio_info: &AudioInfo,
+    width: u32,
+    height: u32,
+    path: &Path,
+) -> Result<PathBuf, String> {
+    let format = DataFormat::from_path(path)
+        .ok_or_else(|| format!("'{}' is not a CSV, .npy or raw file", path.display()))?;
+    let data = spectrogram_data(
+        input_path,
+        settings,
+        audio_info,
+        width,
+        height,
+        &AtomicBool::new(false),
+    )
+    .ok_or_else(|| format!("failed to analyze '{}'", input_path))?;
+    data.write(path, format)
+        .map_err(|e| format!("failed to save '{}': {}", path.display(), e))?;
+
+    let mut axes = vec!["time", "frequency"];
+    if data.lanes.len() > 1 {
+        axes.insert(0, "channel");
+    }
+    let sidecar = json!({
+        "file": input_path,
+        "data": path.file_name().map(|name| name.to_string_lossy()),
+        "format": format.as_str(),
+        "dtype": "float32",
+        "byte_order": "little",
+        "shape": data.shape(),
+        "axes": axes,
+        "unit": "dBFS",
+        "floor_db": FLOOR_DB,
+        "channels": data.lanes,
+        "sample_rate": data.sample_rate,
+        "fft_size": data.fft_size,
+        "window": settings.win_func.as_str(),
+        "overlap": settings.overlap,
+        "time_range": settings.view.time_range(audio_info.duration),
+        "times_s": data.times,
+        "frequencies_hz": data.frequencies,
+    });
+    let sidecar_path = path.with_extension("json");
+    let json = serde_json::to_string_pretty(&sidecar)
+        .map_err(|e| format!("failed to write JSON: {}", e))?;
+    fs::write(&sidecar_path, json)
+        .map_err(|e| format!("failed to save '{}': {}", sidecar_path.display(), e))?;
+    Ok(sidecar_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(lanes: usize, times: usize, bins: usize) -> SpectrogramData {
+        SpectrogramData {
+            values: (0..lanes * times * bins).map(|i| -(i as f32)).collect(),
+            lanes: if lanes > 1 {
+                (0..lanes).map(|lane| format!("C{}", lane)).collect()
+            } else {
+                vec!["all".to_string()]
+            },
+            times: (0..times).map(|t| t as f64 * 0.1).collect(),
+            frequencies: (0..bins).map(|b| b as f32 * 10.0).collect(),
+            sample_rate: 48000,
+            fft_size: 4096,
+        }
+    }
+
+    /// Written file of `data` in a fresh temporary path.
+    fn written(data: &SpectrogramData, format: DataFormat, name: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "spek-rs-test-{}-{}.{}",
+            std::process::id(),
+            name,
+            format.as_str()
+        ));
+        data.write(&path, format).unwrap();
+        let content = fs::read(&path).unwrap();
+        fs::remove_file(&path).ok();
+        content
+    }
+
+    /// Shape stored in the dict of an `.npy` header.
+    fn header_shape(header: &[u8]) -> Vec<usize> {
+        let dict = std::str::from_utf8(header).unwrap();
+        let shape = dict.split("'shape': (").nth(1).unwrap();
+        let shape = &shape[..shape.find(')').unwrap()];
+        shape
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| d.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn npy_header_is_aligned() {
+        for shape in [vec![7], vec![3, 5], vec![2, 300, 2049]] {
+            let header = npy_header(&shape);
+            assert_eq!(header.len() % 64, 0, "shape {:?}", shape);
+            assert_eq!(&header[..8], b"\x93NUMPY\x01\x00");
+            let dict_len = u16::from_le_bytes([header[8], header[9]]) as usize;
+            assert_eq!(10 + dict_len, header.len());
+            assert_eq!(header.last(), Some(&b'\n'));
+            assert!(header.windows(14).any(|w| w == b"'descr': '<f4'"));
+            assert_eq!(header_shape(&header[10..]), shape);
+        }
+    }
+
+    #[test]
+    fn npy_of_combined_and_split_channels() {
+        for (lanes, shape) in [(1, vec![4, 6]), (2, vec![2, 4, 6])] {
+            let data = data(lanes, 4, 6);
+            assert_eq!(data.shape(), shape);
+            let content = written(&data, DataFormat::Npy, &format!("npy{}", lanes));
+            let header_len = 10 + u16::from_le_bytes([content[8], content[9]]) as usize;
+            assert_eq!(header_shape(&content[10..header_len]), shape);
+            assert_eq!(content.len(), header_len + data.values.len() * 4);
+            let last = &content[content.len() - 4..];
+            assert_eq!(
+                f32::from_le_bytes(last.try_into().unwrap()),
+                *data.values.last().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn raw_holds_every_value() {
+        let data = data(3, 5, 7);
+        let content = written(&data, DataFormat::Raw, "raw");
+        assert_eq!(content.len(), 3 * 5 * 7 * 4);
+        assert_eq!(f32::from_le_bytes(content[4..8].try_into().unwrap()), -1.0);
+    }
+
+    #[test]
+    fn csv_has_a_row_per_time_slice() {
+        let combined = data(1, 4, 6).to_csv();
+        let rows: Vec<&str> = combined.lines().collect();
+        assert_eq!(rows.len(), 1 + 4);
+        assert!(rows.iter().all(|row| row.split(',').count() == 1 + 6));
+        assert!(rows[0].starts_with("time_s,0.00,10.00"));
+
+        let split = data(2, 4, 6).to_csv();
+        let rows: Vec<&str> = split.lines().collect();
+        assert_eq!(rows.len(), 1 + 2 * 4);
+        assert!(rows.iter().all(|row| row.split(',').count() == 2 + 6));
+        assert!(rows[5].starts_with("C1,0.000000,"));
+    }
+}
@@ -3,7 +3,6 @@ use ffmpeg_sidecar::{self, command::ffmpeg_is_installed};
 use crate::ui::FfmpegSetup;
 use crate::utils::get_ffmpeg_paths;
 use eframe::egui;
-use image;
 
 pub fn setup_ffmpeg() -> eframe::Result<()> {
     if ffmpeg_is_installed() {
@@ -0,0 +1,267 @@
+use std::sync::atomic::AtomicBool;
+
+use crate::cutoff;
+use crate::utils::AudioInfo;
+
+/// Common sample rates a file may have been upsampled from.
+const SOURCE_RATES: [u32; 4] = [44100, 48000, 88200, 96000];
+/// How far above the Nyquist frequency of a source rate the content may still reach.
+const NYQUIST_TOLERANCE: f32 = 1.02;
+/// Width of the bands the averaged spectrum is split into.
+const BAND_HZ: f32 = 250.0;
+/// Bands this far above the noise floor count as content.
+const CONTENT_DB: f32 = 20.0;
+/// Scale of a 24-bit integer sample in the decoded float samples.
+const SCALE_24_BIT: f64 = 8388608.0;
+
+/// Result of the check for upsampled or padded "high resolution" files.
+#[derive(Debug, Clone)]
+pub struct HiResReport {
+    pub sample_rate: u32,
+    pub bits_per_sample: u32,
+    /// Frequency above which there is no content, `None` if the spectrum reaches Nyquist.
+    pub bandwidth_hz: Option<f32>,
+    /// Lowest common sample rate that holds all the content, if lower than the file's.
+    pub upsampled_from: Option<u32>,
+    /// Bits actually used by the samples, `None` for lossy or silent files.
+    pub effective_bits: Option<u32>,
+}
+
+impl HiResReport {
+    /// Whether the low-order bits of the samples are always zero.
+    pub fn is_padded(&self) -> bool {
+        self.effective_bits
+            .is_some_and(|bits| bits < self.bits_per_sample)
+    }
+
+    pub fn is_fake(&self) -> bool {
+        self.upsampled_from.is_some() || self.is_padded()
+    }
+
+    /// Short human readable summary, e.g. "upsampled from 44.1 kHz, 16-bit padded to 24-bit".
+    pub fn verdict(&self) -> String {
+        let mut findings = Vec::new();
+        if let Some(rate) = self.upsampled_from {
+            findings.push(format!("upsampled from {}", format_rate(rate)));
+        }
+        if let (true, Some(bits)) = (self.is_padded(), self.effective_bits) {
+            findings.push(format!(
+                "{}-bit padded to {}-bit",
+                bits, self.bits_per_sample
+            ));
+        }
+        if !findings.is_empty() {
+            findings.join(", ")
+        } else if self.sample_rate > 48000 || self.bits_per_sample > 16 {
+            "genuine high resolution".to_string()
+        } else {
+            "not high resolution".to_string()
+        }
+    }
+}
+
+/// Formats a sample rate in kHz, e.g. "44.1 kHz" or "48 kHz".
+pub fn format_rate(rate: u32) -> String {
+    format!("{} kHz", rate as f32 / 1000.0)
+}
+
+/// Checks whether the content of `input_path` reaches above the Nyquist frequency of lower
+/// sample rates and how many bits of the samples are used.
+/// Returns `None` if the file could not be decoded or the analysis was cancelled.
+pub fn detect_hires(
+    input_path: &str,
+    audio_info: &AudioInfo,
+    cancel_token: &AtomicBool,
+) -> Option<HiResReport> {
+    let mut bits = BitUsage::default();
+    let power = cutoff::average_power(input_path, audio_info, cancel_token, |samples| {
+        bits.add(samples)
+    })?;
+    Some(analyze_spectrum(&power, &bits, audio_info))
+}
+
+/// Checks the averaged power spectrum from `cutoff::average_power` and the bits used by
+/// the samples it was computed from.
+pub fn analyze_spectrum(power: &[f64], bits: &BitUsage, audio_info: &AudioInfo) -> HiResReport {
+    let bandwidth_hz = bandwidth(power, audio_info.sample_rate as f32 / 2.0);
+    // Upsampling at least doubles the rate, e.g. 44.1 to 96 kHz
+    let upsampled_from = bandwidth_hz.and_then(|bandwidth| {
+        SOURCE_RATES
+            .into_iter()
+            .filter(|&rate| rate * 2 <= audio_info.sample_rate)
+            .find(|&rate| bandwidth <= rate as f32 / 2.0 * NYQUIST_TOLERANCE)
+    });
+    // Lossy codecs have no bit depth, their decoded samples use every bit
+    let effective_bits = (audio_info.bits_per_sample > 0)
+        .then(|| bits.effective_bits(audio_info.bits_per_sample))
+        .flatten();
+
+    HiResReport {
+        sample_rate: audio_info.sample_rate,
+        bits_per_sample: audio_info.bits_per_sample,
+        bandwidth_hz,
+        upsampled_from,
+        effective_bits,
+    }
+}
+
+/// Upper edge of the highest band of `power` clearly above the noise floor,
+/// `None` if the content reaches Nyquist or the spectrum is flat.
+fn bandwidth(power: &[f64], nyquist_hz: f32) -> Option<f32> {
+    let bin_hz = nyquist_hz / power.len() as f32;
+    let bins_per_band = ((BAND_HZ / bin_hz).round() as usize).max(1);
+    let band_hz = bin_hz * bins_per_band as f32;
+    let bands: Vec<f32> = power
+        .chunks(bins_per_band)
+        .map(|chunk| {
+            let mean = chunk.iter().sum::<f64>() / chunk.len() as f64;
+            10.0 * (mean.max(1e-20)).log10() as f32
+        })
+        .collect();
+
+    let mut sorted = bands.clone();
+    sorted.sort_by(f32::total_cmp);
+    let floor = sorted[sorted.len() / 10];
+    let last = bands
+        .iter()
+        .rposition(|&level| level > floor + CONTENT_DB)?;
+    (last + 1 < bands.len()).then(|| (last + 1) as f32 * band_hz)
+}
+
+/// Collects which bits of the 24-bit integer grid the samples use.
+#[derive(Default)]
+pub struct BitUsage {
+    used: u32,
+    /// Some samples are finer than 24 bits, e.g. in 32-bit or float files.
+    finer: bool,
+}
+
+impl BitUsage {
+    pub fn add(&mut self, samples: &[f32]) {
+        if self.finer {
+            return;
+        }
+        for &sample in samples {
+            let scaled = sample as f64 * SCALE_24_BIT;
+            if scaled.fract() != 0.0 {
+                self.finer = true;
+                return;
+            }
+            self.used |= scaled as i64 as u32;
+        }
+    }
+
+    /// Highest bit depth that holds all samples, `None` for silence.
+    fn effective_bits(&self, bits_per_sample: u32) -> Option<u32> {
+        if self.finer {
+            Some(bits_per_sample)
+        } else if self.used == 0 {
+            None
+        } else {
+            Some(24 - (self.used & 0xFFFFFF).trailing_zeros())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::AudioSource;
+
+    fn audio_info(sample_rate: u32, bits_per_sample: u32) -> AudioInfo {
+        AudioInfo {
+            duration: 10.0,
+            sample_rate,
+            format: "flac".to_string(),
+            bits_per_sample,
+            channels: 1,
+            channel_names: vec!["FC".to_string()],
+            source: AudioSource::default(),
+        }
+    }
+
+    /// Averaged power of music up to `bandwidth_hz` over a -140 dB noise floor.
+    fn spectrum(bandwidth_hz: f32, nyquist_hz: f32) -> Vec<f64> {
+        let bins = 4096;
+        (0..bins)
+            .map(|i| {
+                let hz = i as f32 * nyquist_hz / bins as f32;
+                let db = if hz < bandwidth_hz { -30.0 } else { -140.0 };
+                10f64.powf(db / 10.0)
+            })
+            .collect()
+    }
+
+    /// A sine quantized to `bits` and scaled to floats like ffmpeg decodes it.
+    fn quantized_sine(bits: u32) -> Vec<f32> {
+        let scale = (1u32 << (bits - 1)) as f64;
+        (0..4800)
+            .map(|i| {
+                let sample = 0.5 * (i as f64 * 0.05).sin();
+                ((sample * scale).round() / scale) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn padded_16_bit_samples() {
+        let mut bits = BitUsage::default();
+        bits.add(&quantized_sine(16));
+        assert!(!bits.finer);
+        assert_eq!(bits.effective_bits(24), Some(16));
+
+        let info = audio_info(96000, 24);
+        let report = analyze_spectrum(&spectrum(f32::INFINITY, 48000.0), &bits, &info);
+        assert_eq!(report.effective_bits, Some(16));
+        assert!(report.is_padded());
+        assert_eq!(report.verdict(), "16-bit padded to 24-bit");
+    }
+
+    #[test]
+    fn genuine_24_bit_samples() {
+        let mut bits = BitUsage::default();
+        bits.add(&quantized_sine(24));
+        assert_eq!(bits.effective_bits(24), Some(24));
+    }
+
+    #[test]
+    fn float_samples_are_finer() {
+        let mut bits = BitUsage::default();
+        bits.add(&[0.25, 0.1234567, -0.5]);
+        assert!(bits.finer);
+        assert_eq!(bits.effective_bits(32), Some(32));
+    }
+
+    #[test]
+    fn silence_has_no_bit_depth() {
+        let mut bits = BitUsage::default();
+        bits.add(&[0.0; 64]);
+        assert_eq!(bits.effective_bits(24), None);
+    }
+
+    #[test]
+    fn upsampled_from_44_1_khz() {
+        let power = spectrum(20000.0, 48000.0);
+        let bandwidth = bandwidth(&power, 48000.0).unwrap();
+        assert!(
+            (bandwidth - 20000.0).abs() <= BAND_HZ,
+            "bandwidth {}",
+            bandwidth
+        );
+
+        let report = analyze_spectrum(&power, &BitUsage::default(), &audio_info(96000, 24));
+        assert_eq!(report.upsampled_from, Some(44100));
+        assert!(report.is_fake());
+    }
+
+    #[test]
+    fn full_band_is_not_upsampled() {
+        let power = spectrum(f32::INFINITY, 48000.0);
+        assert_eq!(bandwidth(&power, 48000.0), None);
+
+        let report = analyze_spectrum(&power, &BitUsage::default(), &audio_info(96000, 24));
+        assert_eq!(report.upsampled_from, None);
+        assert_eq!(report.effective_bits, None);
+        assert_eq!(report.verdict(), "genuine high resolution");
+    }
+}
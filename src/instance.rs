@@ -0,0 +1,217 @@
+use eframe::egui;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// First line of every message, so unrelated connections are ignored.
+const HEADER: &str = "spek-rs open";
+/// Answer of the running instance once it got the files.
+const ACK: &str = "spek-rs opened";
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long either side waits for the message of the other one.
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Result of looking for an already running instance.
+pub enum Instance {
+    /// No other instance is running, this one receives the files of later launches.
+    Primary(Listener),
+    /// The files were handed over to the running instance.
+    Forwarded,
+}
+
+/// Forwards `paths` to the running instance or becomes the running instance.
+/// Returns `None` if neither is possible, the app then works on its own.
+pub fn acquire(paths: &[String]) -> Option<Instance> {
+    if forward(paths).is_ok() {
+        return Some(Instance::Forwarded);
+    }
+    match imp::bind() {
+        Ok(listener) => Some(Instance::Primary(Listener(listener))),
+        Err(e) => {
+            eprintln!("Single instance mode not available: {}", e);
+            None
+        }
+    }
+}
+
+/// Makes `path` absolute, as the running instance may have another working directory.
+pub fn absolute_path(path: &str) -> String {
+    std::path::absolute(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+fn forward(paths: &[String]) -> Result<(), String> {
+    let mut stream = imp::connect()?;
+    let mut message = format!("{}\n", HEADER);
+    for path in paths {
+        message.push_str(path);
+        message.push('\n');
+    }
+    // An empty line ends the list
+    message.push('\n');
+    stream
+        .write_all(message.as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    // Whatever listens on the socket may not be a running instance that works
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .map_err(|e| e.to_string())?;
+    if reply.trim_end() != ACK {
+        return Err("no answer from the running instance".to_string());
+    }
+    Ok(())
+}
+
+/// Local socket of the running instance.
+pub struct Listener(imp::Listener);
+
+impl Listener {
+    /// Accepts launches in the background, every message is a list of files to open
+    /// (empty if the app was started without files) and wakes up `ctx`.
+    pub fn spawn(self, ctx: egui::Context) -> Receiver<Vec<String>> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in self.0.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                // A client that never finishes its message must not hold up later launches
+                let sender = sender.clone();
+                let ctx = ctx.clone();
+                thread::spawn(move || receive(stream, &sender, &ctx));
+            }
+        });
+        receiver
+    }
+}
+
+/// Reads the files of one launch and confirms them once they are handed over to the app.
+fn receive(stream: imp::Stream, sender: &Sender<Vec<String>>, ctx: &egui::Context) {
+    stream.set_read_timeout(Some(MESSAGE_TIMEOUT)).ok();
+    stream.set_write_timeout(Some(MESSAGE_TIMEOUT)).ok();
+    let mut lines = BufReader::new(&stream).lines();
+    if !matches!(lines.next(), Some(Ok(line)) if line == HEADER) {
+        return;
+    }
+    let mut paths = Vec::new();
+    loop {
+        match lines.next() {
+            Some(Ok(line)) if line.is_empty() => break,
+            Some(Ok(line)) => paths.push(line),
+            // Cut off or timed out
+            _ => return,
+        }
+    }
+    if sender.send(paths).is_err() {
+        return;
+    }
+    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+    ctx.request_repaint();
+    (&stream).write_all(format!("{}\n", ACK).as_bytes()).ok();
+}
+
+fn user_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "user".to_string())
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::*;
+    use std::io::ErrorKind;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    pub type Stream = UnixStream;
+
+    pub struct Listener(UnixListener);
+
+    impl Listener {
+        pub fn incoming(&self) -> impl Iterator<Item = std::io::Result<Stream>> + '_ {
+            self.0.incoming()
+        }
+    }
+
+    fn socket_path() -> PathBuf {
+        dirs::runtime_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join(format!("spek-rs-{}.sock", user_name()))
+    }
+
+    pub fn connect() -> Result<UnixStream, String> {
+        let stream = UnixStream::connect(socket_path()).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(CONNECT_TIMEOUT)).ok();
+        stream.set_read_timeout(Some(MESSAGE_TIMEOUT)).ok();
+        Ok(stream)
+    }
+
+    pub fn bind() -> Result<Listener, String> {
+        let path = socket_path();
+        let listener = match UnixListener::bind(&path) {
+            // Nobody accepts connections, so the socket was left behind by an instance that
+            // crashed. A live one, e.g. of a launch at the same moment, is kept.
+            Err(e) if e.kind() == ErrorKind::AddrInUse && UnixStream::connect(&path).is_err() => {
+                std::fs::remove_file(&path).ok();
+                UnixListener::bind(&path)
+            }
+            result => result,
+        };
+        listener
+            .map(Listener)
+            .map_err(|e| format!("failed to bind '{}': {}", path.display(), e))
+    }
+}
+
+/// Loopback TCP with the port stored in a file, as std has no named pipes.
+#[cfg(not(unix))]
+mod imp {
+    use super::*;
+    use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+
+    pub type Stream = TcpStream;
+
+    pub struct Listener(TcpListener);
+
+    impl Listener {
+        pub fn incoming(&self) -> impl Iterator<Item = std::io::Result<Stream>> + '_ {
+            self.0.incoming()
+        }
+    }
+
+    fn port_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|path| path.join("spek-rs").join(format!("{}.port", user_name())))
+    }
+
+    pub fn connect() -> Result<TcpStream, String> {
+        let path = port_path().ok_or("no cache directory")?;
+        let port: u16 = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())?
+            .trim()
+            .parse()
+            .map_err(|_| "invalid port file".to_string())?;
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let stream =
+            TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(CONNECT_TIMEOUT)).ok();
+        // A stale port file may point at another program, it won't answer
+        stream.set_read_timeout(Some(MESSAGE_TIMEOUT)).ok();
+        Ok(stream)
+    }
+
+    pub fn bind() -> Result<Listener, String> {
+        let path = port_path().ok_or("no cache directory")?;
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(|e| e.to_string())?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
+        std::fs::write(&path, port.to_string())
+            .map_err(|e| format!("failed to write '{}': {}", path.display(), e))?;
+        Ok(Listener(listener))
+    }
+}
@@ -1,5 +1,10 @@
+use crate::clipping::{ClipEvent, ClipKind};
+use crate::cutoff::CutoffReport;
 use crate::palettes;
+use crate::settings::{FrequencyAxis, FrequencyScale, Strip, ViewRange};
+use crate::spectrum::AverageSpectrum;
 use crate::utils::AudioInfo;
+use crate::waveform::PeakEnvelope;
 use ab_glyph::{Font, FontVec, PxScale};
 use font_kit::source::SystemSource;
 use image::{Rgba, RgbaImage};
@@ -10,12 +15,36 @@ pub const TOP_MARGIN: u32 = 64;
 pub const BOTTOM_MARGIN: u32 = 64;
 pub const LEFT_MARGIN: u32 = 80;
 pub const RIGHT_MARGIN: u32 = 100;
+/// Height of a strip under the spectrogram and the space above it.
+pub const STRIP_HEIGHT: u32 = 56;
+pub const STRIP_GAP: u32 = 8;
 
+/// Space taken by `strips` strips between the spectrogram and the time scale.
+pub fn strips_height(strips: usize) -> u32 {
+    strips as u32 * (STRIP_GAP + STRIP_HEIGHT)
+}
+
+/// Margin under the spectrogram, including the strips.
+pub fn bottom_margin(strips: usize) -> u32 {
+    BOTTOM_MARGIN + strips_height(strips)
+}
+
+/// Top edge of the strip at `index` under a spectrogram of `spec_height`.
+pub fn strip_top(spec_height: u32, index: usize) -> u32 {
+    TOP_MARGIN + spec_height + strips_height(index) + STRIP_GAP
+}
+
+fn load_font() -> FontVec {
+    let font_data = include_bytes!("../assets/DejaVuLGCSans.ttf");
+    FontVec::try_from_vec(font_data.to_vec()).expect("Error constructing Font from bytes")
+}
+
+#[allow(clippy::too_many_arguments)]
 fn draw_time_scale(
     image: &mut RgbaImage,
     spec_width: u32,
     spec_height: u32,
-    duration: f64,
+    time_range: (f64, f64),
     font: &FontVec,
     scale: PxScale,
     line_color: Rgba<u8>,
@@ -24,6 +53,14 @@ fn draw_time_scale(
     draw_labels: bool,
 ) {
     let num_ticks = 10;
+    let (start, end) = time_range;
+    // Show fractions of a second when zoomed in
+    let step = (end - start) / num_ticks as f64;
+    let decimals = if step >= 1.0 {
+        0
+    } else {
+        (-step.log10()).ceil().clamp(1.0, 3.0) as usize
+    };
     for i in 0..=num_ticks {
         let fraction = i as f32 / num_ticks as f32;
         let x = (LEFT_MARGIN - 1) as f32 + fraction * (spec_width as f32 + 1.0); // "- 1" so it starts with border
@@ -42,10 +79,21 @@ fn draw_time_scale(
         draw_line_segment_mut(image, (x, y_start), (x, y_end), line_color);
 
         if draw_labels {
-            let time_sec = duration * fraction as f64;
+            let time_sec = start + (end - start) * fraction as f64;
             let minutes = (time_sec / 60.0).floor() as u32;
-            let seconds = (time_sec % 60.0).floor() as u32;
-            let label = format!("{}:{:02}", minutes, seconds);
+            let label = if decimals == 0 {
+                let seconds = (time_sec % 60.0).floor() as u32;
+                format!("{}:{:02}", minutes, seconds)
+            } else {
+                let seconds = time_sec - minutes as f64 * 60.0;
+                format!(
+                    "{}:{:0width$.decimals$}",
+                    minutes,
+                    seconds,
+                    width = decimals + 3,
+                    decimals = decimals
+                )
+            };
             let (text_width, _) = imageproc::drawing::text_size(scale, font, &label);
             draw_text_mut(
                 image,
@@ -60,78 +108,144 @@ fn draw_time_scale(
     }
 }
 
+/// Evenly spaced ticks from the bottom to the top of a lane, as (height fraction, label).
+fn linear_freq_ticks(freq_range: (f32, f32), lane_height: u32) -> Vec<(f32, String)> {
+    let min_freq_khz = freq_range.0 / 1000.0;
+    let max_freq_khz = freq_range.1 / 1000.0;
+
+    // About one label every 32 px, at least the middle one if the lane has room for it
+    let num_ticks = if lane_height >= 48 {
+        (lane_height / 32).clamp(2, 10)
+    } else {
+        1
+    };
+    let label_step_khz = (max_freq_khz - min_freq_khz) / num_ticks as f32;
+
+    (0..=num_ticks)
+        .map(|i| {
+            let fraction = i as f32 / num_ticks as f32;
+            let freq_khz = min_freq_khz + fraction * (max_freq_khz - min_freq_khz);
+            (fraction, freq_label(freq_khz, label_step_khz))
+        })
+        .collect()
+}
+
+/// Ticks at round frequencies (20, 50, 100, 200, 500 Hz, 1 kHz...) for the non-linear axes,
+/// leaving out those too close to the one below.
+fn round_freq_ticks(axis: FrequencyAxis, lane_height: u32) -> Vec<(f32, String)> {
+    const MIN_SPACING: f32 = 16.0;
+    let mut ticks = Vec::new();
+    let mut last_y: Option<f32> = None;
+    for decade in [10.0, 100.0, 1000.0, 10000.0, 100000.0] {
+        for step in [1.0, 2.0, 5.0] {
+            let freq: f32 = decade * step;
+            let fraction = axis.fraction_of(freq);
+            if !(0.0..=1.0).contains(&fraction) {
+                continue;
+            }
+            let y = fraction * lane_height as f32;
+            if last_y.is_some_and(|last| y - last < MIN_SPACING) {
+                continue;
+            }
+            last_y = Some(y);
+            let label = if freq >= 1000.0 {
+                format!("{:.0} kHz", freq / 1000.0)
+            } else {
+                format!("{:.0} Hz", freq)
+            };
+            ticks.push((fraction, label));
+        }
+    }
+    ticks
+}
+
+#[allow(clippy::too_many_arguments)]
 fn draw_freq_scale(
     image: &mut RgbaImage,
     spec_width: u32,
     spec_height: u32,
     audio_info: AudioInfo,
+    axis: FrequencyAxis,
     font: &FontVec,
     scale: PxScale,
     line_color: Rgba<u8>,
     text_color: Rgba<u8>,
     split_channels: bool,
 ) {
-    let max_freq_khz = (audio_info.sample_rate / 2) as f32 / 1000.0;
-    let draw_multi_channel = audio_info.channels > 1 && split_channels;
+    // ffmpeg's separate mode gives every channel the same whole number of rows
+    let lanes = if split_channels {
+        audio_info.channels.max(1)
+    } else {
+        1
+    };
+    let lane_height = (spec_height / lanes).max(1);
 
-    let channel_count = if draw_multi_channel { 2 } else { 1 };
-    let height_per_channel = if draw_multi_channel {
-        spec_height / 2
+    let ticks = if axis.scale == FrequencyScale::Linear {
+        linear_freq_ticks(axis.range, lane_height)
     } else {
-        spec_height
+        round_freq_ticks(axis, lane_height)
     };
 
-    for channel in 0..channel_count {
-        let y_offset = TOP_MARGIN + (channel * height_per_channel);
-        let num_ticks = if draw_multi_channel { 5 } else { 10 };
-        for i in 0..=num_ticks {
-            let fraction = i as f32 / num_ticks as f32;
-            let y = (y_offset - 1) as f32 + (1.0 - fraction) * (height_per_channel + 1) as f32;
+    for lane in 0..lanes {
+        let y_offset = TOP_MARGIN + (lane * lane_height);
+        for (fraction, label) in &ticks {
+            // The top of a lane is the bottom of the one above it, skip it to avoid overlap
+            if lane > 0 && *fraction >= 1.0 {
+                continue;
+            }
 
-            let x_start_left = LEFT_MARGIN as f32 - 6.0;
+            let y = (y_offset - 1) as f32 + (1.0 - fraction) * (lane_height + 1) as f32;
 
-            if !(draw_multi_channel && channel == 1 && i == num_ticks) {
-                // Left ticks
-                let x_end_left = LEFT_MARGIN as f32 - 1.0;
-                draw_line_segment_mut(image, (x_start_left, y), (x_end_left, y), line_color);
+            // Left ticks
+            let x_start_left = LEFT_MARGIN as f32 - 6.0;
+            let x_end_left = LEFT_MARGIN as f32 - 1.0;
+            draw_line_segment_mut(image, (x_start_left, y), (x_end_left, y), line_color);
 
-                // Right ticks
-                let x_start_right = LEFT_MARGIN as f32 + spec_width as f32 + 1.0;
-                let x_end_right = x_start_right + 5.0;
-                draw_line_segment_mut(image, (x_start_right, y), (x_end_right, y), line_color);
-            }
+            // Right ticks
+            let x_start_right = LEFT_MARGIN as f32 + spec_width as f32 + 1.0;
+            let x_end_right = x_start_right + 5.0;
+            draw_line_segment_mut(image, (x_start_right, y), (x_end_right, y), line_color);
 
             // Freq labels
-            if draw_multi_channel && channel == 1 && i == num_ticks {
-                // Skip max freq label for bottom channel to avoid overlap
-            } else {
-                let freq_khz = fraction * max_freq_khz;
-                let label = format!("{:.0} kHz", freq_khz);
-                let (text_width, text_height) = imageproc::drawing::text_size(scale, font, &label);
-                draw_text_mut(
-                    image,
-                    text_color,
-                    (x_start_left - text_width as f32 - 8.0) as i32,
-                    (y - text_height as f32 / 2.0) as i32 - 2,
-                    scale,
-                    font,
-                    &label,
-                );
-            }
+            let (text_width, text_height) = imageproc::drawing::text_size(scale, font, label);
+            draw_text_mut(
+                image,
+                text_color,
+                (x_start_left - text_width as f32 - 8.0) as i32,
+                (y - text_height as f32 / 2.0) as i32 - 2,
+                scale,
+                font,
+                label,
+            );
         }
     }
 }
 
+/// Formats a frequency label with enough precision to tell neighbouring ticks apart.
+fn freq_label(freq_khz: f32, step_khz: f32) -> String {
+    if step_khz >= 1.0 {
+        format!("{:.0} kHz", freq_khz)
+    } else if step_khz >= 0.1 {
+        format!("{:.1} kHz", freq_khz)
+    } else {
+        format!("{:.0} Hz", freq_khz * 1000.0)
+    }
+}
+
+/// Labels of the gradient, dBFS from `levels.0` at the bottom to `levels.1` at the top, or
+/// with `delta_range` a level difference from `+delta_range` to `-delta_range`.
+#[allow(clippy::too_many_arguments)]
 fn draw_dbfs_scale(
     image: &mut RgbaImage,
     spec_width: u32,
     spec_height: u32,
+    levels: (f32, f32),
+    delta_range: Option<f32>,
     font: &FontVec,
     scale: PxScale,
     color: Rgba<u8>,
 ) {
-    let db_range: f32 = -120.0;
-    let num_ticks = 10;
+    let num_ticks = if delta_range.is_some() { 6 } else { 10 };
     let gradient_x = LEFT_MARGIN as f32 + spec_width as f32 + 34.0;
     let gradient_width = 10.0;
     let label_x = gradient_x + gradient_width + 5.0;
@@ -140,8 +254,16 @@ fn draw_dbfs_scale(
         let fraction = i as f32 / num_ticks as f32;
         let y = (TOP_MARGIN - 1) as f32 + (1.0 - fraction) * (spec_height + 1) as f32;
 
-        let db_level = (fraction - 1.0) * db_range.abs();
-        let label = format!("{:.0}", db_level);
+        let label = match delta_range {
+            Some(range) => match (fraction * 2.0 - 1.0) * range {
+                delta if delta.abs() < 0.05 => "0".to_string(),
+                delta => format!("{:+.0}", delta),
+            },
+            None => match levels.0 + fraction * (levels.1 - levels.0) {
+                level if level.abs() < 0.5 => "0".to_string(),
+                level => format!("{:.0}", level),
+            },
+        };
 
         let (_, text_height) = imageproc::drawing::text_size(scale, font, &label);
         draw_text_mut(
@@ -181,6 +303,7 @@ fn truncate_text(font: &FontVec, scale: PxScale, text: &str, max_width: u32) ->
     String::new()
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw_text_with_fallback(
     image: &mut RgbaImage,
     color: Rgba<u8>,
@@ -261,23 +384,6 @@ fn draw_text_with_fallback(
     }
 }
 
-fn yuv8bit_to_rgb(y: f32, u: f32, v: f32) -> Rgba<u8> {
-    // Formula for full-range YUV [0,255] to RGB [0,255]
-    let u = u - 128.0;
-    let v = v - 128.0;
-
-    let r = y + 1.402 * v;
-    let g = y - 0.344136 * u - 0.714136 * v;
-    let b = y + 1.772 * u;
-
-    Rgba([
-        r.clamp(0.0, 255.0) as u8,
-        g.clamp(0.0, 255.0) as u8,
-        b.clamp(0.0, 255.0) as u8,
-        255,
-    ])
-}
-
 pub fn draw_gradient_line_mut(
     image: &mut RgbaImage,
     start: (f32, f32),
@@ -296,15 +402,7 @@ pub fn draw_gradient_line_mut(
 
     if steps < 1.0 {
         if (x0 as u32) < image.width() && (y0 as u32) < image.height() {
-            let (_, y_interp, u_interp, v_interp) = palette[0];
-            let y_8bit = y_interp * 255.0;
-            let u_8bit = 128.0 + u_interp * 255.0 * saturation;
-            let v_8bit = 128.0 + v_interp * 255.0 * saturation;
-            let color = yuv8bit_to_rgb(
-                y_8bit.clamp(0.0, 255.0),
-                u_8bit.clamp(0.0, 255.0),
-                v_8bit.clamp(0.0, 255.0),
-            );
+            let color = palettes::color_at(palette, 0.0, saturation);
             for i in 0..thickness {
                 let x = (x0 as u32) + i;
                 if x < image.width() {
@@ -321,42 +419,7 @@ pub fn draw_gradient_line_mut(
         let x_pos = (x0 + t * dx).round() as u32;
         let y_pos = (y0 + t * dy).round() as u32;
 
-        // Find the segment in the palette that `a` falls into
-        let mut end_idx = 1;
-        while end_idx < palette.len() - 1 && palette[end_idx].0 < a {
-            end_idx += 1;
-        }
-        let start_idx = end_idx - 1;
-
-        let start_stop = palette[start_idx];
-        let end_stop = palette[end_idx];
-
-        let (start_a, start_y, start_u, start_v) = start_stop;
-        let (end_a, end_y, end_u, end_v) = end_stop;
-
-        // Calculate interpolation factor within the segment
-        let lerp_frac = if (end_a - start_a).abs() < f32::EPSILON {
-            0.0
-        } else {
-            (a - start_a) / (end_a - start_a)
-        };
-
-        // Interpolate Y, U, V
-        let y_interp = start_y * (1.0 - lerp_frac) + end_y * lerp_frac;
-        let u_interp = start_u * (1.0 - lerp_frac) + end_u * lerp_frac;
-        let v_interp = start_v * (1.0 - lerp_frac) + end_v * lerp_frac;
-
-        // Construct 8-bit YUV pixel, applying saturation, to match ffmpeg's internal pipeline
-        let y_8bit = y_interp * 255.0;
-        let u_8bit = 128.0 + u_interp * 255.0 * saturation;
-        let v_8bit = 128.0 + v_interp * 255.0 * saturation;
-
-        // Clip YUV components before conversion, which is crucial for high saturation
-        let color = yuv8bit_to_rgb(
-            y_8bit.clamp(0.0, 255.0),
-            u_8bit.clamp(0.0, 255.0),
-            v_8bit.clamp(0.0, 255.0),
-        );
+        let color = palettes::color_at(palette, a, saturation);
 
         // Draw a horizontal line for thickness
         for k in 0..thickness {
@@ -372,25 +435,40 @@ use crate::settings::SpectrogramColorScheme;
 
 /// Creates an image with a legend template.
 /// The spectrogram itself will be drawn on top of this template later.
+#[allow(clippy::too_many_arguments)]
 pub fn draw_legend(
     spec_width: u32,
     spec_height: u32,
     filename: &str,
     ffmpeg_settings: &str,
     audio_info: Option<AudioInfo>,
+    view: ViewRange,
+    frequency_scale: FrequencyScale,
     saturation: f32,
     color_scheme: SpectrogramColorScheme,
+    levels: (f32, f32),
+    delta_range: Option<f32>,
     split_channels: bool,
+    strips: &[Strip],
     bg_color_param: [u8; 3],
     text_color_param: [u8; 3],
     line_color_param: [u8; 3],
 ) -> RgbaImage {
     let final_width = spec_width + LEFT_MARGIN + RIGHT_MARGIN;
-    let final_height = spec_height + TOP_MARGIN + BOTTOM_MARGIN;
+    let final_height = spec_height + TOP_MARGIN + bottom_margin(strips.len());
+    let lanes = match &audio_info {
+        Some(info) if split_channels => info.channels.max(1),
+        _ => 1,
+    };
 
     // Create a new image with a background color
     let mut image = RgbaImage::new(final_width, final_height);
-    let bg_color = Rgba([bg_color_param[0], bg_color_param[1], bg_color_param[2], 255u8]);
+    let bg_color = Rgba([
+        bg_color_param[0],
+        bg_color_param[1],
+        bg_color_param[2],
+        255u8,
+    ]);
     draw_filled_rect_mut(
         &mut image,
         Rect::at(0, 0).of_size(final_width, final_height),
@@ -398,7 +476,12 @@ pub fn draw_legend(
     );
 
     // Draw spec borders
-    let line_color = Rgba([line_color_param[0], line_color_param[1], line_color_param[2], 255u8]);
+    let line_color = Rgba([
+        line_color_param[0],
+        line_color_param[1],
+        line_color_param[2],
+        255u8,
+    ]);
     let top_left = (LEFT_MARGIN as f32 - 1.0, TOP_MARGIN as f32 - 1.0);
     let top_right = ((LEFT_MARGIN + spec_width) as f32, TOP_MARGIN as f32 - 1.0);
     let bottom_left = (LEFT_MARGIN as f32 - 1.0, (TOP_MARGIN + spec_height) as f32);
@@ -412,14 +495,17 @@ pub fn draw_legend(
     draw_line_segment_mut(&mut image, bottom_left, top_left, line_color);
 
     // Load font
-    let font_data = include_bytes!("../assets/DejaVuLGCSans.ttf");
-    let font =
-        FontVec::try_from_vec(font_data.to_vec()).expect("Error constructing Font from bytes");
+    let font = load_font();
 
     let font_normal = PxScale::from(16.0);
     let font_small = PxScale::from(13.0);
     let font_scales = PxScale::from(14.0);
-    let text_color = Rgba([text_color_param[0], text_color_param[1], text_color_param[2], 255u8]);
+    let text_color = Rgba([
+        text_color_param[0],
+        text_color_param[1],
+        text_color_param[2],
+        255u8,
+    ]);
 
     // Draw filename
     draw_text_with_fallback(
@@ -442,6 +528,14 @@ pub fn draw_legend(
         if info.bits_per_sample > 0 {
             details.push(format!("{} bit", info.bits_per_sample));
         }
+        if info.source.stream > 0 {
+            details.push(format!("Stream {}", info.source.stream + 1));
+        }
+        if info.source.mid_side {
+            details.push("Mid/Side".to_string());
+        } else if !info.source.channels.is_empty() {
+            details.push(info.channel_names.join("+"));
+        }
         let audio_details = details.join(", ");
         if !ffmpeg_settings.is_empty() {
             display_string = format!("{}, {}", audio_details, ffmpeg_settings);
@@ -473,8 +567,8 @@ pub fn draw_legend(
         &app_info,
     );
 
-    // dBFS gradient (right)
-    let dbfs_label = "dBFS";
+    // dBFS gradient (right), a level difference is in plain dB
+    let dbfs_label = if delta_range.is_some() { "dB" } else { "dBFS" };
     let (text_width, _) = imageproc::drawing::text_size(font_small, &font, dbfs_label);
     let gradient_center_x = (LEFT_MARGIN + spec_width + 34 + 5) as i32;
     draw_text_mut(
@@ -487,30 +581,54 @@ pub fn draw_legend(
         dbfs_label,
     );
 
+    for (index, strip) in strips.iter().enumerate() {
+        draw_strip_frame(
+            &mut image,
+            spec_width,
+            strip_top(spec_height, index),
+            *strip,
+            lanes,
+            &font,
+            font_small,
+            line_color,
+            text_color,
+        );
+    }
+
     // Time scale (bottom)
+    let strips_bottom = spec_height + strips_height(strips.len());
     draw_text_mut(
         &mut image,
         text_color,
         (LEFT_MARGIN + spec_width / 2) as i32,
-        (TOP_MARGIN + spec_height + 35) as i32,
+        (TOP_MARGIN + strips_bottom + 35) as i32,
         font_normal,
         &font,
         "Time",
     );
 
     // dBFS vertical gradient line on the right
-    let palette = palettes::get_palette(color_scheme);
+    let palette = if delta_range.is_some() {
+        palettes::DIVERGING
+    } else {
+        palettes::get_palette(color_scheme)
+    };
     let line_x = (LEFT_MARGIN + spec_width + 34) as f32;
     let start_point = (line_x, TOP_MARGIN as f32);
     let end_point = (line_x, (TOP_MARGIN + spec_height) as f32);
     draw_gradient_line_mut(&mut image, start_point, end_point, palette, saturation, 10);
 
     if let Some(info) = audio_info {
+        let time_range = view.time_range(info.duration);
+        let axis = FrequencyAxis {
+            scale: frequency_scale,
+            range: view.frequency_range(info.sample_rate as f32 / 2.0),
+        };
         draw_time_scale(
             &mut image,
             spec_width,
-            spec_height,
-            info.duration,
+            strips_bottom,
+            time_range,
             &font,
             font_scales,
             line_color,
@@ -522,7 +640,7 @@ pub fn draw_legend(
             &mut image,
             spec_width,
             spec_height,
-            info.duration,
+            time_range,
             &font,
             font_scales,
             line_color,
@@ -535,6 +653,7 @@ pub fn draw_legend(
             spec_width,
             spec_height,
             info,
+            axis,
             &font,
             font_scales,
             line_color,
@@ -547,9 +666,627 @@ pub fn draw_legend(
         &mut image,
         spec_width,
         spec_height,
+        levels,
+        delta_range,
         &font,
         font_scales,
         text_color,
     );
     image
 }
+
+/// Draws the border, the scale and the name of an empty strip whose inside starts at `top`.
+/// The waveform is split into `lanes` like the spectrogram.
+#[allow(clippy::too_many_arguments)]
+fn draw_strip_frame(
+    image: &mut RgbaImage,
+    spec_width: u32,
+    top: u32,
+    strip: Strip,
+    lanes: u32,
+    font: &FontVec,
+    scale: PxScale,
+    line_color: Rgba<u8>,
+    text_color: Rgba<u8>,
+) {
+    let left = LEFT_MARGIN as f32 - 1.0;
+    let right = (LEFT_MARGIN + spec_width) as f32;
+    let top_y = top as f32 - 1.0;
+    let bottom_y = (top + STRIP_HEIGHT) as f32;
+    draw_line_segment_mut(image, (left, top_y), (right, top_y), line_color);
+    draw_line_segment_mut(image, (right, top_y), (right, bottom_y), line_color);
+    draw_line_segment_mut(image, (right, bottom_y), (left, bottom_y), line_color);
+    draw_line_segment_mut(image, (left, bottom_y), (left, top_y), line_color);
+
+    let (name, ticks) = match strip {
+        Strip::Waveform if lanes > 1 => {
+            // One lane per channel, the channel names are on the spectrogram lanes
+            for lane in 1..lanes {
+                let y = top as f32 + (lane * STRIP_HEIGHT / lanes) as f32 - 1.0;
+                draw_line_segment_mut(image, (left, y), (right, y), line_color);
+            }
+            ("Waveform", Vec::new())
+        }
+        Strip::Waveform => ("Waveform", vec![(1.0, "1"), (0.5, "0"), (0.0, "-1")]),
+        Strip::PhaseCorrelation => ("Correlation", vec![(1.0, "+1"), (0.5, "0"), (0.0, "-1")]),
+    };
+    for (fraction, label) in ticks {
+        let y = bottom_y - fraction * (STRIP_HEIGHT + 1) as f32;
+        draw_line_segment_mut(image, (left - 5.0, y), (left, y), line_color);
+        let (text_width, text_height) = imageproc::drawing::text_size(scale, font, label);
+        // Keep the outer labels inside the strip
+        let label_y = (y - text_height as f32 / 2.0 - 2.0)
+            .clamp(top_y - 2.0, bottom_y - text_height as f32 - 2.0);
+        draw_text_mut(
+            image,
+            text_color,
+            (left - 9.0 - text_width as f32) as i32,
+            label_y as i32,
+            scale,
+            font,
+            label,
+        );
+    }
+    // At the bottom, the top of the first strip is next to the dBFS label
+    let (_, text_height) = imageproc::drawing::text_size(scale, font, name);
+    draw_text_mut(
+        image,
+        text_color,
+        (LEFT_MARGIN + spec_width + 8) as i32,
+        (bottom_y - text_height as f32 - 4.0) as i32,
+        scale,
+        font,
+        name,
+    );
+}
+
+/// Plots the phase correlation of every column into the strip whose inside starts at `top`,
+/// green towards +1 (in phase) and red towards -1 (out of phase). `None` is silence.
+pub fn draw_phase_correlation(
+    image: &mut RgbaImage,
+    left: u32,
+    top: u32,
+    width: u32,
+    values: &[Option<f32>],
+    line_color: [u8; 3],
+) {
+    if values.is_empty() {
+        return;
+    }
+    let center = top as f32 + (STRIP_HEIGHT as f32 - 1.0) / 2.0;
+    let half_height = (STRIP_HEIGHT as f32 - 1.0) / 2.0;
+    let line = Rgba([line_color[0], line_color[1], line_color[2], 255]);
+    let mut x = left as f32;
+    while x < (left + width) as f32 {
+        draw_line_segment_mut(
+            image,
+            (x, center),
+            ((x + 2.0).min((left + width - 1) as f32), center),
+            line,
+        );
+        x += 6.0;
+    }
+
+    for column in 0..width {
+        let index = (column as usize * values.len() / width as usize).min(values.len() - 1);
+        let Some(value) = values[index] else {
+            continue;
+        };
+        let value = value.clamp(-1.0, 1.0);
+        let color = if value >= 0.0 {
+            Rgba([40, 200, 90, 255])
+        } else {
+            Rgba([235, 60, 60, 255])
+        };
+        let x = (left + column) as f32;
+        draw_line_segment_mut(image, (x, center), (x, center - value * half_height), color);
+    }
+}
+
+/// Plots the peak envelope of every channel into the strip whose inside starts at `top`,
+/// one lane per channel if `split`, otherwise the envelope of all channels together.
+pub fn draw_waveform(
+    image: &mut RgbaImage,
+    left: u32,
+    top: u32,
+    width: u32,
+    envelopes: &[PeakEnvelope],
+    split: bool,
+    line_color: [u8; 3],
+) {
+    let Some(columns) = envelopes.first().map(Vec::len).filter(|&len| len > 0) else {
+        return;
+    };
+    let lanes: Vec<PeakEnvelope> = if split {
+        envelopes.to_vec()
+    } else {
+        vec![(0..columns)
+            .map(|column| {
+                envelopes
+                    .iter()
+                    .fold((0.0f32, 0.0f32), |(min, max), envelope| {
+                        (min.min(envelope[column].0), max.max(envelope[column].1))
+                    })
+            })
+            .collect()]
+    };
+
+    let line = Rgba([line_color[0], line_color[1], line_color[2], 255]);
+    let color = Rgba([90, 170, 255, 255]);
+    let lane_count = lanes.len() as u32;
+    for (lane, envelope) in lanes.iter().enumerate() {
+        let lane_top = top + lane as u32 * STRIP_HEIGHT / lane_count;
+        let lane_height =
+            (lane as u32 + 1) * STRIP_HEIGHT / lane_count - lane as u32 * STRIP_HEIGHT / lane_count;
+        // Leave the separator line between the lanes free
+        let inner_height = if lane + 1 < lanes.len() {
+            lane_height - 1
+        } else {
+            lane_height
+        };
+        let half_height = (inner_height as f32 - 1.0) / 2.0;
+        let center = lane_top as f32 + half_height;
+        draw_line_segment_mut(
+            image,
+            (left as f32, center),
+            ((left + width - 1) as f32, center),
+            line,
+        );
+
+        for column in 0..width {
+            let index = (column as usize * columns / width as usize).min(columns - 1);
+            let (min, max) = envelope[index];
+            let x = (left + column) as f32;
+            draw_line_segment_mut(
+                image,
+                (x, center - max.clamp(-1.0, 1.0) * half_height),
+                (x, center - min.clamp(-1.0, 1.0) * half_height),
+                color,
+            );
+        }
+    }
+}
+
+/// Writes `note` right aligned on the line of the audio details in the header,
+/// covering the end of the details if they are too long.
+pub fn draw_header_note(
+    image: &mut RgbaImage,
+    note: &str,
+    bg_color_param: [u8; 3],
+    text_color_param: [u8; 3],
+) {
+    let font = load_font();
+    let font_normal = PxScale::from(16.0);
+    let (text_width, text_height) = imageproc::drawing::text_size(font_normal, &font, note);
+    let x = image.width().saturating_sub(text_width + 10);
+    let background_x = x.saturating_sub(12).max(LEFT_MARGIN);
+    draw_filled_rect_mut(
+        image,
+        Rect::at(background_x as i32, 26).of_size(
+            image.width().saturating_sub(background_x).max(1),
+            text_height + 6,
+        ),
+        Rgba([bg_color_param[0], bg_color_param[1], bg_color_param[2], 255]),
+    );
+    draw_text_mut(
+        image,
+        Rgba([
+            text_color_param[0],
+            text_color_param[1],
+            text_color_param[2],
+            255,
+        ]),
+        x as i32,
+        28,
+        font_normal,
+        &font,
+        note,
+    );
+}
+
+/// Marks the detected lossy cutoff with a dashed line across every channel lane.
+/// `origin` is the top left corner of the spectrogram inside `image`. With `draw_pointer`
+/// a small arrow is also drawn next to the frequency scale on the right.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_cutoff_marker(
+    image: &mut RgbaImage,
+    origin: (u32, u32),
+    spec_width: u32,
+    spec_height: u32,
+    lanes: u32,
+    axis: FrequencyAxis,
+    report: &CutoffReport,
+    draw_pointer: bool,
+) {
+    let Some(cutoff_hz) = report.cutoff_hz else {
+        return;
+    };
+    if cutoff_hz < axis.range.0 || cutoff_hz > axis.range.1 {
+        return;
+    }
+    // Cyan stands out on all palettes
+    let color = Rgba([0, 230, 255, 255]);
+    let font = load_font();
+    let font_scale = PxScale::from(13.0);
+
+    let lanes = lanes.max(1);
+    let lane_height = spec_height / lanes;
+    let fraction = axis.fraction_of(cutoff_hz).clamp(0.0, 1.0);
+    let x_start = origin.0 as f32;
+    let x_end = (origin.0 + spec_width) as f32 - 1.0;
+
+    for lane in 0..lanes {
+        let y = (origin.1 + lane * lane_height) as f32 + (1.0 - fraction) * lane_height as f32;
+
+        let mut x = x_start;
+        while x < x_end {
+            let dash_end = (x + 6.0).min(x_end);
+            draw_line_segment_mut(image, (x, y), (dash_end, y), color);
+            x += 10.0;
+        }
+
+        if draw_pointer {
+            // Arrow pointing at the frequency scale
+            let tip = x_end + 8.0;
+            for i in 0..10 {
+                let offset = i as f32 / 2.0;
+                draw_line_segment_mut(
+                    image,
+                    (tip + i as f32, y - offset),
+                    (tip + i as f32, y + offset),
+                    color,
+                );
+            }
+        }
+
+        if lane == 0 {
+            let label = format!("{:.1} kHz, {}", cutoff_hz / 1000.0, report.verdict());
+            let label = truncate_text(&font, font_scale, &label, spec_width.saturating_sub(8));
+            let (text_width, text_height) =
+                imageproc::drawing::text_size(font_scale, &font, &label);
+            // Below the line if there's no room above it
+            let label_y = if y - (text_height as f32) - 6.0 < origin.1 as f32 {
+                y + 4.0
+            } else {
+                y - text_height as f32 - 6.0
+            };
+            draw_text_mut(
+                image,
+                color,
+                (x_end - text_width as f32 - 4.0) as i32,
+                label_y as i32,
+                font_scale,
+                &font,
+                &label,
+            );
+        }
+    }
+}
+
+/// Marks clipping events with small triangles under the time scale of the spectrogram.
+/// `origin` is the top left corner of the spectrogram inside `image`.
+pub fn draw_clip_markers(
+    image: &mut RgbaImage,
+    origin: (u32, u32),
+    spec_width: u32,
+    spec_height: u32,
+    time_range: (f64, f64),
+    events: &[ClipEvent],
+) {
+    let (start, end) = time_range;
+    if end <= start {
+        return;
+    }
+    let y = (origin.1 + spec_height) as f32 + 1.0;
+    // Overs first, so clipped samples stay visible where both happen
+    for kind in [ClipKind::TruePeakOver, ClipKind::Clipped] {
+        let color = match kind {
+            ClipKind::Clipped => Rgba([255, 40, 40, 255]),
+            ClipKind::TruePeakOver => Rgba([255, 170, 0, 255]),
+        };
+        for event in events.iter().filter(|event| event.kind == kind) {
+            if event.time + event.duration < start || event.time > end {
+                continue;
+            }
+            let x_of = |time: f64| {
+                let fraction = ((time.clamp(start, end) - start) / (end - start)) as f32;
+                origin.0 as f32 + fraction * (spec_width as f32 - 1.0)
+            };
+            let x = x_of(event.time);
+            // Line under the whole event, for long stretches of clipping
+            let x_end = x_of(event.time + event.duration);
+            for offset in [0.0, 1.0] {
+                draw_line_segment_mut(image, (x, y + offset), (x_end, y + offset), color);
+            }
+            // Triangle pointing up at the spectrogram
+            for i in 0..7 {
+                let half_width = i as f32 * 0.6;
+                draw_line_segment_mut(
+                    image,
+                    (x - half_width, y + i as f32),
+                    (x + half_width, y + i as f32),
+                    color,
+                );
+            }
+        }
+    }
+}
+
+/// Writes the channel names into the top left corner of every lane of a split spectrogram.
+/// `origin` is the top left corner of the spectrogram inside `image`.
+pub fn draw_channel_labels(
+    image: &mut RgbaImage,
+    origin: (u32, u32),
+    spec_width: u32,
+    spec_height: u32,
+    names: &[String],
+) {
+    let lanes = names.len() as u32;
+    if lanes == 0 {
+        return;
+    }
+    let font = load_font();
+    let font_scale = PxScale::from(13.0);
+    let lane_height = spec_height / lanes;
+
+    for (lane, name) in names.iter().enumerate() {
+        let label = truncate_text(&font, font_scale, name, spec_width.saturating_sub(8));
+        let (text_width, text_height) = imageproc::drawing::text_size(font_scale, &font, &label);
+        let x = origin.0 + 4;
+        let y = origin.1 + lane as u32 * lane_height + 3;
+        if text_height + 6 > lane_height {
+            continue;
+        }
+
+        // Darken the spectrogram behind the label so it stays readable on bright palettes
+        for py in y.saturating_sub(2)..(y + text_height + 3).min(image.height()) {
+            for px in x.saturating_sub(3)..(x + text_width + 3).min(image.width()) {
+                let pixel = image.get_pixel_mut(px, py);
+                for c in 0..3 {
+                    pixel[c] /= 3;
+                }
+            }
+        }
+        draw_text_mut(
+            image,
+            Rgba([255, 255, 255, 255]),
+            x as i32,
+            y as i32 - 2,
+            font_scale,
+            &font,
+            &label,
+        );
+    }
+}
+
+/// Space right of the average spectrum plot, which has no gradient.
+const PLOT_RIGHT_MARGIN: u32 = 24;
+const AVERAGE_COLOR: Rgba<u8> = Rgba([90, 170, 255, 255]);
+const PEAK_HOLD_COLOR: Rgba<u8> = Rgba([255, 160, 40, 255]);
+
+/// Creates an image with the average and peak hold curves of `spectrum` on a frequency axis
+/// `plot_width` pixels wide and a level axis `plot_height` pixels high, from `levels.0`
+/// to `levels.1` dBFS.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_spectrum_plot(
+    spectrum: &AverageSpectrum,
+    filename: &str,
+    details: &str,
+    axis: FrequencyAxis,
+    levels: (f32, f32),
+    plot_width: u32,
+    plot_height: u32,
+    bg_color_param: [u8; 3],
+    text_color_param: [u8; 3],
+    line_color_param: [u8; 3],
+) -> RgbaImage {
+    let final_width = plot_width + LEFT_MARGIN + PLOT_RIGHT_MARGIN;
+    let final_height = plot_height + TOP_MARGIN + BOTTOM_MARGIN;
+    let mut image = RgbaImage::new(final_width, final_height);
+    let bg_color = Rgba([bg_color_param[0], bg_color_param[1], bg_color_param[2], 255]);
+    let text_color = Rgba([
+        text_color_param[0],
+        text_color_param[1],
+        text_color_param[2],
+        255,
+    ]);
+    let line_color = Rgba([
+        line_color_param[0],
+        line_color_param[1],
+        line_color_param[2],
+        255,
+    ]);
+    // Grid lines are a dim version of the line color
+    let grid_color = Rgba(std::array::from_fn(|i| {
+        if i == 3 {
+            255
+        } else {
+            ((bg_color_param[i] as u32 * 3 + line_color_param[i] as u32) / 4) as u8
+        }
+    }));
+    draw_filled_rect_mut(
+        &mut image,
+        Rect::at(0, 0).of_size(final_width, final_height),
+        bg_color,
+    );
+
+    let font = load_font();
+    let font_normal = PxScale::from(16.0);
+    let font_small = PxScale::from(13.0);
+    let font_scales = PxScale::from(14.0);
+
+    draw_text_with_fallback(
+        &mut image,
+        text_color,
+        LEFT_MARGIN as i32,
+        10,
+        font_normal,
+        &font,
+        filename,
+        plot_width,
+    );
+    let details = truncate_text(&font, font_normal, details, plot_width);
+    draw_text_mut(
+        &mut image,
+        text_color,
+        LEFT_MARGIN as i32,
+        28,
+        font_normal,
+        &font,
+        &details,
+    );
+
+    // Key of the curves in the top-right corner
+    let mut key_x = final_width - 10;
+    for (label, color) in [("Peak hold", PEAK_HOLD_COLOR), ("Average", AVERAGE_COLOR)] {
+        let (text_width, _) = imageproc::drawing::text_size(font_small, &font, label);
+        key_x -= text_width;
+        draw_text_mut(
+            &mut image,
+            text_color,
+            key_x as i32,
+            5,
+            font_small,
+            &font,
+            label,
+        );
+        key_x -= 22;
+        draw_filled_rect_mut(&mut image, Rect::at(key_x as i32, 12).of_size(16, 3), color);
+        key_x -= 12;
+    }
+
+    let left = LEFT_MARGIN as f32 - 1.0;
+    let right = (LEFT_MARGIN + plot_width) as f32;
+    let top = TOP_MARGIN as f32 - 1.0;
+    let bottom = (TOP_MARGIN + plot_height) as f32;
+    let level_y = |level: f32| {
+        let fraction =
+            ((level - levels.0) / (levels.1 - levels.0).max(f32::EPSILON)).clamp(0.0, 1.0);
+        top + (1.0 - fraction) * (plot_height + 1) as f32
+    };
+
+    // Level grid and labels (left), about one label every 28 px
+    let span = (levels.1 - levels.0).max(1.0);
+    let step = [1.0, 2.0, 3.0, 5.0, 10.0, 20.0, 30.0, 50.0]
+        .into_iter()
+        .find(|step| step / span * plot_height as f32 >= 28.0)
+        .unwrap_or(100.0);
+    let mut level = (levels.0 / step).ceil() * step;
+    while level <= levels.1 {
+        let y = level_y(level);
+        draw_dotted_line(&mut image, (left, right), y, grid_color);
+        draw_line_segment_mut(&mut image, (left - 5.0, y), (left, y), line_color);
+        let label = if level.abs() < 0.5 {
+            "0".to_string()
+        } else {
+            format!("{:.0}", level)
+        };
+        let (text_width, text_height) = imageproc::drawing::text_size(font_scales, &font, &label);
+        draw_text_mut(
+            &mut image,
+            text_color,
+            (left - 9.0 - text_width as f32) as i32,
+            (y - text_height as f32 / 2.0) as i32 - 2,
+            font_scales,
+            &font,
+            &label,
+        );
+        level += step;
+    }
+    let (text_width, _) = imageproc::drawing::text_size(font_small, &font, "dBFS");
+    draw_text_mut(
+        &mut image,
+        text_color,
+        (left - 9.0 - text_width as f32) as i32,
+        TOP_MARGIN as i32 - 22,
+        font_small,
+        &font,
+        "dBFS",
+    );
+
+    // Frequency grid and labels (bottom), the tick helpers space them for a vertical axis
+    let ticks = if axis.scale == FrequencyScale::Linear {
+        linear_freq_ticks(axis.range, plot_width / 3)
+    } else {
+        round_freq_ticks(axis, plot_width / 4)
+    };
+    for (fraction, label) in &ticks {
+        let x = left + fraction * (plot_width + 1) as f32;
+        for y in (TOP_MARGIN..TOP_MARGIN + plot_height).step_by(4) {
+            if (x as u32) > LEFT_MARGIN && (x as u32) < LEFT_MARGIN + plot_width {
+                image.put_pixel(x as u32, y, grid_color);
+            }
+        }
+        draw_line_segment_mut(&mut image, (x, bottom), (x, bottom + 5.0), line_color);
+        let (text_width, _) = imageproc::drawing::text_size(font_scales, &font, label);
+        let label_x =
+            (x - text_width as f32 / 2.0).clamp(0.0, final_width.saturating_sub(text_width) as f32);
+        draw_text_mut(
+            &mut image,
+            text_color,
+            label_x as i32,
+            bottom as i32 + 8,
+            font_scales,
+            &font,
+            label,
+        );
+    }
+    draw_text_mut(
+        &mut image,
+        text_color,
+        (LEFT_MARGIN + plot_width / 2) as i32 - 36,
+        (TOP_MARGIN + plot_height + 35) as i32,
+        font_normal,
+        &font,
+        "Frequency",
+    );
+
+    // Curves, every column shows the bins between its edges
+    let bins = spectrum.average_db.len();
+    let bin_hz = spectrum.frequency(1);
+    let columns: Vec<(f32, f32)> = (0..plot_width)
+        .map(|column| {
+            let bin_at = |fraction: f32| (axis.frequency_at(fraction) / bin_hz).round() as usize;
+            let from = bin_at(column as f32 / plot_width as f32).min(bins - 1);
+            let to = bin_at((column + 1) as f32 / plot_width as f32).clamp(from + 1, bins);
+            let power = spectrum.average_db[from..to]
+                .iter()
+                .map(|db| 10f64.powf(*db as f64 / 10.0))
+                .sum::<f64>()
+                / (to - from) as f64;
+            let peak = spectrum.peak_db[from..to]
+                .iter()
+                .fold(f32::MIN, |a, &b| a.max(b));
+            (10.0 * power.log10() as f32, peak)
+        })
+        .collect();
+    for (curve, color) in [(1, PEAK_HOLD_COLOR), (0, AVERAGE_COLOR)] {
+        let level = |point: &(f32, f32)| if curve == 0 { point.0 } else { point.1 };
+        for (column, pair) in columns.windows(2).enumerate() {
+            let x = (LEFT_MARGIN + column as u32) as f32;
+            draw_line_segment_mut(
+                &mut image,
+                (x, level_y(level(&pair[0]))),
+                (x + 1.0, level_y(level(&pair[1]))),
+                color,
+            );
+        }
+    }
+
+    draw_line_segment_mut(&mut image, (left, top), (right, top), line_color);
+    draw_line_segment_mut(&mut image, (right, top), (right, bottom), line_color);
+    draw_line_segment_mut(&mut image, (right, bottom), (left, bottom), line_color);
+    draw_line_segment_mut(&mut image, (left, bottom), (left, top), line_color);
+    image
+}
+
+/// Horizontal line of single pixels 4 px apart, from `x_range.0` to `x_range.1`.
+fn draw_dotted_line(image: &mut RgbaImage, x_range: (f32, f32), y: f32, color: Rgba<u8>) {
+    let mut x = x_range.0 + 4.0;
+    while x < x_range.1 {
+        if (x as u32) < image.width() && (y as u32) < image.height() {
+            image.put_pixel(x as u32, y as u32, color);
+        }
+        x += 4.0;
+    }
+}
@@ -0,0 +1,459 @@
+use serde::Serialize;
+use std::f64::consts::PI;
+use std::sync::atomic::AtomicBool;
+
+use crate::clipping::{ClipDetector, ClippingReport};
+use crate::pcm::PcmDecoder;
+use crate::utils::AudioInfo;
+
+/// Blocks quieter than this don't count towards the integrated loudness and loudness range.
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+/// Gates below the mean loudness for the integrated loudness and the loudness range, in LU.
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+/// Length of the momentary and short-term windows in 100 ms steps.
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+/// Taps of every phase of the true peak interpolation filter.
+const TRUE_PEAK_TAPS: usize = 12;
+
+/// Loudness and levels of a file as defined by EBU R128 and ITU-R BS.1770.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoudnessReport {
+    /// `None` if the file is too short or too quiet to be measured.
+    pub integrated_lufs: Option<f32>,
+    pub loudness_range_lu: Option<f32>,
+    pub short_term_max_lufs: Option<f32>,
+    pub sample_peak_dbfs: f32,
+    pub true_peak_dbtp: f32,
+    pub channels: Vec<ChannelLevels>,
+    pub clipping: ClippingReport,
+}
+
+/// Levels of a single channel.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelLevels {
+    pub name: String,
+    pub rms_dbfs: f32,
+    pub peak_dbfs: f32,
+}
+
+impl LoudnessReport {
+    /// Short summary for the legend, e.g. "-14.2 LUFS, LRA 6.3 LU, -0.8 dBTP".
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(integrated) = self.integrated_lufs {
+            parts.push(format!("{:.1} LUFS", integrated));
+        }
+        if let Some(range) = self.loudness_range_lu {
+            parts.push(format!("LRA {:.1} LU", range));
+        }
+        parts.push(format!("{} dBTP", format_db(self.true_peak_dbtp)));
+        parts.join(", ")
+    }
+}
+
+/// Formats a level in dB, silence as "-inf".
+pub fn format_db(db: f32) -> String {
+    if db.is_finite() {
+        format!("{:.1}", db)
+    } else {
+        "-inf".to_string()
+    }
+}
+
+/// Measures the loudness and levels of the stream of `input_path` described by `audio_info`.
+/// Returns `None` if the file could not be decoded or the analysis was cancelled.
+pub fn measure_loudness(
+    input_path: &str,
+    audio_info: &AudioInfo,
+    cancel_token: &AtomicBool,
+) -> Option<LoudnessReport> {
+    let decoder = PcmDecoder::spawn(
+        input_path,
+        &audio_info.source,
+        audio_info.channels,
+        audio_info.sample_rate,
+        None,
+    )?;
+    let mut meter = LoudnessMeter::new(
+        &audio_info.channel_names,
+        decoder.channels,
+        audio_info.sample_rate,
+    );
+    if !decoder.for_each_chunk(cancel_token, |samples| meter.process(samples)) {
+        return None;
+    }
+    Some(meter.report())
+}
+
+/// Accumulates the K-weighted energy of 100 ms blocks, the peaks and the RMS of every channel.
+struct LoudnessMeter {
+    names: Vec<String>,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    true_peak: TruePeakMeter,
+    clipping: ClipDetector,
+    block_frames: usize,
+    block_fill: usize,
+    block_energy: Vec<f64>,
+    /// Channel weighted mean square of every complete 100 ms block.
+    blocks: Vec<f64>,
+    sum_squares: Vec<f64>,
+    peaks: Vec<f32>,
+    frames: usize,
+}
+
+impl LoudnessMeter {
+    fn new(channel_names: &[String], channels: usize, sample_rate: u32) -> Self {
+        let names: Vec<String> = (0..channels)
+            .map(|c| {
+                channel_names
+                    .get(c)
+                    .cloned()
+                    .unwrap_or_else(|| format!("Channel {}", c + 1))
+            })
+            .collect();
+        // The LFE is left out and the surround channels are louder, as in BS.1770
+        let weights = names
+            .iter()
+            .map(|name| match name.as_str() {
+                "LFE" | "LFE2" => 0.0,
+                "SL" | "SR" | "BL" | "BR" => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+        let rate = sample_rate as f64;
+        Self {
+            clipping: ClipDetector::new(&names, sample_rate),
+            names,
+            weights,
+            filters: vec![[Biquad::high_shelf(rate), Biquad::high_pass(rate)]; channels],
+            true_peak: TruePeakMeter::new(channels, sample_rate),
+            block_frames: (sample_rate as usize / 10).max(1),
+            block_fill: 0,
+            block_energy: vec![0.0; channels],
+            blocks: Vec::new(),
+            sum_squares: vec![0.0; channels],
+            peaks: vec![0.0; channels],
+            frames: 0,
+        }
+    }
+
+    fn process(&mut self, interleaved: &[f32]) {
+        let channels = self.names.len();
+        for frame in interleaved.chunks_exact(channels) {
+            for (c, &sample) in frame.iter().enumerate() {
+                self.peaks[c] = self.peaks[c].max(sample.abs());
+                self.sum_squares[c] += (sample as f64).powi(2);
+                let [shelf, high_pass] = &mut self.filters[c];
+                let weighted = high_pass.process(shelf.process(sample as f64));
+                self.block_energy[c] += weighted * weighted;
+            }
+            let true_peaks = self.true_peak.process(frame);
+            self.clipping.process(frame, true_peaks);
+            self.frames += 1;
+
+            self.block_fill += 1;
+            if self.block_fill == self.block_frames {
+                let energy = self
+                    .block_energy
+                    .iter()
+                    .zip(&self.weights)
+                    .map(|(e, w)| w * e / self.block_frames as f64)
+                    .sum();
+                self.blocks.push(energy);
+                self.block_energy.fill(0.0);
+                self.block_fill = 0;
+            }
+        }
+    }
+
+    fn report(self) -> LoudnessReport {
+        let momentary = window_energies(&self.blocks, MOMENTARY_BLOCKS);
+        let short_term = window_energies(&self.blocks, SHORT_TERM_BLOCKS);
+
+        let integrated_lufs = gated(&momentary, INTEGRATED_RELATIVE_GATE)
+            .map(|energies| loudness(mean(&energies)) as f32);
+        let loudness_range_lu = gated(&short_term, RANGE_RELATIVE_GATE).map(|energies| {
+            let mut levels: Vec<f64> = energies.into_iter().map(loudness).collect();
+            levels.sort_by(f64::total_cmp);
+            (percentile(&levels, 0.95) - percentile(&levels, 0.10)) as f32
+        });
+        let short_term_max_lufs = short_term
+            .iter()
+            .copied()
+            .reduce(f64::max)
+            .map(|energy| loudness(energy) as f32);
+
+        let sample_peak = self.peaks.iter().copied().fold(0.0f32, f32::max);
+        let frames = self.frames.max(1) as f64;
+        let channels = self
+            .names
+            .into_iter()
+            .zip(self.sum_squares.iter().zip(&self.peaks))
+            .map(|(name, (sum, peak))| ChannelLevels {
+                name,
+                rms_dbfs: (10.0 * (sum / frames).log10()) as f32,
+                peak_dbfs: 20.0 * peak.log10(),
+            })
+            .collect();
+
+        LoudnessReport {
+            integrated_lufs,
+            loudness_range_lu,
+            short_term_max_lufs,
+            sample_peak_dbfs: 20.0 * sample_peak.log10(),
+            true_peak_dbtp: 20.0 * self.true_peak.peak.max(sample_peak).log10(),
+            channels,
+            clipping: self.clipping.report(),
+        }
+    }
+}
+
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Mean energy of every window of `length` blocks, sliding by one block.
+fn window_energies(blocks: &[f64], length: usize) -> Vec<f64> {
+    blocks.windows(length).map(mean).collect()
+}
+
+/// Energies above the absolute gate and `relative_gate` LU below their mean loudness,
+/// `None` if nothing is left.
+fn gated(energies: &[f64], relative_gate: f64) -> Option<Vec<f64>> {
+    let audible: Vec<f64> = energies
+        .iter()
+        .copied()
+        .filter(|&e| loudness(e) > ABSOLUTE_GATE_LUFS)
+        .collect();
+    if audible.is_empty() {
+        return None;
+    }
+    let gate = loudness(mean(&audible)) + relative_gate;
+    let gated: Vec<f64> = audible
+        .into_iter()
+        .filter(|&e| loudness(e) > gate)
+        .collect();
+    (!gated.is_empty()).then_some(gated)
+}
+
+/// Value at `fraction` of the sorted `values`.
+fn percentile(values: &[f64], fraction: f64) -> f64 {
+    let index = ((values.len() - 1) as f64 * fraction).round() as usize;
+    values[index]
+}
+
+/// Second order IIR filter of the K-weighting.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    /// Head related high shelf of the K-weighting, coefficients as in libebur128.
+    fn high_shelf(rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2],
+        }
+    }
+
+    /// High pass of the K-weighting.
+    fn high_pass(rate: f64) -> Self {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        Self {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// Finds the peak between the samples by oversampling to at least 192 kHz.
+struct TruePeakMeter {
+    /// Interpolation filter split into one set of taps per output phase.
+    phases: Vec<[f64; TRUE_PEAK_TAPS]>,
+    /// Latest samples of every channel, newest first.
+    history: Vec<[f64; TRUE_PEAK_TAPS]>,
+    /// Highest interpolated value of every channel in the latest frame.
+    frame_peaks: Vec<f32>,
+    peak: f32,
+}
+
+impl TruePeakMeter {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        let factor = match sample_rate {
+            r if r < 96000 => 4,
+            r if r < 192000 => 2,
+            _ => 1,
+        };
+        let length = TRUE_PEAK_TAPS * factor;
+        let center = (length - 1) as f64 / 2.0;
+        let taps: Vec<f64> = (0..length)
+            .map(|i| {
+                let x = (i as f64 - center) / factor as f64;
+                let sinc = if x.abs() < 1e-9 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let window = 0.5 - 0.5 * (2.0 * PI * (i as f64 + 0.5) / length as f64).cos();
+                sinc * window
+            })
+            .collect();
+        // Every phase passes DC unchanged, so full scale samples don't read as overs
+        let phases = (0..factor)
+            .map(|phase| {
+                let gain: f64 = (0..TRUE_PEAK_TAPS).map(|k| taps[k * factor + phase]).sum();
+                std::array::from_fn(|k| taps[k * factor + phase] / gain)
+            })
+            .collect();
+
+        Self {
+            phases,
+            history: vec![[0.0; TRUE_PEAK_TAPS]; channels],
+            frame_peaks: vec![0.0; channels],
+            peak: 0.0,
+        }
+    }
+
+    /// Returns the highest interpolated value of every channel.
+    fn process(&mut self, frame: &[f32]) -> &[f32] {
+        for ((history, frame_peak), &sample) in self
+            .history
+            .iter_mut()
+            .zip(self.frame_peaks.iter_mut())
+            .zip(frame)
+        {
+            history.copy_within(0..TRUE_PEAK_TAPS - 1, 1);
+            history[0] = sample as f64;
+            *frame_peak = self
+                .phases
+                .iter()
+                .map(|phase| {
+                    let value: f64 = phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+                    value.abs() as f32
+                })
+                .fold(0.0, f32::max);
+            self.peak = self.peak.max(*frame_peak);
+        }
+        &self.frame_peaks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// Interleaved sine of `amplitude` in every channel.
+    fn sine(frequency: f64, amplitude: f64, seconds: f64, channels: usize) -> Vec<f32> {
+        let frames = (seconds * SAMPLE_RATE as f64) as usize;
+        (0..frames)
+            .flat_map(|n| {
+                let value =
+                    amplitude * (2.0 * PI * frequency * n as f64 / SAMPLE_RATE as f64).sin();
+                std::iter::repeat_n(value as f32, channels)
+            })
+            .collect()
+    }
+
+    fn measure(samples: &[f32], channels: usize) -> LoudnessReport {
+        let names = vec!["FL".to_string(), "FR".to_string()];
+        let mut meter = LoudnessMeter::new(&names, channels, SAMPLE_RATE);
+        // Decoded audio arrives in chunks
+        for chunk in samples.chunks(4096 * channels) {
+            meter.process(chunk);
+        }
+        meter.report()
+    }
+
+    #[test]
+    fn stereo_tone_at_minus_20_dbfs_reads_minus_20_lufs() {
+        let report = measure(&sine(997.0, 0.1, 10.0, 2), 2);
+        let integrated = report.integrated_lufs.unwrap();
+        assert!((integrated + 20.0).abs() < 0.1, "{}", integrated);
+        assert!((report.sample_peak_dbfs + 20.0).abs() < 0.01);
+        // A sine's RMS is 3 dB below its peak
+        assert!((report.channels[0].rms_dbfs + 23.01).abs() < 0.05);
+    }
+
+    #[test]
+    fn mono_tone_reads_3_db_quieter() {
+        let report = measure(&sine(997.0, 0.1, 10.0, 1), 1);
+        let integrated = report.integrated_lufs.unwrap();
+        assert!((integrated + 23.01).abs() < 0.1, "{}", integrated);
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let mut samples = sine(997.0, 0.1, 5.0, 2);
+        samples.extend(vec![0.0; 5 * SAMPLE_RATE as usize * 2]);
+        let report = measure(&samples, 2);
+        // Without gating half silence would read -23 LUFS, only the blocks around the end
+        // of the tone count a little
+        let integrated = report.integrated_lufs.unwrap();
+        assert!((integrated + 20.0).abs() < 0.3, "{}", integrated);
+
+        let report = measure(&vec![0.0; 5 * SAMPLE_RATE as usize * 2], 2);
+        assert_eq!(report.integrated_lufs, None);
+    }
+
+    #[test]
+    fn full_scale_dc_is_not_a_true_peak_over() {
+        let mut meter = TruePeakMeter::new(1, SAMPLE_RATE);
+        // The step from silence rings, once the filter is filled every phase must read 1.0
+        for _ in 0..TRUE_PEAK_TAPS {
+            meter.process(&[1.0]);
+        }
+        for _ in 0..1000 {
+            let peak = meter.process(&[1.0])[0];
+            assert!((peak - 1.0).abs() < 1e-4, "{}", peak);
+        }
+    }
+
+    #[test]
+    fn true_peak_finds_peaks_between_samples() {
+        // A quarter of the sample rate sampled 45 degrees off its peaks
+        let mut meter = TruePeakMeter::new(1, SAMPLE_RATE);
+        let mut sample_peak = 0.0f32;
+        for n in 0..1000 {
+            let sample = (PI / 2.0 * n as f64 + PI / 4.0).sin() as f32;
+            sample_peak = sample_peak.max(sample.abs());
+            meter.process(&[sample]);
+        }
+        assert!((sample_peak - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+        assert!(meter.peak > 0.95, "{}", meter.peak);
+    }
+}
@@ -1,46 +1,63 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use eframe::egui;
-use image;
 use std::env;
-use std::process::{Command, Stdio};
 
 mod ui;
 use ui::MyApp;
+mod analyze;
+mod batch;
+mod cli;
+mod clipping;
+mod cutoff;
+mod diff;
+mod export;
 mod ffmpeg_setup;
+mod hires;
+mod instance;
 mod legend;
+mod loudness;
 mod palettes;
+mod pcm;
+mod playback;
 mod settings;
+mod spectrum;
+mod stereo;
+mod stft;
 mod utils;
+mod waveform;
 
 fn main() -> eframe::Result {
-    ffmpeg_setup::setup_ffmpeg()?;
-
-    env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
-    println!("spek-rs v{}", env!("CARGO_PKG_VERSION"));
-
     let args: Vec<String> = env::args().collect();
-    let input_path = if args.len() > 1 {
-        Some(args[1].clone())
-    } else {
-        None
-    };
+    if let Some(exit_code) = cli::run(&args) {
+        std::process::exit(exit_code);
+    }
 
-    // Handle multiple files
-    if args.len() > 2 {
-        let exe_path = env::current_exe().expect("Failed to get current executable path");
-        for path in args.iter().skip(2) {
-            if let Err(e) = Command::new(&exe_path)
-                .arg(path)
-                .stdin(Stdio::null())
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .spawn()
-            {
-                eprintln!("Failed to spawn process for {}: {}", path, e);
+    // Files of later launches are opened by the first window
+    let new_instance = args.iter().any(|arg| arg == "--new-instance");
+    let paths: Vec<String> = args
+        .iter()
+        .skip(1)
+        .filter(|arg| *arg != "--new-instance")
+        .map(|path| instance::absolute_path(path))
+        .collect();
+    let listener = if new_instance {
+        None
+    } else {
+        match instance::acquire(&paths) {
+            Some(instance::Instance::Forwarded) => {
+                println!("Opened in the running spek-rs window.");
+                return Ok(());
             }
+            Some(instance::Instance::Primary(listener)) => Some(listener),
+            None => None,
         }
-    }
+    };
+
+    ffmpeg_setup::setup_ffmpeg()?;
+
+    env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+    println!("spek-rs v{}", env!("CARGO_PKG_VERSION"));
 
     let app_settings = settings::AppSettings::load();
 
@@ -65,10 +82,10 @@ fn main() -> eframe::Result {
             viewport = viewport.with_inner_size(app_settings.window_size);
         } else {
             // spectogram + legend, spectogram + legend + menu bar
-            viewport = viewport.with_inner_size([500.0 + 180.0, 320.0 + 128.0 + 39.0]);
+            viewport = viewport.with_inner_size([500.0 + 180.0, 320.0 + 128.0 + 39.0 + 24.0]);
         }
         viewport = viewport
-            .with_min_inner_size([500.0 + 180.0, 320.0 + 128.0 + 39.0])
+            .with_min_inner_size([500.0 + 180.0, 320.0 + 128.0 + 39.0 + 24.0])
             .with_resizable(true);
 
         eframe::NativeOptions {
@@ -84,7 +101,12 @@ fn main() -> eframe::Result {
             egui_extras::install_image_loaders(&_cc.egui_ctx);
             // _cc.egui_ctx.set_theme(egui::Theme::Light);
             _cc.egui_ctx.set_theme(egui::Theme::Dark);
-            Ok(Box::new(MyApp::new(None, input_path, app_settings)))
+            let mut app = MyApp::new(None, None, app_settings);
+            app.open_files(paths);
+            if let Some(listener) = listener {
+                app.receive_files(listener.spawn(_cc.egui_ctx.clone()));
+            }
+            Ok(Box::new(app))
         }),
     )
 }
@@ -1,4 +1,5 @@
 use crate::settings::SpectrogramColorScheme;
+use image::Rgba;
 
 // Color palettes from ffmpeg/libavfilter/avf_showspectrum.c
 // https://github.com/FFmpeg/FFmpeg/blob/master/libavfilter/avf_showspectrum.c
@@ -137,6 +138,7 @@ const COOL: &[(f32, f32, f32, f32)] = &[
     (1.0, 1.0, -0.5, 0.5),
 ];
 
+#[allow(clippy::eq_op)] // Kept verbatim from ffmpeg's table
 const MAGMA: &[(f32, f32, f32, f32)] = &[
     (0.0, 0.0, 0.0, 0.0),
     (0.10, 23. / 256., (175. - 128.) / 256., (120. - 128.) / 256.),
@@ -285,6 +287,16 @@ const TERRAIN: &[(f32, f32, f32, f32)] = &[
 
 const GRAYSCALE: &[(f32, f32, f32, f32)] = &[(0.0, 0.0, 0.0, 0.0), (1.0, 1.0, 0.0, 0.0)];
 
+/// Diverging palette for level differences: blue below 0.5, black at 0.5 and red above,
+/// getting brighter the larger the difference.
+pub const DIVERGING: &[(f32, f32, f32, f32)] = &[
+    (0.0, 0.81976, 0.10171, -0.10920),
+    (0.25, 0.39122, 0.28823, -0.19513),
+    (0.5, 0.0, 0.0, 0.0),
+    (0.75, 0.43976, -0.20391, 0.32967),
+    (1.0, 0.89551, -0.17341, 0.07453),
+];
+
 pub fn get_palette(scheme: SpectrogramColorScheme) -> &'static [(f32, f32, f32, f32)] {
     match scheme {
         SpectrogramColorScheme::Intensity => INTENSITY,
@@ -305,3 +317,73 @@ pub fn get_palette(scheme: SpectrogramColorScheme) -> &'static [(f32, f32, f32,
         SpectrogramColorScheme::Channel => GRAYSCALE,
     }
 }
+
+/// Returns the color of intensity `a` (0.0 - 1.0) in the given palette.
+pub fn color_at(palette: &[(f32, f32, f32, f32)], a: f32, saturation: f32) -> Rgba<u8> {
+    // Find the segment in the palette that `a` falls into
+    let mut end_idx = 1;
+    while end_idx < palette.len() - 1 && palette[end_idx].0 < a {
+        end_idx += 1;
+    }
+    let start_idx = end_idx - 1;
+
+    let (start_a, start_y, start_u, start_v) = palette[start_idx];
+    let (end_a, end_y, end_u, end_v) = palette[end_idx];
+
+    // Calculate interpolation factor within the segment
+    let lerp_frac = if (end_a - start_a).abs() < f32::EPSILON {
+        0.0
+    } else {
+        ((a - start_a) / (end_a - start_a)).clamp(0.0, 1.0)
+    };
+
+    // Interpolate Y, U, V
+    let y_interp = start_y * (1.0 - lerp_frac) + end_y * lerp_frac;
+    let u_interp = start_u * (1.0 - lerp_frac) + end_u * lerp_frac;
+    let v_interp = start_v * (1.0 - lerp_frac) + end_v * lerp_frac;
+
+    // Construct 8-bit YUV pixel, applying saturation, to match ffmpeg's internal pipeline
+    let y_8bit = y_interp * 255.0;
+    let u_8bit = 128.0 + u_interp * 255.0 * saturation;
+    let v_8bit = 128.0 + v_interp * 255.0 * saturation;
+
+    // Clip YUV components before conversion, which is crucial for high saturation
+    yuv8bit_to_rgb(
+        y_8bit.clamp(0.0, 255.0),
+        u_8bit.clamp(0.0, 255.0),
+        v_8bit.clamp(0.0, 255.0),
+    )
+}
+
+/// Finds the intensity (0.0 - 1.0) whose palette color is closest to `color`.
+pub fn value_of_color(palette: &[(f32, f32, f32, f32)], saturation: f32, color: [u8; 3]) -> f32 {
+    const STEPS: usize = 256;
+    let distance =
+        |c: Rgba<u8>| -> i32 { (0..3).map(|i| (c[i] as i32 - color[i] as i32).pow(2)).sum() };
+
+    let mut best = (0, i32::MAX);
+    for step in 0..=STEPS {
+        let d = distance(color_at(palette, step as f32 / STEPS as f32, saturation));
+        if d < best.1 {
+            best = (step, d);
+        }
+    }
+    best.0 as f32 / STEPS as f32
+}
+
+fn yuv8bit_to_rgb(y: f32, u: f32, v: f32) -> Rgba<u8> {
+    // Formula for full-range YUV [0,255] to RGB [0,255]
+    let u = u - 128.0;
+    let v = v - 128.0;
+
+    let r = y + 1.402 * v;
+    let g = y - 0.344136 * u - 0.714136 * v;
+    let b = y + 1.772 * u;
+
+    Rgba([
+        r.clamp(0.0, 255.0) as u8,
+        g.clamp(0.0, 255.0) as u8,
+        b.clamp(0.0, 255.0) as u8,
+        255,
+    ])
+}
@@ -0,0 +1,176 @@
+use ffmpeg_sidecar::child::FfmpegChild;
+use std::io::{ErrorKind, Read};
+use std::process::ChildStdout;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::settings::AudioSource;
+use crate::utils;
+
+/// Streams decoded audio from ffmpeg as interleaved 32-bit float samples.
+pub struct PcmDecoder {
+    child: FfmpegChild,
+    stdout: ChildStdout,
+    pub channels: usize,
+    pending: Vec<u8>,
+}
+
+impl PcmDecoder {
+    /// Starts decoding the selected stream and channels of `input_path`, mixed to `channels`,
+    /// optionally only the `time_range` between the given start and end seconds.
+    pub fn spawn(
+        input_path: &str,
+        source: &AudioSource,
+        channels: u32,
+        sample_rate: u32,
+        time_range: Option<(f64, f64)>,
+    ) -> Option<Self> {
+        let mut cmd_builder = utils::ffmpeg_command();
+        cmd_builder.args(["-hide_banner", "-loglevel", "error"]);
+        if let Some((start, end)) = time_range {
+            cmd_builder.args([
+                "-ss",
+                &format!("{:.6}", start),
+                "-t",
+                &format!("{:.6}", end - start),
+            ]);
+        }
+        cmd_builder.args(["-i", input_path, "-map", &source.stream_specifier()]);
+        if let Some(pan) = source.pan_filter() {
+            cmd_builder.args(["-af", &pan]);
+        }
+        cmd_builder.args([
+            "-ac",
+            &channels.to_string(),
+            "-ar",
+            &sample_rate.to_string(),
+            "-f",
+            "f32le",
+            "-acodec",
+            "pcm_f32le",
+            "-",
+        ]);
+
+        let mut child = match cmd_builder.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("Failed to spawn ffmpeg: {}", e);
+                return None;
+            }
+        };
+        let stdout = child.take_stdout()?;
+
+        Some(Self {
+            child,
+            stdout,
+            channels: channels.max(1) as usize,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Appends the next chunk of interleaved samples to `out`.
+    /// Returns the number of frames read, 0 means the end of the stream.
+    pub fn read(&mut self, out: &mut Vec<f32>) -> std::io::Result<usize> {
+        let mut read_buf = [0u8; 65536];
+        let frame_bytes = 4 * self.channels;
+
+        loop {
+            match self.stdout.read(&mut read_buf) {
+                Ok(0) => return Ok(0),
+                Ok(n) => self.pending.extend_from_slice(&read_buf[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+
+            let frames = self.pending.len() / frame_bytes;
+            if frames == 0 {
+                continue;
+            }
+
+            let used = frames * frame_bytes;
+            out.extend(
+                self.pending[..used]
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            );
+            self.pending.drain(..used);
+            return Ok(frames);
+        }
+    }
+
+    /// Decodes the whole stream, passing every chunk of interleaved samples to `on_chunk`.
+    /// Returns `false` if decoding failed or was cancelled.
+    pub fn for_each_chunk(
+        mut self,
+        cancel_token: &AtomicBool,
+        mut on_chunk: impl FnMut(&[f32]),
+    ) -> bool {
+        let mut samples = Vec::new();
+        loop {
+            if cancel_token.load(Ordering::Relaxed) {
+                self.kill();
+                return false;
+            }
+            samples.clear();
+            match self.read(&mut samples) {
+                Ok(0) => break,
+                Ok(_) => on_chunk(&samples),
+                Err(e) => {
+                    eprintln!("Failed to read ffmpeg stdout: {}", e);
+                    self.kill();
+                    return false;
+                }
+            }
+        }
+        self.finish()
+    }
+
+    /// Decodes the whole stream, passing every frame to `on_frame` along with its column
+    /// among `columns` evenly spaced slices of the `total` expected frames.
+    /// Returns `false` if decoding failed or was cancelled.
+    pub fn for_each_frame_in_columns(
+        self,
+        total: usize,
+        columns: usize,
+        cancel_token: &AtomicBool,
+        mut on_frame: impl FnMut(usize, &[f32]),
+    ) -> bool {
+        let channels = self.channels;
+        let total = total.max(1);
+        let mut frame = 0;
+        self.for_each_chunk(cancel_token, |samples| {
+            for chunk in samples.chunks_exact(channels) {
+                on_frame((frame * columns / total).min(columns - 1), chunk);
+                frame += 1;
+            }
+        })
+    }
+
+    /// Stops ffmpeg if it's still running.
+    pub fn kill(&mut self) {
+        if let Err(e) = self.child.kill() {
+            eprintln!("Failed to kill ffmpeg process: {}", e);
+        }
+        let _ = self.child.wait();
+    }
+
+    /// Waits for ffmpeg to exit and reports errors, if any.
+    pub fn finish(mut self) -> bool {
+        match self.child.wait() {
+            Ok(status) if status.success() => true,
+            Ok(_) => {
+                let mut stderr_output = String::new();
+                if let Some(mut stderr) = self.child.take_stderr() {
+                    if stderr.read_to_string(&mut stderr_output).is_ok() {
+                        eprintln!("ffmpeg error:\n{}", stderr_output);
+                    }
+                }
+                eprintln!("ffmpeg process exited with non-zero status");
+                false
+            }
+            Err(e) => {
+                eprintln!("Failed to wait for ffmpeg process: {}", e);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,658 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::pcm::PcmDecoder;
+use crate::settings::AudioSource;
+use crate::utils::{self, AudioInfo};
+
+/// How far ahead of the playhead audio is written to the sink, in seconds.
+const LEAD: f64 = 0.1;
+/// Length of the blocks written to the sink, in seconds.
+const BLOCK: f64 = 0.02;
+/// Files with more channels are downmixed to stereo for playback.
+const MAX_CHANNELS: u32 = 2;
+
+/// Destination of the played audio.
+pub trait AudioSink: Send {
+    /// Called before the first samples of every playback.
+    fn open(&mut self, channels: usize, sample_rate: u32) -> Result<(), String>;
+    /// Plays interleaved samples.
+    fn write(&mut self, samples: &[f32]) -> Result<(), String>;
+    /// Drops the queued audio, called on pause, seek and at the end of the file.
+    fn stop(&mut self);
+}
+
+/// Discards the audio, the playhead still moves in real time.
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn open(&mut self, _channels: usize, _sample_rate: u32) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn write(&mut self, _samples: &[f32]) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn stop(&mut self) {}
+}
+
+/// Records everything that was played into a 32-bit float WAV file.
+pub struct WavSink {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    data_len: u32,
+}
+
+impl WavSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            writer: None,
+            data_len: 0,
+        }
+    }
+
+    /// Writes the final sizes into the header.
+    fn update_header(&mut self) -> std::io::Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        writer.seek(SeekFrom::Start(4))?;
+        writer.write_all(&(36 + self.data_len).to_le_bytes())?;
+        writer.seek(SeekFrom::Start(40))?;
+        writer.write_all(&self.data_len.to_le_bytes())?;
+        writer.seek(SeekFrom::End(0))?;
+        writer.flush()
+    }
+}
+
+impl AudioSink for WavSink {
+    fn open(&mut self, channels: usize, sample_rate: u32) -> Result<(), String> {
+        // A single file is kept for all playbacks, seeking just appends to it
+        if self.writer.is_some() {
+            return Ok(());
+        }
+        let file = File::create(&self.path)
+            .map_err(|e| format!("failed to create '{}': {}", self.path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+
+        let channels = channels as u16;
+        let block_align = channels * 4;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&36u32.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&3u16.to_le_bytes()); // IEEE float
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&32u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        writer
+            .write_all(&header)
+            .map_err(|e| format!("failed to write '{}': {}", self.path.display(), e))?;
+
+        self.writer = Some(writer);
+        self.data_len = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Err("WAV sink is not open".to_string());
+        };
+        for sample in samples {
+            writer
+                .write_all(&sample.to_le_bytes())
+                .map_err(|e| format!("failed to write '{}': {}", self.path.display(), e))?;
+        }
+        self.data_len += samples.len() as u32 * 4;
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Err(e) = self.update_header() {
+            eprintln!("Failed to write '{}': {}", self.path.display(), e);
+        }
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Plays the audio on the default output device through ffmpeg,
+/// or ffplay on Windows where ffmpeg has no audio output device.
+pub struct DeviceSink {
+    format: Option<(usize, u32)>,
+    child: Option<(Child, ChildStdin)>,
+}
+
+impl DeviceSink {
+    pub fn new() -> Self {
+        Self {
+            format: None,
+            child: None,
+        }
+    }
+
+    fn spawn(channels: usize, sample_rate: u32) -> Result<(Child, ChildStdin), String> {
+        let mut command = output_command(channels, sample_rate);
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            const CREATE_NO_WINDOW: u32 = 0x08000000;
+            command.creation_flags(CREATE_NO_WINDOW);
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("failed to start audio output: {}", e))?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| "failed to open audio output".to_string())?;
+        Ok((child, stdin))
+    }
+}
+
+impl Default for DeviceSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioSink for DeviceSink {
+    fn open(&mut self, channels: usize, sample_rate: u32) -> Result<(), String> {
+        self.format = Some((channels, sample_rate));
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        if self.child.is_none() {
+            let (channels, sample_rate) = self
+                .format
+                .ok_or_else(|| "audio output is not open".to_string())?;
+            self.child = Some(Self::spawn(channels, sample_rate)?);
+        }
+        let Some((_, stdin)) = self.child.as_mut() else {
+            return Ok(());
+        };
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        stdin
+            .write_all(&bytes)
+            .map_err(|e| format!("audio output stopped: {}", e))
+    }
+
+    fn stop(&mut self) {
+        // The queued audio can't be taken back from the pipe, restart the output instead
+        if let Some((mut child, stdin)) = self.child.take() {
+            drop(stdin);
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Drop for DeviceSink {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(not(windows))]
+fn output_command(channels: usize, sample_rate: u32) -> Command {
+    #[cfg(target_os = "macos")]
+    let device = ["-f", "audiotoolbox", "-"];
+    #[cfg(not(target_os = "macos"))]
+    let device = ["-f", "pulse", "spek-rs"];
+
+    let mut command = Command::new(utils::ffmpeg_command().as_inner().get_program());
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-nostdin"])
+        .args(["-f", "f32le", "-ar", &sample_rate.to_string()])
+        .args(["-ac", &channels.to_string(), "-i", "-"])
+        .args(device);
+    command
+}
+
+#[cfg(windows)]
+fn output_command(channels: usize, sample_rate: u32) -> Command {
+    let ffplay = utils::get_ffmpeg_paths().directory.join("ffplay.exe");
+    let mut command = Command::new(if ffplay.exists() {
+        ffplay.into_os_string()
+    } else {
+        "ffplay".into()
+    });
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-nodisp", "-autoexit"])
+        .args(["-f", "f32le", "-sample_rate", &sample_rate.to_string()])
+        .args(["-ch_layout", &format!("{}c", channels), "-i", "-"]);
+    command
+}
+
+/// Returns the sink selected by the `SPEK_AUDIO_OUTPUT` environment variable:
+/// `null`, a path to a `.wav` file, or the output device when it's not set.
+pub fn default_sink() -> Box<dyn AudioSink> {
+    match std::env::var("SPEK_AUDIO_OUTPUT") {
+        Ok(output) if output == "null" => Box::new(NullSink),
+        Ok(output) if output.to_lowercase().ends_with(".wav") => Box::new(WavSink::new(output)),
+        _ => Box::new(DeviceSink::new()),
+    }
+}
+
+/// Marks a playback whose first samples were not written yet.
+const NOT_STARTED: u64 = u64::MAX;
+
+/// State of a running playback, shared with its thread.
+struct Playback {
+    requested: Instant,
+    /// Nanoseconds between `requested` and the first samples reaching the sink.
+    delay: Arc<AtomicU64>,
+    from: f64,
+    finished: Arc<AtomicBool>,
+}
+
+impl Playback {
+    fn elapsed(&self) -> f64 {
+        match self.delay.load(Ordering::Relaxed) {
+            NOT_STARTED => 0.0,
+            delay => self
+                .requested
+                .elapsed()
+                .saturating_sub(Duration::from_nanos(delay))
+                .as_secs_f64(),
+        }
+    }
+}
+
+/// Plays a file from any position into an `AudioSink`.
+/// The playhead follows the wall clock and the audio is paced to it.
+pub struct Player {
+    input_path: String,
+    source: AudioSource,
+    channels: u32,
+    sample_rate: u32,
+    duration: f64,
+    sink: Arc<Mutex<Box<dyn AudioSink>>>,
+    /// Incremented to stop the running playback thread.
+    generation: Arc<AtomicU64>,
+    playback: Option<Playback>,
+    position: f64,
+}
+
+impl Player {
+    pub fn new(input_path: &str, audio_info: &AudioInfo, sink: Box<dyn AudioSink>) -> Self {
+        // Mid and side are only for the analysis, the selected channels are played as they are
+        let mut source = audio_info.source.clone();
+        let mut channels = audio_info.channels;
+        if source.mid_side {
+            source = source.without_mid_side();
+            channels = source.channels.len().max(2) as u32;
+        }
+        Self {
+            input_path: input_path.to_string(),
+            source,
+            channels: channels.clamp(1, MAX_CHANNELS),
+            sample_rate: audio_info.sample_rate,
+            duration: audio_info.duration,
+            sink: Arc::new(Mutex::new(sink)),
+            generation: Arc::new(AtomicU64::new(0)),
+            playback: None,
+            position: 0.0,
+        }
+    }
+
+    /// Returns true while the audio is playing, it stops by itself at the end of the file.
+    pub fn is_playing(&mut self) -> bool {
+        if let Some(playback) = &self.playback {
+            if playback.finished.load(Ordering::Relaxed) {
+                self.playback = None;
+                self.position = self.duration;
+            }
+        }
+        self.playback.is_some()
+    }
+
+    /// Current playhead position in seconds.
+    pub fn position(&self) -> f64 {
+        match &self.playback {
+            Some(playback) => (playback.from + playback.elapsed()).min(self.duration),
+            None => self.position,
+        }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    pub fn play(&mut self) {
+        if self.is_playing() {
+            return;
+        }
+        let from = if self.position >= self.duration {
+            0.0
+        } else {
+            self.position
+        };
+        let requested = Instant::now();
+        let delay = Arc::new(AtomicU64::new(NOT_STARTED));
+        let finished = Arc::new(AtomicBool::new(false));
+        let id = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let job = PlaybackJob {
+            input_path: self.input_path.clone(),
+            source: self.source.clone(),
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            from,
+            to: self.duration,
+            sink: self.sink.clone(),
+            generation: self.generation.clone(),
+            id,
+            requested,
+            delay: delay.clone(),
+            finished: finished.clone(),
+        };
+        thread::spawn(move || job.run());
+
+        self.playback = Some(Playback {
+            requested,
+            delay,
+            from,
+            finished,
+        });
+    }
+
+    pub fn pause(&mut self) {
+        if self.playback.is_some() {
+            self.position = self.position();
+            self.playback = None;
+            self.stop_thread();
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        if self.is_playing() {
+            self.pause();
+        } else {
+            self.play();
+        }
+    }
+
+    /// Moves the playhead to `time` seconds, playback continues from there if it was running.
+    pub fn seek(&mut self, time: f64) {
+        let was_playing = self.is_playing();
+        self.pause();
+        self.position = time.clamp(0.0, self.duration);
+        if was_playing {
+            self.play();
+        }
+    }
+
+    fn stop_thread(&mut self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        match self.sink.lock() {
+            Ok(mut sink) => sink.stop(),
+            Err(e) => eprintln!("Failed to stop audio output: {}", e),
+        }
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+/// Decodes one playback and writes it to the sink until it's stopped or reaches the end.
+struct PlaybackJob {
+    input_path: String,
+    source: AudioSource,
+    channels: u32,
+    sample_rate: u32,
+    from: f64,
+    to: f64,
+    sink: Arc<Mutex<Box<dyn AudioSink>>>,
+    generation: Arc<AtomicU64>,
+    id: u64,
+    requested: Instant,
+    delay: Arc<AtomicU64>,
+    finished: Arc<AtomicBool>,
+}
+
+impl PlaybackJob {
+    fn is_current(&self) -> bool {
+        self.generation.load(Ordering::Relaxed) == self.id
+    }
+
+    fn run(self) {
+        let Some(mut decoder) = PcmDecoder::spawn(
+            &self.input_path,
+            &self.source,
+            self.channels,
+            self.sample_rate,
+            Some((self.from, self.to)),
+        ) else {
+            self.finished.store(true, Ordering::Relaxed);
+            return;
+        };
+
+        if let Ok(mut sink) = self.sink.lock() {
+            if let Err(e) = sink.open(self.channels as usize, self.sample_rate) {
+                eprintln!("Failed to play audio: {}", e);
+                self.finished.store(true, Ordering::Relaxed);
+                decoder.kill();
+                return;
+            }
+        }
+
+        let channels = self.channels as usize;
+        let block_samples = ((self.sample_rate as f64 * BLOCK) as usize).max(1) * channels;
+        let mut started: Option<Instant> = None;
+        let mut written_frames = 0usize;
+        let mut samples = Vec::new();
+        let mut stopped = false;
+
+        'decode: loop {
+            samples.clear();
+            match decoder.read(&mut samples) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Failed to read decoded audio: {}", e);
+                    break;
+                }
+            }
+
+            for block in samples.chunks(block_samples) {
+                // Stay a little ahead of the playhead
+                loop {
+                    if !self.is_current() {
+                        stopped = true;
+                        break 'decode;
+                    }
+                    let Some(started) = started else {
+                        break;
+                    };
+                    let ahead = written_frames as f64 / self.sample_rate as f64
+                        - started.elapsed().as_secs_f64();
+                    if ahead <= LEAD {
+                        break;
+                    }
+                    thread::sleep(Duration::from_secs_f64((ahead - LEAD).min(BLOCK)));
+                }
+
+                let Ok(mut sink) = self.sink.lock() else {
+                    break 'decode;
+                };
+                // Checked again under the lock so a stopped playback can't write after `stop`
+                if !self.is_current() {
+                    stopped = true;
+                    break 'decode;
+                }
+                if let Err(e) = sink.write(block) {
+                    eprintln!("Failed to play audio: {}", e);
+                    break 'decode;
+                }
+                if started.is_none() {
+                    // The playhead starts moving with the first samples
+                    started = Some(Instant::now());
+                    self.delay.store(
+                        self.requested.elapsed().as_nanos() as u64,
+                        Ordering::Relaxed,
+                    );
+                }
+                written_frames += block.len() / channels;
+            }
+        }
+
+        if stopped {
+            decoder.kill();
+            return;
+        }
+        decoder.finish();
+
+        // Let the queued audio play out before reporting the end
+        let remaining = written_frames as f64 / self.sample_rate as f64
+            - started.map_or(0.0, |started| started.elapsed().as_secs_f64());
+        if remaining > 0.0 {
+            thread::sleep(Duration::from_secs_f64(remaining));
+        }
+        if let Ok(mut sink) = self.sink.lock() {
+            if self.is_current() {
+                sink.stop();
+            }
+        }
+        self.finished.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audio_info(duration: f64) -> AudioInfo {
+        AudioInfo {
+            duration,
+            sample_rate: 48000,
+            format: "pcm_f32le".to_string(),
+            bits_per_sample: 32,
+            channels: 2,
+            channel_names: vec!["FL".to_string(), "FR".to_string()],
+            source: AudioSource::default(),
+        }
+    }
+
+    /// Playback that was requested `ago` seconds ago and started after `delay` seconds.
+    fn playback(from: f64, ago: f64, delay: Option<f64>) -> Playback {
+        Playback {
+            requested: Instant::now() - Duration::from_secs_f64(ago),
+            delay: Arc::new(AtomicU64::new(
+                delay.map_or(NOT_STARTED, |delay| (delay * 1e9) as u64),
+            )),
+            from,
+            finished: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn wav_sink_writes_sizes_into_header_on_stop() {
+        let path =
+            std::env::temp_dir().join(format!("spek-rs-wav-sink-{}.wav", std::process::id()));
+        let mut sink = WavSink::new(&path);
+        assert!(sink.write(&[0.0]).is_err());
+
+        sink.open(2, 44100).unwrap();
+        sink.write(&[0.5; 100]).unwrap();
+        sink.stop();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 400);
+        assert_eq!(read_u32(&bytes, 4), 36 + 400);
+        assert_eq!(read_u32(&bytes, 24), 44100);
+        assert_eq!(read_u32(&bytes, 40), 400);
+        assert_eq!(&bytes[22..24], &2u16.to_le_bytes());
+
+        // Later playbacks append to the same file
+        sink.open(2, 44100).unwrap();
+        sink.write(&[0.25; 50]).unwrap();
+        sink.stop();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 600);
+        assert_eq!(read_u32(&bytes, 4), 36 + 600);
+        assert_eq!(read_u32(&bytes, 40), 600);
+
+        drop(sink);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn seek_clamps_to_the_file() {
+        let mut player = Player::new("test.wav", &audio_info(10.0), Box::new(NullSink));
+        player.seek(4.0);
+        assert!(!player.is_playing());
+        assert_eq!(player.position(), 4.0);
+        player.seek(-1.0);
+        assert_eq!(player.position(), 0.0);
+        player.seek(20.0);
+        assert_eq!(player.position(), 10.0);
+    }
+
+    #[test]
+    fn playhead_waits_for_the_first_samples() {
+        let mut player = Player::new("test.wav", &audio_info(10.0), Box::new(NullSink));
+        player.playback = Some(playback(3.0, 2.0, None));
+        assert_eq!(player.position(), 3.0);
+
+        // Requested 2 s ago, the audio started 0.5 s after that
+        player.playback = Some(playback(3.0, 2.0, Some(0.5)));
+        assert!(
+            (player.position() - 4.5).abs() < 0.1,
+            "{}",
+            player.position()
+        );
+
+        player.pause();
+        assert!(player.playback.is_none());
+        let paused = player.position();
+        assert!((paused - 4.5).abs() < 0.1, "{}", paused);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(player.position(), paused);
+    }
+
+    #[test]
+    fn playhead_stops_at_the_end() {
+        let mut player = Player::new("test.wav", &audio_info(10.0), Box::new(NullSink));
+        player.playback = Some(playback(9.0, 5.0, Some(0.0)));
+        assert_eq!(player.position(), 10.0);
+
+        player
+            .playback
+            .as_ref()
+            .unwrap()
+            .finished
+            .store(true, Ordering::Relaxed);
+        assert!(!player.is_playing());
+        assert_eq!(player.position(), 10.0);
+    }
+}
@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 
+use crate::export::DEFAULT_JPEG_QUALITY;
+
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum SpectrogramColorScheme {
     Intensity,
@@ -188,6 +191,407 @@ impl std::fmt::Display for SpectrogramScale {
     }
 }
 
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum SpectrogramEngine {
+    Ffmpeg,
+    Native,
+}
+
+impl SpectrogramEngine {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpectrogramEngine::Ffmpeg => "ffmpeg",
+            SpectrogramEngine::Native => "native",
+        }
+    }
+    pub const VALUES: [Self; 2] = [Self::Ffmpeg, Self::Native];
+}
+
+impl std::fmt::Display for SpectrogramEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpectrogramEngine::Ffmpeg => write!(f, "FFmpeg"),
+            SpectrogramEngine::Native => write!(f, "Native"),
+        }
+    }
+}
+
+/// Spacing of the frequency axis.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum FrequencyScale {
+    Linear,
+    Log,
+    Mel,
+    Bark,
+}
+
+impl FrequencyScale {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrequencyScale::Linear => "lin",
+            FrequencyScale::Log => "log",
+            FrequencyScale::Mel => "mel",
+            FrequencyScale::Bark => "bark",
+        }
+    }
+    pub const VALUES: [Self; 4] = [Self::Linear, Self::Log, Self::Mel, Self::Bark];
+
+    /// showspectrum(pic) only knows the linear and log scales.
+    pub fn ffmpeg_supported(&self) -> bool {
+        matches!(self, FrequencyScale::Linear | FrequencyScale::Log)
+    }
+
+    /// showspectrum(pic) option for the scale, empty for ffmpeg's default linear one.
+    pub fn ffmpeg_filter_args(&self) -> &'static str {
+        match self {
+            FrequencyScale::Log => ":fscale=log",
+            _ => "",
+        }
+    }
+
+    /// Position of `freq` on the mel or bark scale, unchanged for the others.
+    fn warp(&self, freq: f32) -> f32 {
+        match self {
+            FrequencyScale::Mel => 2595.0 * (1.0 + freq / 700.0).log10(),
+            // Traunmüller's approximation
+            FrequencyScale::Bark => 26.81 * freq / (1960.0 + freq) - 0.53,
+            _ => freq,
+        }
+    }
+
+    fn unwarp(&self, value: f32) -> f32 {
+        match self {
+            FrequencyScale::Mel => 700.0 * (10f32.powf(value / 2595.0) - 1.0),
+            FrequencyScale::Bark => 1960.0 * (value + 0.53) / (26.28 - value),
+            _ => value,
+        }
+    }
+}
+
+impl std::fmt::Display for FrequencyScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrequencyScale::Linear => write!(f, "Linear"),
+            FrequencyScale::Log => write!(f, "Log"),
+            FrequencyScale::Mel => write!(f, "Mel"),
+            FrequencyScale::Bark => write!(f, "Bark"),
+        }
+    }
+}
+
+/// Maps frequencies of the visible range to their height in the spectrogram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrequencyAxis {
+    pub scale: FrequencyScale,
+    /// Lowest and highest frequency in Hz.
+    pub range: (f32, f32),
+}
+
+impl FrequencyAxis {
+    /// Lowest frequency of the log scale above the bottom of the range, same as ffmpeg's.
+    const LOG_MIN_FREQ: f32 = 20.0;
+
+    /// Height of `freq`, 0.0 at the bottom of the range and 1.0 at the top.
+    pub fn fraction_of(&self, freq: f32) -> f32 {
+        let (min, max) = self.range;
+        match self.scale {
+            FrequencyScale::Linear => (freq - min) / (max - min).max(f32::EPSILON),
+            FrequencyScale::Log => {
+                let span = ((max - min) / Self::LOG_MIN_FREQ).max(1.0 + f32::EPSILON);
+                ((freq - min) / Self::LOG_MIN_FREQ).max(f32::EPSILON).ln() / span.ln()
+            }
+            FrequencyScale::Mel | FrequencyScale::Bark => {
+                let (low, high) = (self.scale.warp(min), self.scale.warp(max));
+                (self.scale.warp(freq) - low) / (high - low).max(f32::EPSILON)
+            }
+        }
+    }
+
+    /// Frequency at `fraction` of the height, inverse of `fraction_of`.
+    pub fn frequency_at(&self, fraction: f32) -> f32 {
+        let (min, max) = self.range;
+        match self.scale {
+            FrequencyScale::Linear => min + fraction * (max - min),
+            // Like ffmpeg's fscale=log, which spans 20 Hz to the width of the range
+            FrequencyScale::Log => {
+                let span = ((max - min) / Self::LOG_MIN_FREQ).max(1.0);
+                min + Self::LOG_MIN_FREQ * span.powf(fraction)
+            }
+            FrequencyScale::Mel | FrequencyScale::Bark => {
+                let (low, high) = (self.scale.warp(min), self.scale.warp(max));
+                self.scale.unwarp(low + fraction * (high - low))
+            }
+        }
+    }
+}
+
+/// Arrangement of the spectrograms in the compare view.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum CompareLayout {
+    SideBySide,
+    Stacked,
+}
+
+impl CompareLayout {
+    pub const VALUES: [Self; 2] = [Self::SideBySide, Self::Stacked];
+}
+
+impl std::fmt::Display for CompareLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompareLayout::SideBySide => write!(f, "Side by side"),
+            CompareLayout::Stacked => write!(f, "Stacked"),
+        }
+    }
+}
+
+/// What the spectrogram of two files shows.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum DifferenceMode {
+    /// Level of the first file relative to the second one, per bin in dB.
+    Delta,
+    /// Spectrogram of the first file minus the second one.
+    Signal,
+}
+
+impl DifferenceMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DifferenceMode::Delta => "delta",
+            DifferenceMode::Signal => "signal",
+        }
+    }
+    pub const VALUES: [Self; 2] = [Self::Delta, Self::Signal];
+}
+
+impl std::fmt::Display for DifferenceMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DifferenceMode::Delta => write!(f, "dB delta"),
+            DifferenceMode::Signal => write!(f, "Difference signal"),
+        }
+    }
+}
+
+/// Second file of a difference spectrogram, compared against the rendered one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub path: String,
+    pub mode: DifferenceMode,
+    /// Find `offset` by cross-correlating both files.
+    pub align: bool,
+    /// Seconds the audio of `path` is delayed against the first file, skipped when decoding.
+    pub offset: f64,
+    /// Largest delta in dB shown by the palette, in both directions.
+    pub range_db: f32,
+}
+
+impl Difference {
+    pub const DEFAULT_RANGE_DB: f32 = 30.0;
+
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            mode: DifferenceMode::Delta,
+            align: false,
+            offset: 0.0,
+            range_db: Self::DEFAULT_RANGE_DB,
+        }
+    }
+}
+
+/// Visible part of the file, `None` means the whole range.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ViewRange {
+    /// Start and end in seconds.
+    pub time: Option<(f64, f64)>,
+    /// Lowest and highest frequency in Hz.
+    pub frequency: Option<(f32, f32)>,
+}
+
+impl ViewRange {
+    pub fn is_full(&self) -> bool {
+        self.time.is_none() && self.frequency.is_none()
+    }
+
+    pub fn time_range(&self, duration: f64) -> (f64, f64) {
+        match self.time {
+            Some((start, end)) => {
+                let start = start.clamp(0.0, duration);
+                (start, end.clamp(start, duration))
+            }
+            None => (0.0, duration),
+        }
+    }
+
+    pub fn frequency_range(&self, nyquist: f32) -> (f32, f32) {
+        match self.frequency {
+            Some((min, max)) => {
+                let min = min.clamp(0.0, nyquist);
+                (min, max.clamp(min, nyquist))
+            }
+            None => (0.0, nyquist),
+        }
+    }
+
+    /// This view moved inside a file of `duration` seconds and `nyquist` Hz, keeping the
+    /// spans of the zoomed ranges unless the file is shorter or narrower than them.
+    pub fn fitted(&self, duration: f64, nyquist: f32) -> ViewRange {
+        ViewRange {
+            time: self.time.map(|range| fit_range(range, duration)),
+            frequency: self.frequency.map(|(min, max)| {
+                let (min, max) = fit_range((min as f64, max as f64), nyquist as f64);
+                (min as f32, max as f32)
+            }),
+        }
+    }
+
+    /// ffmpeg input options that seek to the visible time range of a file of `duration` seconds.
+    pub fn ffmpeg_input_args(&self, duration: f64) -> Vec<String> {
+        if self.time.is_none() {
+            return Vec::new();
+        }
+        let (start, end) = self.time_range(duration);
+        vec![
+            "-ss".to_string(),
+            format!("{:.6}", start),
+            "-t".to_string(),
+            format!("{:.6}", end - start),
+        ]
+    }
+
+    /// showspectrum(pic) options for the visible frequency range up to `nyquist`.
+    pub fn ffmpeg_filter_args(&self, nyquist: f32) -> String {
+        if self.frequency.is_none() {
+            return String::new();
+        }
+        let (min, max) = self.frequency_range(nyquist);
+        format!(":start={:.0}:stop={:.0}", min, max)
+    }
+}
+
+/// Moves `range` inside `0..=limit`, it's only shortened if it doesn't fit.
+fn fit_range((start, end): (f64, f64), limit: f64) -> (f64, f64) {
+    let limit = limit.max(0.0);
+    let span = (end - start).clamp(0.0, limit);
+    let start = start.clamp(0.0, limit - span);
+    (start, start + span)
+}
+
+/// Audio stream of the file and its channels that are analyzed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioSource {
+    /// Zero based index among the audio streams of the file.
+    pub stream: usize,
+    /// Zero based channels of the stream to use, all of them when empty.
+    pub channels: Vec<usize>,
+    /// Analyze the mid (L+R) and side (L-R) signals of the first two channels instead.
+    pub mid_side: bool,
+}
+
+impl AudioSource {
+    /// ffmpeg stream specifier of the selected stream.
+    pub fn stream_specifier(&self) -> String {
+        format!("0:a:{}", self.stream)
+    }
+
+    /// ffmpeg `pan` filter that keeps only the selected channels or turns them into mid and
+    /// side, `None` when all channels are used as they are.
+    pub fn pan_filter(&self) -> Option<String> {
+        if self.mid_side {
+            let (left, right) = match self.channels.as_slice() {
+                [] => (0, 1),
+                [left, right, ..] => (*left, *right),
+                [channel] => (*channel, *channel),
+            };
+            return Some(format!(
+                "pan=stereo|c0=0.5*c{l}+0.5*c{r}|c1=0.5*c{l}-0.5*c{r}",
+                l = left,
+                r = right
+            ));
+        }
+        if self.channels.is_empty() {
+            return None;
+        }
+        let mut filter = format!("pan={}c", self.channels.len());
+        for (output, input) in self.channels.iter().enumerate() {
+            filter.push_str(&format!("|c{}=c{}", output, input));
+        }
+        Some(filter)
+    }
+
+    /// Start of an ffmpeg filter graph reading the selected stream and channels,
+    /// the next filter is appended directly after it.
+    pub fn filter_input(&self) -> String {
+        match self.pan_filter() {
+            Some(pan) => format!("[{}]{},", self.stream_specifier(), pan),
+            None => format!("[{}]", self.stream_specifier()),
+        }
+    }
+
+    /// Returns this source with the selected channels as they are instead of mid and side.
+    pub fn without_mid_side(&self) -> AudioSource {
+        AudioSource {
+            mid_side: false,
+            ..self.clone()
+        }
+    }
+
+    /// Returns this source without `channel` of a stream with `total` channels,
+    /// `None` if no channel would be left.
+    pub fn without_channel(&self, channel: usize, total: usize) -> Option<AudioSource> {
+        let mut channels: Vec<usize> = if self.channels.is_empty() {
+            (0..total).collect()
+        } else {
+            self.channels.clone()
+        };
+        channels.retain(|&c| c != channel);
+        if channels.is_empty() {
+            return None;
+        }
+        if channels.len() == total {
+            channels.clear();
+        }
+        Some(AudioSource {
+            stream: self.stream,
+            channels,
+            mid_side: self.mid_side,
+        })
+    }
+}
+
+/// Plot drawn under the spectrogram, sharing its time axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strip {
+    Waveform,
+    PhaseCorrelation,
+}
+
+impl Strip {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Strip::Waveform => "Waveform",
+            Strip::PhaseCorrelation => "Phase correlation",
+        }
+    }
+}
+
+impl std::fmt::Display for Strip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// FFT sizes that can be selected instead of the automatic one.
+pub const FFT_SIZES: [u32; 9] = [256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536];
+/// Largest overlap of the FFT frames, as a fraction of the FFT size.
+pub const MAX_OVERLAP: f32 = 0.95;
+/// Dynamic range of the log scale in dB, same as ffmpeg's default.
+pub const DEFAULT_DB_RANGE: f32 = 120.0;
+/// Values of the dynamic range and of the upper level limit accepted by ffmpeg.
+pub const DB_RANGE_BOUNDS: RangeInclusive<f32> = 10.0..=200.0;
+pub const DB_LIMIT_BOUNDS: RangeInclusive<f32> = -100.0..=100.0;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct AppSettings {
@@ -203,6 +607,31 @@ pub struct AppSettings {
     pub horizontal: bool,
     pub legend: bool,
     pub live_mode: bool,
+    pub engine: SpectrogramEngine,
+    pub frequency_scale: FrequencyScale,
+    /// FFT size of the native engine, `None` follows the image height.
+    pub fft_size: Option<u32>,
+    /// Overlap of the FFT frames averaged into one column, from 0.0 to `MAX_OVERLAP`.
+    pub overlap: f32,
+    /// Levels shown from `db_limit - db_range` to `db_limit` dBFS.
+    pub db_range: f32,
+    pub db_limit: f32,
+    /// Band shown unless zoomed in, in Hz, `None` for 0 Hz and Nyquist.
+    pub min_frequency: Option<f32>,
+    pub max_frequency: Option<f32>,
+    pub detect_cutoff: bool,
+    /// Print the loudness summary into the header of the custom legend.
+    pub loudness_legend: bool,
+    /// Mark clipped samples and true peak overs under the time scale of the custom legend.
+    pub clip_markers: bool,
+    /// Draw the peak waveform under the spectrogram.
+    pub waveform: bool,
+    /// Draw the phase correlation of the first two channels under the spectrogram.
+    pub phase_correlation: bool,
+    pub crosshair: bool,
+    /// Quality of images saved as JPEG, from 1 to 100.
+    pub jpeg_quality: u8,
+    pub compare_layout: CompareLayout,
     pub remember_settings: bool,
     pub custom_legend: bool,
     pub custom_legend_bg_color: [u8; 3],
@@ -210,6 +639,12 @@ pub struct AppSettings {
     pub custom_legend_line_color: [u8; 3],
     pub save_window_size: bool,
     pub window_size: [f32; 2],
+    #[serde(skip)]
+    pub view: ViewRange,
+    #[serde(skip)]
+    pub source: AudioSource,
+    #[serde(skip)]
+    pub difference: Option<Difference>,
 }
 
 impl Default for AppSettings {
@@ -227,18 +662,105 @@ impl Default for AppSettings {
             horizontal: false,
             legend: true,
             live_mode: false,
+            engine: SpectrogramEngine::Ffmpeg,
+            frequency_scale: FrequencyScale::Linear,
+            fft_size: None,
+            overlap: 0.0,
+            db_range: DEFAULT_DB_RANGE,
+            db_limit: 0.0,
+            min_frequency: None,
+            max_frequency: None,
+            detect_cutoff: false,
+            loudness_legend: false,
+            clip_markers: false,
+            waveform: false,
+            phase_correlation: false,
+            crosshair: false,
+            jpeg_quality: DEFAULT_JPEG_QUALITY,
+            compare_layout: CompareLayout::Stacked,
             remember_settings: false,
             custom_legend: true,
             custom_legend_bg_color: [0, 0, 0],
             custom_legend_text_color: [255, 255, 255],
             custom_legend_line_color: [255, 255, 255],
             save_window_size: false,
-            window_size: [500.0 + 180.0, 320.0 + 128.0 + 39.0],
+            window_size: [500.0 + 180.0, 320.0 + 128.0 + 39.0 + 24.0],
+            view: ViewRange::default(),
+            source: AudioSource::default(),
+            difference: None,
         }
     }
 }
 
 impl AppSettings {
+    /// Returns true if the legend is drawn by us rather than by ffmpeg.
+    /// Live mode and the native engine can't use the ffmpeg legend.
+    pub fn use_custom_legend(&self) -> bool {
+        self.legend && (self.custom_legend || self.live_mode || self.native_engine())
+    }
+
+    /// Plots drawn between the spectrogram and the time scale, only by the custom legend.
+    pub fn strips(&self) -> Vec<Strip> {
+        if !self.use_custom_legend() {
+            return Vec::new();
+        }
+        let mut strips = Vec::new();
+        if self.waveform {
+            strips.push(Strip::Waveform);
+        }
+        if self.phase_correlation {
+            strips.push(Strip::PhaseCorrelation);
+        }
+        strips
+    }
+
+    /// Returns true if the spectrogram is computed by us, either because it was selected
+    /// or because ffmpeg can't draw it.
+    pub fn native_engine(&self) -> bool {
+        self.engine == SpectrogramEngine::Native || !self.ffmpeg_supported()
+    }
+
+    /// Returns false if ffmpeg's showspectrum(pic) filters can't draw these settings.
+    /// They choose the FFT size from the image height and have no overlap option.
+    pub fn ffmpeg_supported(&self) -> bool {
+        self.difference.is_none()
+            && self.frequency_scale.ffmpeg_supported()
+            && self.fft_size.is_none()
+            && self.overlap == 0.0
+    }
+
+    /// showspectrum(pic) options for the level range, empty for ffmpeg's defaults.
+    pub fn level_filter_args(&self) -> String {
+        if self.db_range == DEFAULT_DB_RANGE && self.db_limit == 0.0 {
+            String::new()
+        } else {
+            format!(":drange={}:limit={}", self.db_range, self.db_limit)
+        }
+    }
+
+    /// `view` limited to the frequency band of the settings, unless it's zoomed in.
+    pub fn band_view(&self, mut view: ViewRange, nyquist: f32) -> ViewRange {
+        if view.frequency.is_some()
+            || (self.min_frequency.is_none() && self.max_frequency.is_none())
+        {
+            return view;
+        }
+        let min = self.min_frequency.unwrap_or(0.0).clamp(0.0, nyquist);
+        let max = self.max_frequency.unwrap_or(nyquist).clamp(0.0, nyquist);
+        if max - min >= 1.0 {
+            view.frequency = Some((min, max));
+        }
+        view
+    }
+
+    /// Frequency axis of the visible range of a file with the given `nyquist` frequency.
+    pub fn frequency_axis(&self, nyquist: f32) -> FrequencyAxis {
+        FrequencyAxis {
+            scale: self.frequency_scale,
+            range: self.view.frequency_range(nyquist),
+        }
+    }
+
     fn config_path() -> Option<PathBuf> {
         dirs::config_dir().map(|mut path| {
             path.push("spek-rs");
@@ -271,3 +793,62 @@ impl AppSettings {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_past_the_end_is_clamped() {
+        let view = ViewRange {
+            time: Some((50.0, 60.0)),
+            frequency: Some((30000.0, 40000.0)),
+        };
+        assert_eq!(view.time_range(20.0), (20.0, 20.0));
+        assert_eq!(view.frequency_range(22050.0), (22050.0, 22050.0));
+    }
+
+    #[test]
+    fn fitted_view_keeps_its_span() {
+        let view = ViewRange {
+            time: Some((50.0, 60.0)),
+            frequency: Some((-1000.0, 3000.0)),
+        };
+        let fitted = view.fitted(20.0, 22050.0);
+        assert_eq!(fitted.time, Some((10.0, 20.0)));
+        assert_eq!(fitted.frequency, Some((0.0, 4000.0)));
+
+        let longer = ViewRange {
+            time: Some((-5.0, 30.0)),
+            frequency: None,
+        };
+        assert_eq!(longer.fitted(20.0, 22050.0).time, Some((0.0, 20.0)));
+        assert_eq!(longer.fitted(20.0, 22050.0).frequency, None);
+    }
+
+    #[test]
+    fn frequency_axis_round_trips() {
+        for range in [(0.0, 22050.0), (1000.0, 9000.0), (15000.0, 15500.0)] {
+            for scale in FrequencyScale::VALUES {
+                let axis = FrequencyAxis { scale, range };
+                // The log scale starts 20 Hz above the bottom of the range
+                let low = range.0 + FrequencyAxis::LOG_MIN_FREQ;
+                for step in 0..=20 {
+                    let freq = low + (range.1 - low) * step as f32 / 20.0;
+                    let fraction = axis.fraction_of(freq);
+                    assert!((0.0..=1.0 + 1e-6).contains(&fraction));
+                    let back = axis.frequency_at(fraction);
+                    assert!(
+                        (back - freq).abs() <= freq * 1e-4,
+                        "{} axis over {:?}: {} Hz came back as {} Hz",
+                        scale,
+                        range,
+                        freq,
+                        back
+                    );
+                }
+                assert!(axis.fraction_of(range.1) > 0.999);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+use crate::settings::{AppSettings, SpectogramWinFunc};
+use crate::stft::{self, StftAnalyzer};
+use crate::utils::AudioInfo;
+
+/// FFT size of the average spectrum unless one is set.
+const DEFAULT_FFT_SIZE: usize = 8192;
+/// Levels below this are written as this, about the noise of 32-bit float samples.
+pub const FLOOR_DB: f32 = -200.0;
+
+/// Part of the file and analysis settings an average spectrum is measured with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrumRequest {
+    pub time_range: (f64, f64),
+    pub win_func: SpectogramWinFunc,
+    pub fft_size: usize,
+    pub overlap: f32,
+}
+
+impl SpectrumRequest {
+    /// Request for the rendered time range with the window, FFT size and overlap of `settings`.
+    pub fn new(settings: &AppSettings, audio_info: &AudioInfo) -> Self {
+        Self {
+            time_range: settings.view.time_range(audio_info.duration),
+            win_func: settings.win_func,
+            fft_size: settings
+                .fft_size
+                .map_or(DEFAULT_FFT_SIZE, |size| size as usize),
+            overlap: settings.overlap,
+        }
+    }
+}
+
+/// Long-term spectrum of a file, all channels together.
+#[derive(Debug, Clone)]
+pub struct AverageSpectrum {
+    pub request: SpectrumRequest,
+    pub sample_rate: u32,
+    /// Power average of all frames per bin from 0 Hz to Nyquist, in dBFS.
+    pub average_db: Vec<f32>,
+    /// Highest level of any frame and channel per bin, in dBFS.
+    pub peak_db: Vec<f32>,
+}
+
+impl AverageSpectrum {
+    /// Center frequency of `bin` in Hz.
+    pub fn frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate as f32 / self.request.fft_size as f32
+    }
+
+    /// Writes the frequency, average and peak level of every bin as CSV.
+    pub fn write_csv(&self, path: &Path) -> std::io::Result<()> {
+        let mut content = String::from("frequency_hz,average_dbfs,peak_dbfs\n");
+        for (bin, (average, peak)) in self.average_db.iter().zip(&self.peak_db).enumerate() {
+            content.push_str(&format!(
+                "{:.2},{:.2},{:.2}\n",
+                self.frequency(bin),
+                average,
+                peak
+            ));
+        }
+        std::fs::write(path, content)
+    }
+}
+
+/// Averages the spectrum of every FFT frame in the requested part of `input_path` and keeps
+/// the peak of every bin. Returns `None` if decoding failed, was cancelled or found no samples.
+pub fn average_spectrum(
+    input_path: &str,
+    audio_info: &AudioInfo,
+    request: SpectrumRequest,
+    cancel_token: &AtomicBool,
+) -> Option<AverageSpectrum> {
+    let mut analyzer = StftAnalyzer::new(request.fft_size, request.win_func);
+    let bins = analyzer.bins();
+    // One frame per column, overlapping like the frames averaged by the spectrogram
+    let hop = ((request.fft_size as f32 * (1.0 - request.overlap)) as usize).max(1);
+    let (start, end) = request.time_range;
+    let total = ((end - start) * audio_info.sample_rate as f64) as usize;
+    let columns = (total / hop).max(1);
+
+    let mut power = vec![0.0f64; bins];
+    let mut peak = vec![0.0f32; bins];
+    let mut combined = vec![0.0f32; bins];
+    let mut count = 0;
+    let finished = stft::for_each_column(
+        input_path,
+        audio_info,
+        Some(request.time_range),
+        &mut analyzer,
+        columns,
+        cancel_token,
+        |_, spectra| {
+            for channel in spectra {
+                for (p, &a) in peak.iter_mut().zip(channel) {
+                    *p = p.max(a);
+                }
+            }
+            let spectrum = stft::lane_spectrum(spectra, 0, false, &mut combined);
+            for (p, &a) in power.iter_mut().zip(spectrum) {
+                *p += (a * a) as f64;
+            }
+            count += 1;
+        },
+    );
+    if !finished || count == 0 {
+        return None;
+    }
+
+    let to_db = |amplitude: f32| (20.0 * amplitude.log10()).max(FLOOR_DB);
+    Some(AverageSpectrum {
+        request,
+        sample_rate: audio_info.sample_rate,
+        average_db: power
+            .iter()
+            .map(|p| to_db((p / count as f64).sqrt() as f32))
+            .collect(),
+        peak_db: peak.into_iter().map(to_db).collect(),
+    })
+}
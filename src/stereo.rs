@@ -0,0 +1,60 @@
+use std::sync::atomic::AtomicBool;
+
+use crate::pcm::PcmDecoder;
+use crate::utils::AudioInfo;
+
+/// Columns whose channels are both quieter than this have no correlation (about -100 dBFS).
+const SILENCE_ENERGY: f64 = 1e-10;
+
+/// Phase correlation of the first two channels in each of `columns` evenly spaced time slices
+/// of `time_range`, from -1.0 (out of phase) over 0.0 (unrelated) to 1.0 (mono).
+/// Silent slices are `None`. Returns `None` for mono sources or if decoding failed.
+pub fn phase_correlation(
+    input_path: &str,
+    audio_info: &AudioInfo,
+    time_range: (f64, f64),
+    columns: usize,
+    cancel_token: &AtomicBool,
+) -> Option<Vec<Option<f32>>> {
+    if audio_info.channels < 2 || columns == 0 {
+        return None;
+    }
+    let decoder = PcmDecoder::spawn(
+        input_path,
+        &audio_info.source,
+        audio_info.channels,
+        audio_info.sample_rate,
+        Some(time_range),
+    )?;
+    let total = (((time_range.1 - time_range.0) * audio_info.sample_rate as f64) as usize).max(1);
+
+    let mut sums = vec![[0.0f64; 3]; columns];
+    let finished =
+        decoder.for_each_frame_in_columns(total, columns, cancel_token, |column, chunk| {
+            let (mut left, mut right) = (chunk[0] as f64, chunk[1] as f64);
+            if audio_info.source.mid_side {
+                // Back from mid and side to left and right
+                (left, right) = (left + right, left - right);
+            }
+            let sum = &mut sums[column];
+            sum[0] += left * right;
+            sum[1] += left * left;
+            sum[2] += right * right;
+        });
+    if !finished {
+        return None;
+    }
+
+    Some(
+        sums.iter()
+            .map(|[both, left, right]| {
+                let count = (total / columns).max(1) as f64;
+                if left.max(*right) / count < SILENCE_ENERGY {
+                    None
+                } else {
+                    Some((both / (left * right).sqrt().max(f64::MIN_POSITIVE)) as f32)
+                }
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,730 @@
+use eframe::egui::{Color32, ColorImage};
+use image::{ImageBuffer, Luma};
+use realfft::{RealFftPlanner, RealToComplex};
+use std::f64::consts::PI;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::palettes;
+use crate::pcm::PcmDecoder;
+use crate::settings::{AppSettings, FrequencyScale, SpectogramWinFunc, SpectrogramScale};
+use crate::utils::{self, AudioInfo};
+
+/// Upper limit for the FFT size when zoomed into a narrow frequency range.
+const MAX_FFT_SIZE: usize = 65536;
+
+/// Computes windowed magnitude spectra of fixed size frames.
+pub struct StftAnalyzer {
+    fft: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    window_sum: f32,
+    /// Distance between the frames averaged into one column, in samples.
+    hop: usize,
+    input: Vec<f32>,
+    output: Vec<realfft::num_complex::Complex<f32>>,
+    scratch: Vec<realfft::num_complex::Complex<f32>>,
+}
+
+impl StftAnalyzer {
+    pub fn new(fft_size: usize, win_func: SpectogramWinFunc) -> Self {
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(fft_size);
+        let window = window_function(win_func, fft_size);
+        let window_sum = window.iter().sum::<f32>().max(f32::EPSILON);
+        let input = fft.make_input_vec();
+        let output = fft.make_output_vec();
+        let scratch = fft.make_scratch_vec();
+        Self {
+            fft,
+            hop: window.len(),
+            window,
+            window_sum,
+            input,
+            output,
+            scratch,
+        }
+    }
+
+    /// Overlaps the frames averaged into one column by `overlap` of the FFT size.
+    pub fn with_overlap(mut self, overlap: f32) -> Self {
+        let fft_size = self.window.len();
+        self.hop = ((fft_size as f32 * (1.0 - overlap)) as usize).clamp(1, fft_size);
+        self
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.window.len()
+    }
+
+    /// Number of frequency bins between 0 Hz and Nyquist.
+    pub fn bins(&self) -> usize {
+        self.window.len() / 2
+    }
+
+    /// Adds the power spectrum of `frame` to `power`.
+    /// Amplitudes are normalized so a full scale sine wave peaks at 1.0 (0 dBFS).
+    /// A `frame` shorter than the FFT size is zero padded.
+    pub fn accumulate_power(&mut self, frame: &[f32], power: &mut [f32]) {
+        for (i, value) in self.input.iter_mut().enumerate() {
+            *value = frame.get(i).copied().unwrap_or(0.0) * self.window[i];
+        }
+
+        if self
+            .fft
+            .process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch)
+            .is_err()
+        {
+            return;
+        }
+
+        let norm = 2.0 / self.window_sum;
+        for (p, c) in power.iter_mut().zip(self.output.iter()) {
+            let amplitude = c.norm() * norm;
+            *p += amplitude * amplitude;
+        }
+    }
+}
+
+/// Decodes `input_path` (or only its `time_range`) and calls `on_column` with the amplitude
+/// spectrum of every channel for each of `columns` evenly spaced time slices.
+/// When a slice is longer than the FFT size, the power of all frames inside it is averaged.
+/// Returns false if decoding failed or was cancelled.
+pub fn for_each_column(
+    input_path: &str,
+    audio_info: &AudioInfo,
+    time_range: Option<(f64, f64)>,
+    analyzer: &mut StftAnalyzer,
+    columns: usize,
+    cancel_token: &AtomicBool,
+    on_column: impl FnMut(usize, &[Vec<f32>]),
+) -> bool {
+    for_each_column_inspecting(
+        input_path,
+        audio_info,
+        time_range,
+        analyzer,
+        columns,
+        cancel_token,
+        |_| {},
+        on_column,
+    )
+}
+
+/// Like `for_each_column`, `inspect` also sees every chunk of decoded interleaved samples.
+#[allow(clippy::too_many_arguments)]
+pub fn for_each_column_inspecting(
+    input_path: &str,
+    audio_info: &AudioInfo,
+    time_range: Option<(f64, f64)>,
+    analyzer: &mut StftAnalyzer,
+    columns: usize,
+    cancel_token: &AtomicBool,
+    mut inspect: impl FnMut(&[f32]),
+    on_column: impl FnMut(usize, &[Vec<f32>]),
+) -> bool {
+    let Some(mut decoder) = PcmDecoder::spawn(
+        input_path,
+        &audio_info.source,
+        audio_info.channels,
+        audio_info.sample_rate,
+        time_range,
+    ) else {
+        return false;
+    };
+
+    let channels = decoder.channels;
+    let duration = time_range.map_or(audio_info.duration, |(start, end)| end - start);
+    let total = ((duration * audio_info.sample_rate as f64).round() as usize).max(1);
+
+    match analyze_columns(
+        channels,
+        total,
+        |out| {
+            let frames = decoder.read(out)?;
+            inspect(out);
+            Ok(frames)
+        },
+        analyzer,
+        columns,
+        cancel_token,
+        on_column,
+    ) {
+        Some(true) => decoder.finish(),
+        Some(false) => {
+            // The stream may be slightly longer than the duration reported by ffprobe
+            decoder.kill();
+            true
+        }
+        None => {
+            decoder.kill();
+            false
+        }
+    }
+}
+
+/// Splits `total` frames of the interleaved samples returned by `read` into `columns` time
+/// slices and calls `on_column` with the amplitude spectrum of every channel for each of them.
+/// Returns whether `read` reached the end, `None` if reading failed or was cancelled.
+pub fn analyze_columns(
+    channels: usize,
+    total: usize,
+    mut read: impl FnMut(&mut Vec<f32>) -> io::Result<usize>,
+    analyzer: &mut StftAnalyzer,
+    columns: usize,
+    cancel_token: &AtomicBool,
+    mut on_column: impl FnMut(usize, &[Vec<f32>]),
+) -> Option<bool> {
+    let fft_size = analyzer.fft_size();
+    let mut buffers: Vec<Vec<f32>> = vec![Vec::new(); channels];
+    let mut offset = 0; // absolute sample index of buffers[_][0]
+    let mut interleaved = Vec::new();
+    let mut eof = false;
+    let mut spectra = vec![vec![0.0f32; analyzer.bins()]; channels];
+
+    for column in 0..columns {
+        let start = column * total / columns;
+        let end = ((column + 1) * total / columns).max(start + 1);
+        let positions = frame_positions(start, end, fft_size, analyzer.hop);
+        let needed_end = positions.last().copied().unwrap_or(start) + fft_size;
+
+        while !eof && offset + buffers[0].len() < needed_end {
+            if cancel_token.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            interleaved.clear();
+            match read(&mut interleaved) {
+                Ok(0) => eof = true,
+                Ok(_) => {
+                    for frame in interleaved.chunks_exact(channels) {
+                        for (buffer, sample) in buffers.iter_mut().zip(frame) {
+                            buffer.push(*sample);
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to read ffmpeg stdout: {}", e);
+                    return None;
+                }
+            }
+        }
+
+        for (buffer, spectrum) in buffers.iter().zip(spectra.iter_mut()) {
+            spectrum.fill(0.0);
+            for &position in &positions {
+                let from = (position - offset).min(buffer.len());
+                let to = (from + fft_size).min(buffer.len());
+                analyzer.accumulate_power(&buffer[from..to], spectrum);
+            }
+            let count = positions.len() as f32;
+            for value in spectrum.iter_mut() {
+                *value = (*value / count).sqrt();
+            }
+        }
+
+        on_column(column, &spectra);
+
+        // Drop samples that no later column needs
+        let next_start = (column + 1) * total / columns;
+        let keep_from = next_start.saturating_sub(fft_size / 2).max(offset);
+        let drain = (keep_from - offset).min(buffers[0].len());
+        for buffer in buffers.iter_mut() {
+            buffer.drain(..drain);
+        }
+        offset += drain;
+    }
+
+    Some(eof)
+}
+
+/// Start positions of the FFT frames covering the samples `start..end`, `hop` samples apart.
+fn frame_positions(start: usize, end: usize, fft_size: usize, hop: usize) -> Vec<usize> {
+    if end - start >= fft_size {
+        (start..=end - fft_size).step_by(hop).collect()
+    } else {
+        let center = (start + end) / 2;
+        vec![center.saturating_sub(fft_size / 2)]
+    }
+}
+
+/// Generates a spectrogram without ffmpeg's showspectrumpic filter, decoding PCM and
+/// computing the STFT in process. The output matches the vertical orientation of ffmpeg.
+pub fn generate_spectrogram(
+    input_path: &str,
+    settings: &AppSettings,
+    width: u32,
+    height: u32,
+    cancel_token: Arc<AtomicBool>,
+) -> Option<ColorImage> {
+    let start = Instant::now();
+    println!("Generating native spectrogram for: {}", input_path);
+    log::debug!("{:#?}", settings);
+
+    let audio_info = match utils::get_audio_info(input_path, &settings.source) {
+        Some(info) if info.duration > 0.0 => info,
+        _ => {
+            eprintln!("Failed to get valid audio info.");
+            return None;
+        }
+    };
+
+    let layout = RowLayout::new(settings, &audio_info, height);
+    let mut analyzer =
+        StftAnalyzer::new(layout.fft_size, settings.win_func).with_overlap(settings.overlap);
+
+    let mut image = ColorImage::new(
+        [width as usize, height as usize],
+        vec![Color32::BLACK; (width * height) as usize],
+    );
+    let mut combined = vec![0.0f32; analyzer.bins()];
+
+    let finished = for_each_column(
+        input_path,
+        &audio_info,
+        settings
+            .view
+            .time
+            .map(|_| settings.view.time_range(audio_info.duration)),
+        &mut analyzer,
+        width as usize,
+        &cancel_token,
+        |x, spectra| draw_column(&mut image, x, spectra, &layout, settings, &mut combined),
+    );
+
+    if !finished {
+        return None;
+    }
+
+    println!("Spectrogram generated in {:?}.", start.elapsed());
+    Some(image)
+}
+
+/// Intensity of every pixel of the native spectrogram before the palette is applied, from
+/// 0 at the bottom of the level range to 65535 at the top, without a legend.
+/// Returns `None` if decoding failed or was cancelled.
+pub fn generate_intensity(
+    input_path: &str,
+    settings: &AppSettings,
+    audio_info: &AudioInfo,
+    width: u32,
+    height: u32,
+    cancel_token: &AtomicBool,
+) -> Option<ImageBuffer<Luma<u16>, Vec<u16>>> {
+    let layout = RowLayout::new(settings, audio_info, height);
+    let mut analyzer =
+        StftAnalyzer::new(layout.fft_size, settings.win_func).with_overlap(settings.overlap);
+    let mut image = ImageBuffer::new(width, height);
+    let mut combined = vec![0.0f32; analyzer.bins()];
+
+    let finished = for_each_column(
+        input_path,
+        audio_info,
+        settings
+            .view
+            .time
+            .map(|_| settings.view.time_range(audio_info.duration)),
+        &mut analyzer,
+        width as usize,
+        cancel_token,
+        |x, spectra| {
+            for lane in 0..layout.lanes {
+                let spectrum = lane_spectrum(spectra, lane, settings.split_channels, &mut combined);
+                for row in 0..layout.lane_height {
+                    let amplitude = layout.row_amplitude(spectrum, row);
+                    let value = scale_value(amplitude * settings.gain, settings);
+                    let y = (lane * layout.lane_height + row) as u32;
+                    image.put_pixel(x as u32, y, Luma([(value * 65535.0).round() as u16]));
+                }
+            }
+        },
+    );
+    finished.then_some(image)
+}
+
+/// Draws the spectra of one time slice into column `x` of `image` with the palette of `settings`.
+pub fn draw_column(
+    image: &mut ColorImage,
+    x: usize,
+    spectra: &[Vec<f32>],
+    layout: &RowLayout,
+    settings: &AppSettings,
+    combined: &mut [f32],
+) {
+    let palette = palettes::get_palette(settings.color_scheme);
+    for lane in 0..layout.lanes {
+        let spectrum = lane_spectrum(spectra, lane, settings.split_channels, combined);
+        for row in 0..layout.lane_height {
+            let amplitude = layout.row_amplitude(spectrum, row);
+            let value = scale_value(amplitude * settings.gain, settings);
+            let color = palettes::color_at(palette, value, settings.saturation);
+            let y = lane * layout.lane_height + row;
+            image[(x, y)] = Color32::from_rgb(color[0], color[1], color[2]);
+        }
+    }
+}
+
+/// Channel lanes of a spectrogram and the FFT bins drawn in each of their rows.
+pub struct RowLayout {
+    pub lanes: usize,
+    pub lane_height: usize,
+    pub fft_size: usize,
+    /// Range of bins of every row of a lane, from the top.
+    rows: Vec<(usize, usize)>,
+}
+
+impl RowLayout {
+    pub fn new(settings: &AppSettings, audio_info: &AudioInfo, height: u32) -> Self {
+        let lanes = if settings.split_channels {
+            audio_info.channels.max(1) as usize
+        } else {
+            1
+        };
+        let lane_height = (height as usize / lanes).max(1);
+
+        // A zoomed frequency range needs a longer FFT to keep one bin per row
+        let nyquist = audio_info.sample_rate as f32 / 2.0;
+        let axis = settings.frequency_axis(nyquist);
+        let (min_freq, max_freq) = axis.range;
+        let mut zoom = (nyquist / (max_freq - min_freq).max(1.0)).max(1.0);
+        if axis.scale != FrequencyScale::Linear {
+            // The low frequencies are spread over many rows
+            zoom *= 4.0;
+        }
+        let fft_size = match settings.fft_size {
+            Some(size) => size as usize,
+            None => ((lane_height as f32 * 2.0 * zoom) as usize)
+                .next_power_of_two()
+                .min(MAX_FFT_SIZE),
+        };
+        let bins = fft_size / 2;
+
+        let bin_at = |fraction: f32| axis.frequency_at(fraction) / nyquist * bins as f32;
+        let rows = (0..lane_height)
+            .map(|row| {
+                let bottom = (lane_height - 1 - row) as f32 / lane_height as f32;
+                let top = (lane_height - row) as f32 / lane_height as f32;
+                let from = (bin_at(bottom) as usize).min(bins - 1);
+                let to = (bin_at(top).ceil() as usize).clamp(from + 1, bins);
+                (from, to)
+            })
+            .collect();
+
+        Self {
+            lanes,
+            lane_height,
+            fft_size,
+            rows,
+        }
+    }
+
+    /// Highest amplitude of the bins drawn in `row` of a lane, row 0 being the top.
+    pub fn row_amplitude(&self, spectrum: &[f32], row: usize) -> f32 {
+        let (from, to) = self.rows[row];
+        spectrum[from..to.min(spectrum.len())]
+            .iter()
+            .fold(0.0f32, |a, &b| a.max(b))
+    }
+}
+
+/// Spectrum drawn in `lane`: its channel when split, otherwise the power average of all
+/// channels, computed into `combined`.
+pub fn lane_spectrum<'a>(
+    spectra: &'a [Vec<f32>],
+    lane: usize,
+    split_channels: bool,
+    combined: &'a mut [f32],
+) -> &'a [f32] {
+    if split_channels {
+        return &spectra[lane];
+    }
+    combined.fill(0.0);
+    for channel in spectra {
+        for (c, v) in combined.iter_mut().zip(channel) {
+            *c += v * v;
+        }
+    }
+    for c in combined.iter_mut() {
+        *c = (*c / spectra.len() as f32).sqrt();
+    }
+    combined
+}
+
+/// Maps an amplitude to the 0.0 - 1.0 intensity range using the scale and level range
+/// of `settings`.
+pub fn scale_value(amplitude: f32, settings: &AppSettings) -> f32 {
+    // Amplitudes are relative to the upper level limit
+    let limit = 10f32.powf(settings.db_limit / 20.0);
+    let a = (amplitude / limit).clamp(0.0, 1.0);
+    match settings.scale {
+        SpectrogramScale::Lin => a,
+        SpectrogramScale::Sqrt => a.sqrt(),
+        SpectrogramScale::Cbrt => a.cbrt(),
+        SpectrogramScale::FourthRt => a.powf(1.0 / 4.0),
+        SpectrogramScale::FifthRt => a.powf(1.0 / 5.0),
+        SpectrogramScale::Log => {
+            let db = 20.0 * amplitude.max(1e-12).log10();
+            let range = settings.db_range.max(1.0);
+            ((db - settings.db_limit + range) / range).clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// Inverse of `scale_value`, converts an intensity back to the amplitude.
+pub fn amplitude_from_value(value: f32, settings: &AppSettings) -> f32 {
+    let v = value.clamp(0.0, 1.0);
+    let limit = 10f32.powf(settings.db_limit / 20.0);
+    match settings.scale {
+        SpectrogramScale::Lin => v * limit,
+        SpectrogramScale::Sqrt => v.powi(2) * limit,
+        SpectrogramScale::Cbrt => v.powi(3) * limit,
+        SpectrogramScale::FourthRt => v.powi(4) * limit,
+        SpectrogramScale::FifthRt => v.powi(5) * limit,
+        SpectrogramScale::Log => {
+            let range = settings.db_range.max(1.0);
+            10f32.powf((v * range - range + settings.db_limit) / 20.0)
+        }
+    }
+}
+
+/// Builds a window of `size` samples.
+/// Formulas follow ffmpeg's libavfilter/window_func.h so both engines look alike.
+pub fn window_function(win_func: SpectogramWinFunc, size: usize) -> Vec<f32> {
+    let n_max = (size.max(2) - 1) as f64;
+    let cos_sum = |n: f64, coefficients: &[f64]| -> f64 {
+        coefficients
+            .iter()
+            .enumerate()
+            .map(|(k, c)| c * (2.0 * PI * k as f64 * n / n_max).cos())
+            .sum()
+    };
+    // Position in the window from -1.0 to 1.0
+    let centered = |n: f64| 2.0 * n / n_max - 1.0;
+
+    let window: Vec<f64> = match win_func {
+        SpectogramWinFunc::Dolph => dolph_chebyshev(size),
+        _ => (0..size)
+            .map(|n| {
+                let n = n as f64;
+                match win_func {
+                    SpectogramWinFunc::Rect => 1.0,
+                    SpectogramWinFunc::Bartlett => 1.0 - centered(n).abs(),
+                    SpectogramWinFunc::Hann | SpectogramWinFunc::Hanning => {
+                        cos_sum(n, &[0.5, -0.5])
+                    }
+                    SpectogramWinFunc::Hamming => cos_sum(n, &[0.54, -0.46]),
+                    SpectogramWinFunc::Blackman => cos_sum(n, &[0.42659, -0.49656, 0.076849]),
+                    SpectogramWinFunc::Welch => 1.0 - centered(n).powi(2),
+                    SpectogramWinFunc::Flattop => cos_sum(
+                        n,
+                        &[
+                            1.0,
+                            -1.985844164102,
+                            1.791176438506,
+                            -1.282075284005,
+                            0.667777530266,
+                            -0.240160796576,
+                            0.056656381764,
+                            -0.008134974479,
+                            0.000624544650,
+                            -0.000019808998,
+                            0.000000132974,
+                        ],
+                    ),
+                    SpectogramWinFunc::Bharris => {
+                        cos_sum(n, &[0.35875, -0.48829, 0.14128, -0.01168])
+                    }
+                    SpectogramWinFunc::Bnuttall => {
+                        cos_sum(n, &[0.3635819, -0.4891775, 0.1365995, -0.0106411])
+                    }
+                    SpectogramWinFunc::Bhann => {
+                        0.62 - 0.48 * (n / n_max - 0.5).abs() - 0.38 * (2.0 * PI * n / n_max).cos()
+                    }
+                    SpectogramWinFunc::Sine => (PI * n / n_max).sin(),
+                    SpectogramWinFunc::Nuttall => {
+                        cos_sum(n, &[0.355768, -0.487396, 0.144232, -0.012604])
+                    }
+                    SpectogramWinFunc::Lanczos => {
+                        let x = PI * centered(n);
+                        if x.abs() < 1e-9 {
+                            1.0
+                        } else {
+                            x.sin() / x
+                        }
+                    }
+                    SpectogramWinFunc::Gauss => (-0.5 * (centered(n) / 0.4).powi(2)).exp(),
+                    SpectogramWinFunc::Tukey => {
+                        let m = n_max / 2.0;
+                        let distance = (n - m).abs();
+                        if distance >= 0.3 * m {
+                            0.5 * (1.0 + (PI * (distance - 0.3 * m) / ((1.0 - 0.3) * m)).cos())
+                        } else {
+                            1.0
+                        }
+                    }
+                    SpectogramWinFunc::Cauchy => {
+                        let x = centered(n) / 2.0;
+                        (1.0 / (1.0 + 4.0 * 16.0 * x * x)).min(1.0)
+                    }
+                    SpectogramWinFunc::Parzen => {
+                        let x = centered(n).abs();
+                        if x <= 0.5 {
+                            1.0 - 6.0 * x * x + 6.0 * x * x * x
+                        } else {
+                            2.0 * (1.0 - x).powi(3)
+                        }
+                    }
+                    SpectogramWinFunc::Poisson => (-centered(n).abs() * 60.0 / 8.69).exp(),
+                    SpectogramWinFunc::Bohman => {
+                        let x = centered(n).abs();
+                        (1.0 - x) * (PI * x).cos() + (PI * x).sin() / PI
+                    }
+                    SpectogramWinFunc::Kaiser => {
+                        let beta = 12.0;
+                        let x = centered(n);
+                        bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / bessel_i0(beta)
+                    }
+                    SpectogramWinFunc::Dolph => unreachable!(),
+                }
+            })
+            .collect(),
+    };
+
+    window.into_iter().map(|w| w as f32).collect()
+}
+
+/// Dolph-Chebyshev window with 150 dB of side lobe attenuation, as implemented in ffmpeg.
+fn dolph_chebyshev(size: usize) -> Vec<f64> {
+    let mut lut = vec![0.0; size];
+    if size < 2 {
+        return vec![1.0; size];
+    }
+
+    let big_n = size as f64;
+    let b0 = (7.600_902_209_541_99 / (big_n - 1.0)).cosh();
+    let c = 1.0 - 1.0 / (b0 * b0);
+    let mut norm = 0.0;
+
+    for n in (0..=(size - 1) / 2).rev() {
+        let mut sum = if n == 0 { 1.0 } else { 0.0 };
+        let mut b = 1.0;
+        let mut t = 1.0;
+        let mut j = 1;
+        while j <= n && sum != t {
+            t = sum;
+            b *= c * (big_n - n as f64 - j as f64) / j as f64;
+            sum += b;
+            b *= (n - j) as f64 / j as f64;
+            j += 1;
+        }
+        sum /= big_n - 1.0 - n as f64;
+        if norm == 0.0 {
+            norm = sum;
+        }
+        sum /= norm;
+        lut[n] = sum;
+        lut[size - 1 - n] = sum;
+    }
+    lut
+}
+
+/// Modified Bessel function of the first kind, order zero.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..50 {
+        term *= (half / k as f64).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn sine(frequency: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| {
+                amplitude * (2.0 * std::f32::consts::PI * frequency * n as f32 / SAMPLE_RATE).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sine_peaks_at_its_bin_with_its_amplitude() {
+        let mut analyzer = StftAnalyzer::new(4096, SpectogramWinFunc::Hann);
+        // Bin 64 of a 4096 point FFT at 48 kHz
+        let frequency = 64.0 * SAMPLE_RATE / 4096.0;
+        let mut power = vec![0.0; analyzer.bins()];
+        analyzer.accumulate_power(&sine(frequency, 0.5, 4096), &mut power);
+
+        let (peak_bin, peak_power) = power
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        assert_eq!(peak_bin, 64);
+        assert!(
+            (peak_power.sqrt() - 0.5).abs() < 0.01,
+            "{}",
+            peak_power.sqrt()
+        );
+        assert!(power[200].sqrt() < 1e-4);
+    }
+
+    #[test]
+    fn columns_split_the_samples_evenly() {
+        let mut analyzer = StftAnalyzer::new(1024, SpectogramWinFunc::Hann);
+        let frequency = 32.0 * SAMPLE_RATE / 1024.0;
+        // Left channel carries the sine, right channel is silent
+        let samples: Vec<f32> = sine(frequency, 1.0, 48000)
+            .into_iter()
+            .flat_map(|s| [s, 0.0])
+            .collect();
+        let mut chunks = samples.chunks(4000);
+
+        let mut seen = Vec::new();
+        let finished = analyze_columns(
+            2,
+            48000,
+            |out| {
+                let chunk = chunks.next().unwrap_or_default();
+                out.extend_from_slice(chunk);
+                Ok(chunk.len())
+            },
+            &mut analyzer,
+            10,
+            &AtomicBool::new(false),
+            |column, spectra| {
+                assert_eq!(spectra.len(), 2);
+                assert!((spectra[0][32] - 1.0).abs() < 0.01, "{}", spectra[0][32]);
+                assert!(spectra[1][32] < 1e-6);
+                seen.push(column);
+            },
+        );
+        assert!(finished.is_some());
+        assert_eq!(seen, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cancelled_analysis_returns_none() {
+        let mut analyzer = StftAnalyzer::new(1024, SpectogramWinFunc::Hann);
+        let result = analyze_columns(
+            1,
+            48000,
+            |_| Ok(0),
+            &mut analyzer,
+            10,
+            &AtomicBool::new(true),
+            |_, _| {},
+        );
+        assert_eq!(result, None);
+    }
+}
@@ -0,0 +1,73 @@
+use eframe::egui;
+
+use super::cursor::SpectrogramArea;
+use super::MyApp;
+use crate::settings::AudioSource;
+
+impl MyApp {
+    /// Right click menu of the split channel lanes to hide a channel or show all of them.
+    pub(super) fn handle_channel_menu(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        area: &SpectrogramArea,
+    ) {
+        if area.lanes < 2 && self.settings.source.channels.is_empty() {
+            return;
+        }
+        if response.secondary_clicked() {
+            self.channel_menu_lane = response
+                .interact_pointer_pos()
+                .filter(|pos| area.rect.contains(*pos))
+                .map(|pos| area.point_at(pos).lane);
+        }
+
+        let mut new_source = None;
+        response.context_menu(|ui| {
+            let source = &self.settings.source;
+            let total = self
+                .tab
+                .audio_streams
+                .get(source.stream)
+                .map_or(area.lanes, |stream| stream.channels as usize);
+
+            if let (Some(lane), Some(info)) = (self.channel_menu_lane, self.tab.audio_info.as_ref())
+            {
+                if area.lanes > 1 && !source.mid_side {
+                    let channel = source.channels.get(lane).copied().unwrap_or(lane);
+                    let name = info.channel_names.get(lane).cloned().unwrap_or_default();
+                    let hidden = source.without_channel(channel, total);
+                    if ui
+                        .add_enabled(
+                            hidden.is_some(),
+                            egui::Button::new(format!("Hide channel {} ({})", channel + 1, name)),
+                        )
+                        .clicked()
+                    {
+                        new_source = hidden;
+                        ui.close();
+                    }
+                }
+            }
+            if ui
+                .add_enabled(
+                    !source.channels.is_empty(),
+                    egui::Button::new("Show all channels"),
+                )
+                .clicked()
+            {
+                new_source = Some(AudioSource {
+                    stream: source.stream,
+                    channels: Vec::new(),
+                    mid_side: source.mid_side,
+                });
+                ui.close();
+            }
+        });
+
+        if let Some(source) = new_source {
+            self.set_source(source);
+            self.regenerate_spectrogram(ui.ctx());
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use eframe::egui::{self, Rect, Stroke};
+
+use super::cursor::{draw_crosshair, SpectrogramPoint};
+use super::tab::Tab;
+use super::MyApp;
+use crate::legend;
+use crate::settings::{AppSettings, CompareLayout, ViewRange};
+
+/// Gap between the spectrograms of the compare view, in logical points.
+const CELL_SPACING: f32 = 4.0;
+
+impl MyApp {
+    /// Returns the tab at `index`, the shown one included.
+    pub(super) fn tab_at(&self, index: usize) -> &Tab {
+        if index == self.active_tab {
+            &self.tab
+        } else {
+            &self.tabs[index]
+        }
+    }
+
+    /// Indices of the tabs shown in the compare view, every tab with a file.
+    pub(super) fn compared_tabs(&self) -> Vec<usize> {
+        (0..self.tabs.len())
+            .filter(|&index| self.tab_at(index).input_path.is_some())
+            .collect()
+    }
+
+    pub(super) fn is_comparing(&self) -> bool {
+        self.compare && self.compared_tabs().len() > 1
+    }
+
+    pub(super) fn toggle_compare(&mut self, ctx: &egui::Context) {
+        self.compare = !self.compare;
+        self.cursor_readout = None;
+        // The other tabs were rendered with the view of the compare mode or their own one
+        self.mark_background_tabs_stale();
+        self.regenerate_spectrogram(ctx);
+    }
+
+    /// Range shown by every compared tab. Unless zoomed, files of different lengths or
+    /// sample rates are cut to the range they have in common.
+    fn shared_view(&self) -> ViewRange {
+        let mut view = self.settings.view;
+        let infos: Vec<_> = self
+            .compared_tabs()
+            .into_iter()
+            .filter_map(|index| self.tab_at(index).audio_info.as_ref())
+            .collect();
+        let min_duration = infos
+            .iter()
+            .map(|info| info.duration)
+            .fold(f64::MAX, f64::min);
+        let max_duration = infos.iter().map(|info| info.duration).fold(0.0, f64::max);
+        if view.time.is_none() && max_duration - min_duration > 0.001 {
+            view.time = Some((0.0, min_duration));
+        }
+        let min_rate = infos.iter().map(|info| info.sample_rate).min();
+        let max_rate = infos.iter().map(|info| info.sample_rate).max();
+        if let Some(min_rate) = min_rate {
+            view = self.settings.band_view(view, min_rate as f32 / 2.0);
+        }
+        if let (None, Some(min_rate), Some(max_rate)) = (view.frequency, min_rate, max_rate) {
+            if min_rate != max_rate {
+                view.frequency = Some((0.0, min_rate as f32 / 2.0));
+            }
+        }
+        // A zoom into the longer or wider file must not end up outside the others
+        match min_rate {
+            Some(min_rate) => view.fitted(min_duration, min_rate as f32 / 2.0),
+            None => view,
+        }
+    }
+
+    /// Range shown by the spectrograms on screen.
+    pub(super) fn visible_view(&self) -> ViewRange {
+        if self.is_comparing() {
+            self.shared_view()
+        } else {
+            self.settings.view
+        }
+    }
+
+    /// Settings the tab at `index` is rendered with.
+    pub(super) fn tab_settings(&self, index: usize) -> AppSettings {
+        let mut settings = self.settings.clone();
+        if index != self.active_tab {
+            let tab = &self.tabs[index];
+            settings.view = tab.view;
+            settings.source = tab.source.clone();
+        }
+        if self.is_comparing() {
+            settings.view = self.shared_view();
+        } else if let Some(info) = &self.tab_at(index).audio_info {
+            let nyquist = info.sample_rate as f32 / 2.0;
+            settings.view = settings
+                .band_view(settings.view, nyquist)
+                .fitted(info.duration, nyquist);
+        }
+        settings.difference = self.tab_at(index).difference.clone();
+        if !settings.ffmpeg_supported() {
+            // ffmpeg can't stream these settings, the native engine renders the whole image instead
+            settings.live_mode = false;
+        }
+        settings
+    }
+
+    /// Spectrogram size in pixels, split between the compared tabs when it follows the window.
+    pub(super) fn render_size(&self) -> (u32, u32) {
+        let (width, height) = if self.settings.custom_resolution || self.settings.resize_with_window
+        {
+            (self.settings.resolution[0], self.settings.resolution[1])
+        } else {
+            (500, 320)
+        };
+        if !self.settings.resize_with_window || !self.is_comparing() {
+            return (width, height);
+        }
+
+        // Every spectrogram has its own legend margins
+        let count = self.compared_tabs().len() as u32;
+        let margin_w = legend::LEFT_MARGIN + legend::RIGHT_MARGIN;
+        let margin_h = legend::TOP_MARGIN + legend::bottom_margin(self.settings.strips().len());
+        match self.settings.compare_layout {
+            CompareLayout::SideBySide => (
+                ((width + margin_w) / count)
+                    .saturating_sub(margin_w)
+                    .max(100),
+                height,
+            ),
+            CompareLayout::Stacked => (
+                width,
+                ((height + margin_h) / count)
+                    .saturating_sub(margin_h)
+                    .max(100),
+            ),
+        }
+    }
+
+    /// Every compared tab in its own cell, with the zoom and cursor shared between them.
+    /// Clicking a spectrogram makes its tab the active one.
+    pub(super) fn show_compare_view(&mut self, ui: &mut egui::Ui) {
+        let indices = self.compared_tabs();
+        let available = ui.available_rect_before_wrap();
+        let count = indices.len() as f32;
+        let cell_size = match self.settings.compare_layout {
+            CompareLayout::SideBySide => egui::vec2(
+                (available.width() - CELL_SPACING * (count - 1.0)) / count,
+                available.height(),
+            ),
+            CompareLayout::Stacked => egui::vec2(
+                available.width(),
+                (available.height() - CELL_SPACING * (count - 1.0)) / count,
+            ),
+        };
+
+        let mut cells = Vec::new();
+        let mut hovered: Option<(usize, SpectrogramPoint)> = None;
+        let mut readout = None;
+        let mut switch_to = None;
+
+        for (position, &index) in indices.iter().enumerate() {
+            let position = position as f32;
+            let cell_min = match self.settings.compare_layout {
+                CompareLayout::SideBySide => {
+                    available.min + egui::vec2(position * (cell_size.x + CELL_SPACING), 0.0)
+                }
+                CompareLayout::Stacked => {
+                    available.min + egui::vec2(0.0, position * (cell_size.y + CELL_SPACING))
+                }
+            };
+            let cell = Rect::from_min_size(cell_min, cell_size);
+
+            let tab = self.tab_at(index);
+            let Some((texture_id, image_size)) =
+                tab.texture.as_ref().map(|t| (t.id(), t.size_vec2()))
+            else {
+                if tab.is_generating {
+                    ui.put(cell, egui::Spinner::new());
+                }
+                continue;
+            };
+
+            // Fit the image into the cell, keeping its aspect ratio
+            let scale = (cell.width() / image_size.x).min(cell.height() / image_size.y);
+            let image_rect = Rect::from_center_size(cell.center(), image_size * scale);
+            let response = ui.put(
+                image_rect,
+                egui::Image::new((texture_id, image_rect.size()))
+                    .sense(egui::Sense::click_and_drag()),
+            );
+            if index == self.active_tab {
+                ui.painter().rect_stroke(
+                    image_rect.expand(1.0),
+                    0.0,
+                    Stroke::new(1.0, ui.visuals().selection.bg_fill),
+                    egui::StrokeKind::Outside,
+                );
+            }
+
+            let area = self.spectrogram_area_of(self.tab_at(index), image_rect);
+            if let Some(area) = &area {
+                self.handle_zoom(ui, &response, area);
+                if index == self.active_tab {
+                    self.handle_playhead(ui, &response, area);
+                } else if response.clicked() {
+                    switch_to = Some(index);
+                }
+                if let Some(pointer) = response.hover_pos().filter(|pos| area.rect.contains(*pos)) {
+                    hovered = Some((index, area.point_at(pointer)));
+                    readout =
+                        self.cursor_readout_at(self.tab_at(index), &response, image_rect, area);
+                }
+            } else if response.clicked() && index != self.active_tab {
+                switch_to = Some(index);
+            }
+            cells.push((index, area));
+        }
+
+        if readout != self.cursor_readout {
+            self.cursor_readout = readout;
+            ui.ctx().request_repaint();
+        }
+
+        // The same time and frequency is marked in every spectrogram
+        if let Some((hovered_index, point)) = hovered {
+            for (index, area) in cells.iter() {
+                let Some(area) = area else {
+                    continue;
+                };
+                if *index == hovered_index && !self.settings.crosshair {
+                    continue;
+                }
+                let lane = point.lane.min(area.lanes - 1);
+                if let Some(pos) = area.point_to_screen(point.time, point.frequency, lane) {
+                    draw_crosshair(ui, area.rect, pos);
+                }
+            }
+        }
+
+        if let Some(index) = switch_to {
+            self.switch_tab(index);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use eframe::egui::{self, Color32, Pos2, Rect, Stroke};
+
+use super::tab::Tab;
+use super::MyApp;
+use crate::diff;
+use crate::legend;
+use crate::palettes;
+use crate::settings::{DifferenceMode, FrequencyAxis};
+use crate::stft;
+use crate::utils;
+
+/// Values under the mouse cursor.
+#[derive(Clone, Copy, PartialEq)]
+pub struct CursorReadout {
+    pub time: f64,
+    pub frequency: f32,
+    /// Zero based channel index, only when channels are split.
+    pub channel: Option<usize>,
+    pub level_db: Option<f32>,
+    /// `level_db` is the delta of a difference spectrogram rather than dBFS.
+    pub relative: bool,
+}
+
+/// Point of the spectrogram under a screen position.
+pub struct SpectrogramPoint {
+    pub time: f64,
+    pub frequency: f32,
+    pub lane: usize,
+}
+
+/// Maps between screen positions and the time and frequency of the displayed spectrogram.
+pub struct SpectrogramArea {
+    /// Spectrogram without the legend, in screen coordinates.
+    pub rect: Rect,
+    pub lanes: usize,
+    /// Size of a channel lane along the frequency axis, in screen coordinates.
+    pub lane_size: f32,
+    /// ffmpeg's horizontal orientation: frequency on the x axis, time on the y axis.
+    pub horizontal: bool,
+    pub time_range: (f64, f64),
+    pub freq_axis: FrequencyAxis,
+}
+
+impl SpectrogramArea {
+    /// Returns the point under `pos`, positions outside are clamped to the edge.
+    pub fn point_at(&self, pos: Pos2) -> SpectrogramPoint {
+        let pos = self.rect.clamp(pos);
+        let relative = pos - self.rect.min;
+        let (time_pos, time_len, freq_pos, freq_len) = if self.horizontal {
+            (
+                relative.y,
+                self.rect.height(),
+                relative.x,
+                self.rect.width(),
+            )
+        } else {
+            (
+                relative.x,
+                self.rect.width(),
+                relative.y,
+                self.rect.height(),
+            )
+        };
+
+        // Split the frequency axis into channel lanes
+        let lane_len = self.lane_size.min(freq_len).max(f32::EPSILON);
+        let lane = ((freq_pos / lane_len) as usize).min(self.lanes - 1);
+        let in_lane = ((freq_pos - lane as f32 * lane_len) / lane_len).clamp(0.0, 1.0);
+        let freq_fraction = if self.horizontal {
+            in_lane
+        } else {
+            1.0 - in_lane
+        };
+
+        let (start, end) = self.time_range;
+        SpectrogramPoint {
+            time: start + (time_pos / time_len) as f64 * (end - start),
+            frequency: self.freq_axis.frequency_at(freq_fraction),
+            lane,
+        }
+    }
+
+    /// Fraction of the visible time range under `pos`, from 0.0 to 1.0.
+    pub fn time_fraction(&self, pos: Pos2) -> f64 {
+        let (start, end) = self.time_range;
+        ((self.point_at(pos).time - start) / (end - start).max(f64::EPSILON)).clamp(0.0, 1.0)
+    }
+
+    /// Screen position of `time` and `frequency` in `lane`, `None` outside the visible range.
+    pub fn point_to_screen(&self, time: f64, frequency: f32, lane: usize) -> Option<Pos2> {
+        let (start, end) = self.time_range;
+        let freq_fraction = self.freq_axis.fraction_of(frequency);
+        if time < start || time > end || !(0.0..=1.0).contains(&freq_fraction) {
+            return None;
+        }
+        let time_fraction = ((time - start) / (end - start).max(f64::EPSILON)) as f32;
+
+        let lane_offset = lane.min(self.lanes - 1) as f32 * self.lane_size;
+        Some(if self.horizontal {
+            Pos2::new(
+                self.rect.min.x + lane_offset + freq_fraction * self.lane_size,
+                self.rect.min.y + time_fraction * self.rect.height(),
+            )
+        } else {
+            Pos2::new(
+                self.rect.min.x + time_fraction * self.rect.width(),
+                self.rect.min.y + lane_offset + (1.0 - freq_fraction) * self.lane_size,
+            )
+        })
+    }
+
+    /// Screen coordinate of `time` along the time axis, `None` outside the visible range.
+    pub fn time_to_screen(&self, time: f64) -> Option<f32> {
+        let (start, end) = self.time_range;
+        if time < start || time > end || end <= start {
+            return None;
+        }
+        let fraction = ((time - start) / (end - start)) as f32;
+        Some(if self.horizontal {
+            self.rect.min.y + fraction * self.rect.height()
+        } else {
+            self.rect.min.x + fraction * self.rect.width()
+        })
+    }
+}
+
+impl MyApp {
+    /// Locates the spectrogram inside the image shown at `image_rect`.
+    /// Returns `None` for the ffmpeg legend, as its layout is unknown.
+    pub(super) fn spectrogram_area(&self, image_rect: Rect) -> Option<SpectrogramArea> {
+        self.spectrogram_area_of(&self.tab, image_rect)
+    }
+
+    /// Locates the spectrogram of `tab` inside its image shown at `image_rect`.
+    pub(super) fn spectrogram_area_of(
+        &self,
+        tab: &Tab,
+        image_rect: Rect,
+    ) -> Option<SpectrogramArea> {
+        let image = tab.final_image.as_ref()?;
+        let audio_info = tab.audio_info.as_ref()?;
+        let view = self
+            .settings
+            .band_view(self.visible_view(), audio_info.sample_rate as f32 / 2.0);
+
+        // Difference spectrograms always have the custom legend
+        let custom_legend =
+            self.settings.use_custom_legend() || (self.settings.legend && tab.difference.is_some());
+        let (min, max) = if custom_legend {
+            (
+                Pos2::new(legend::LEFT_MARGIN as f32, legend::TOP_MARGIN as f32),
+                Pos2::new(
+                    image.width() as f32 - legend::RIGHT_MARGIN as f32,
+                    image.height() as f32
+                        - legend::bottom_margin(self.settings.strips().len()) as f32,
+                ),
+            )
+        } else if !self.settings.legend {
+            (
+                Pos2::ZERO,
+                Pos2::new(image.width() as f32, image.height() as f32),
+            )
+        } else {
+            return None;
+        };
+        if max.x <= min.x || max.y <= min.y {
+            return None;
+        }
+
+        // Image pixels to screen position
+        let to_screen = |p: Pos2| {
+            Pos2::new(
+                image_rect.min.x + p.x * image_rect.width() / image.width() as f32,
+                image_rect.min.y + p.y * image_rect.height() / image.height() as f32,
+            )
+        };
+
+        // Every lane has the same whole number of pixels, like in ffmpeg's separate mode
+        let lanes = utils::channel_lanes(&self.settings, audio_info);
+        let horizontal = self.settings.horizontal && !custom_legend;
+        let lane_size = if horizontal {
+            ((max.x - min.x) as u32 / lanes) as f32 * image_rect.width() / image.width() as f32
+        } else {
+            ((max.y - min.y) as u32 / lanes) as f32 * image_rect.height() / image.height() as f32
+        };
+
+        Some(SpectrogramArea {
+            rect: Rect::from_min_max(to_screen(min), to_screen(max)),
+            lanes: lanes as usize,
+            lane_size,
+            horizontal,
+            time_range: view.time_range(audio_info.duration),
+            freq_axis: FrequencyAxis {
+                scale: self.settings.frequency_scale,
+                range: view.frequency_range(audio_info.sample_rate as f32 / 2.0),
+            },
+        })
+    }
+
+    /// Updates the readout for the pointer hovering the image shown in `image_rect`
+    /// and draws the crosshair if enabled.
+    pub(super) fn handle_cursor(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        image_rect: Rect,
+        area: Option<&SpectrogramArea>,
+    ) {
+        let readout =
+            area.and_then(|area| self.cursor_readout_at(&self.tab, response, image_rect, area));
+        if readout != self.cursor_readout {
+            self.cursor_readout = readout;
+            // The status bar is drawn before the image, show the new values right away
+            ui.ctx().request_repaint();
+        }
+
+        if let (Some(area), Some(pointer), true) =
+            (area, response.hover_pos(), self.settings.crosshair)
+        {
+            if area.rect.contains(pointer) {
+                draw_crosshair(ui, area.rect, pointer);
+            }
+        }
+    }
+
+    pub(super) fn cursor_readout_at(
+        &self,
+        tab: &Tab,
+        response: &egui::Response,
+        image_rect: Rect,
+        area: &SpectrogramArea,
+    ) -> Option<CursorReadout> {
+        let pointer = response.hover_pos()?;
+        if !area.rect.contains(pointer) {
+            return None;
+        }
+        let image = tab.final_image.as_ref()?;
+        let point = area.point_at(pointer);
+        let delta_range = tab
+            .difference
+            .as_ref()
+            .filter(|difference| difference.mode == DifferenceMode::Delta)
+            .map(|difference| difference.range_db);
+
+        let level_db = if tab.is_generating && !self.settings.live_mode {
+            None
+        } else {
+            let x = ((pointer.x - image_rect.min.x) * image.width() as f32 / image_rect.width())
+                as usize;
+            let y = ((pointer.y - image_rect.min.y) * image.height() as f32 / image_rect.height())
+                as usize;
+            let color = image[(x.min(image.width() - 1), y.min(image.height() - 1))];
+            let color = [color.r(), color.g(), color.b()];
+            match delta_range {
+                Some(range) => {
+                    let value = palettes::value_of_color(
+                        palettes::DIVERGING,
+                        self.settings.saturation,
+                        color,
+                    );
+                    Some(diff::delta_from_value(value, range))
+                }
+                None => {
+                    let palette = palettes::get_palette(self.settings.color_scheme);
+                    let value = palettes::value_of_color(palette, self.settings.saturation, color);
+                    let amplitude = stft::amplitude_from_value(value, &self.settings);
+                    Some(20.0 * (amplitude / self.settings.gain.max(f32::EPSILON)).log10())
+                }
+            }
+        };
+
+        Some(CursorReadout {
+            time: point.time,
+            frequency: point.frequency,
+            channel: (area.lanes > 1).then_some(point.lane),
+            level_db,
+            relative: delta_range.is_some(),
+        })
+    }
+
+    pub(super) fn show_status_bar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            self.show_transport(ui);
+            if let Some(readout) = self.cursor_readout {
+                ui.label(format!("Time: {}", utils::format_time(readout.time)));
+                ui.separator();
+                ui.label(format!("Frequency: {:.0} Hz", readout.frequency));
+                if let Some(level_db) = readout.level_db {
+                    ui.separator();
+                    if readout.relative {
+                        ui.label(format!("Difference: {:+.1} dB", level_db));
+                    } else if level_db.is_finite() {
+                        ui.label(format!("Level: {:.1} dBFS", level_db));
+                    } else {
+                        ui.label("Level: -inf dBFS");
+                    }
+                }
+                if let Some(channel) = readout.channel {
+                    ui.separator();
+                    match self.tab.audio_info.as_ref() {
+                        Some(info) if channel < info.channel_names.len() => {
+                            // Lanes of a channel selection show the channel number of the stream
+                            let number = info
+                                .source
+                                .channels
+                                .get(channel)
+                                .copied()
+                                .unwrap_or(channel)
+                                + 1;
+                            ui.label(format!(
+                                "Channel: {} ({})",
+                                number, info.channel_names[channel]
+                            ))
+                        }
+                        _ => ui.label(format!("Channel: {}", channel + 1)),
+                    };
+                }
+            } else if self.tab.final_image.is_some()
+                && self.settings.legend
+                && !self.settings.use_custom_legend()
+            {
+                ui.weak("Cursor readout and zoom are not available with the ffmpeg legend.");
+            } else if self.tab.final_image.is_some() {
+                ui.weak("Hover the spectrogram to read values, drag or scroll to zoom.");
+            }
+
+            self.show_zoom_controls(ui);
+        });
+    }
+}
+
+/// Draws horizontal and vertical lines through `pos`, clipped to `rect`.
+pub(super) fn draw_crosshair(ui: &egui::Ui, rect: Rect, pos: Pos2) {
+    let stroke = Stroke::new(1.0, Color32::from_white_alpha(160));
+    let painter = ui.painter_at(rect);
+    painter.vline(pos.x, rect.y_range(), stroke);
+    painter.hline(rect.x_range(), pos.y, stroke);
+}
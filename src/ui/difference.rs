@@ -0,0 +1,90 @@
+use eframe::egui;
+
+use super::MyApp;
+use crate::settings::{Difference, DifferenceMode};
+
+impl MyApp {
+    /// Opens a tab with the difference between the shown file and the file of the tab at `index`.
+    pub(super) fn open_difference(&mut self, ctx: &egui::Context, index: usize) {
+        let (Some(input_path), Some(other_path)) = (
+            self.tab.input_path.clone(),
+            self.tab_at(index).input_path.clone(),
+        ) else {
+            return;
+        };
+        let source = self.settings.source.clone();
+        self.open_file(input_path);
+        if source != self.settings.source {
+            self.set_source(source);
+        }
+
+        let mut difference = Difference::new(other_path);
+        difference.align = true;
+        self.tab.difference = Some(difference);
+        self.tab.start_alignment(ctx);
+    }
+
+    /// Difference options of the shown tab, or a menu to compare it with another tab.
+    pub(super) fn show_difference_controls(&mut self, ui: &mut egui::Ui) {
+        let Some(mut difference) = self.tab.difference.clone() else {
+            let others: Vec<(usize, String)> = self
+                .compared_tabs()
+                .into_iter()
+                .filter(|&index| index != self.active_tab)
+                .map(|index| (index, self.tab_at(index).title()))
+                .collect();
+            if self.tab.input_path.is_none() || others.is_empty() {
+                return;
+            }
+            ui.menu_button("Difference with", |ui| {
+                for (index, title) in others {
+                    if ui.button(title).clicked() {
+                        self.open_difference(ui.ctx(), index);
+                        ui.close();
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Open a tab with the difference between this file and another one.");
+            return;
+        };
+
+        egui::ComboBox::from_label("Difference")
+            .selected_text(difference.mode.to_string())
+            .width(110.0)
+            .show_ui(ui, |ui| {
+                for mode in DifferenceMode::VALUES {
+                    ui.selectable_value(&mut difference.mode, mode, mode.to_string());
+                }
+            });
+        if difference.mode == DifferenceMode::Delta {
+            ui.add(
+                egui::DragValue::new(&mut difference.range_db)
+                    .range(1.0..=120.0)
+                    .speed(0.5)
+                    .prefix("±")
+                    .suffix(" dB"),
+            )
+            .on_hover_text("Largest level difference shown by the palette.");
+        }
+        let aligning = self.tab.offset_receiver.is_some();
+        let align_changed = ui
+            .checkbox(
+                &mut difference.align,
+                if aligning { "Align …" } else { "Align" },
+            )
+            .on_hover_text(format!(
+                "Compensate the delay of the second file, e.g. encoder delay. Offset: {:+.1} ms",
+                difference.offset * 1000.0
+            ))
+            .changed();
+
+        if Some(&difference) != self.tab.difference.as_ref() {
+            self.tab.difference = Some(difference);
+            if align_changed {
+                self.tab.start_alignment(ui.ctx());
+            }
+            self.tab.stale = true;
+        }
+    }
+}
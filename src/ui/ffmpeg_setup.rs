@@ -36,7 +36,7 @@ impl FfmpegSetup {
             println!("Downloading FFmpeg to {}", ff_paths.directory.display());
 
             // Create dir
-            let _ = tx.send(format!("Creating directory..."));
+            let _ = tx.send("Creating directory...".to_string());
             ctx_clone.request_repaint();
             if let Err(e) = std::fs::create_dir_all(&ff_paths.directory) {
                 let _ = tx.send(format!("Error: {}", e));
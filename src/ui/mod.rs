@@ -1,35 +1,54 @@
 use eframe::egui::{self, Color32, ColorImage};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{self, Receiver};
-use std::sync::Arc;
-use std::thread;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Receiver;
 
 use crate::legend;
-use crate::settings::AppSettings;
+use crate::settings::{AppSettings, AudioSource};
 use crate::utils;
 
 pub mod ffmpeg_setup;
 pub use ffmpeg_setup::FfmpegSetup;
+mod channels;
+mod compare;
+mod cursor;
+mod difference;
+use cursor::CursorReadout;
+mod tab;
+use tab::Tab;
+mod render;
 mod settings_panel;
+mod transport;
 mod window_about;
 mod window_help;
+mod window_hires;
 mod window_keybindings;
 mod window_legend_settings;
+mod window_loudness;
+mod window_spectrum;
+mod zoom;
+
+/// Height of the bottom status bar in logical points.
+const STATUS_BAR_HEIGHT: f32 = 24.0;
 
 pub struct MyApp {
-    texture: Option<egui::TextureHandle>,
-    final_image: Option<eframe::egui::ColorImage>,
-    input_path: Option<String>,
+    /// The shown tab, its slot in `tabs` is empty until another tab is shown.
+    tab: Tab,
+    tabs: Vec<Tab>,
+    active_tab: usize,
     settings: AppSettings,
-    is_generating: bool,
-    image_receiver: Option<Receiver<Option<ColorImage>>>,
-    spectrogram_slice_position: usize,
     about_window_open: bool,
     keybindings_window_open: bool,
     help_window_open: bool,
     legend_settings_window_open: bool,
-    audio_info: Option<utils::AudioInfo>,
-    generation_cancel_token: Option<Arc<AtomicBool>>,
+    loudness_window_open: bool,
+    hires_window_open: bool,
+    spectrum_window_open: bool,
+    cursor_readout: Option<CursorReadout>,
+    zoom_drag_start: Option<egui::Pos2>,
+    /// Lane under the pointer when the channel menu was opened.
+    channel_menu_lane: Option<usize>,
+    file_receiver: Option<Receiver<Vec<String>>>,
+    compare: bool,
 
     // Keybinding triggers
     trigger_open_file: bool,
@@ -45,6 +64,14 @@ pub struct MyApp {
     trigger_gain_down: bool,
     trigger_saturation_up: bool,
     trigger_saturation_down: bool,
+    trigger_zoom_out: bool,
+    trigger_reset_zoom: bool,
+    trigger_play_pause: bool,
+    trigger_seek_back: bool,
+    trigger_seek_forward: bool,
+    trigger_tab_offset: isize,
+    trigger_close_tab: bool,
+    trigger_compare: bool,
 }
 
 impl MyApp {
@@ -53,25 +80,27 @@ impl MyApp {
         input_path: Option<String>,
         app_settings: AppSettings,
     ) -> Self {
-        let audio_info = if let Some(path) = &input_path {
-            utils::get_audio_info(path)
-        } else {
-            None
+        let tab = Tab {
+            final_image: image,
+            ..input_path.map(Tab::open).unwrap_or_default()
         };
         Self {
-            texture: None,
-            final_image: image,
-            input_path,
+            tab,
+            tabs: vec![Tab::default()],
+            active_tab: 0,
             settings: app_settings,
-            is_generating: false,
-            image_receiver: None,
-            spectrogram_slice_position: 0,
             about_window_open: false,
             keybindings_window_open: false,
             help_window_open: false,
             legend_settings_window_open: false,
-            audio_info,
-            generation_cancel_token: None,
+            loudness_window_open: false,
+            hires_window_open: false,
+            spectrum_window_open: false,
+            cursor_readout: None,
+            zoom_drag_start: None,
+            channel_menu_lane: None,
+            file_receiver: None,
+            compare: false,
 
             // Keybinding triggers
             trigger_open_file: false,
@@ -87,123 +116,125 @@ impl MyApp {
             trigger_gain_down: false,
             trigger_saturation_up: false,
             trigger_saturation_down: false,
+            trigger_zoom_out: false,
+            trigger_reset_zoom: false,
+            trigger_play_pause: false,
+            trigger_seek_back: false,
+            trigger_seek_forward: false,
+            trigger_tab_offset: 0,
+            trigger_close_tab: false,
+            trigger_compare: false,
         }
     }
 
     fn regenerate_spectrogram(&mut self, ctx: &egui::Context) {
-        if self.input_path.is_none() {
+        if self.tab.input_path.is_none() {
             return;
         }
-
-        if let Some(token) = &self.generation_cancel_token {
-            token.store(true, Ordering::Relaxed);
+        // A difference is rendered once the delay between the files is known
+        if self.tab.offset_receiver.is_some() {
+            self.tab.stale = true;
+            return;
         }
 
         if self.settings.remember_settings {
             self.settings.save();
         }
 
-        self.is_generating = true;
-        let input_path = self.input_path.clone().unwrap();
-
-        let (sender, receiver) = mpsc::channel();
-        self.image_receiver = Some(receiver);
+        let (width, height) = self.render_size();
+        let settings = self.tab_settings(self.active_tab);
+        self.tab.start_render(ctx, &settings, width, height);
+        // The plot follows the frequency axis and level range
+        self.tab.spectrum_plot = None;
+
+        // Compared tabs share the view, so they are rendered together
+        if self.is_comparing() {
+            for index in self.compared_tabs() {
+                if index != self.active_tab {
+                    let settings = self.tab_settings(index);
+                    self.tabs[index].start_render(ctx, &settings, width, height);
+                }
+            }
+        }
+    }
 
-        let (width, height) = if self.settings.custom_resolution || self.settings.resize_with_window
-        {
-            (self.settings.resolution[0], self.settings.resolution[1])
-        } else {
-            (500, 320)
+    /// Switches to another audio stream or channel selection of the current file.
+    fn set_source(&mut self, source: AudioSource) {
+        let Some(path) = &self.tab.input_path else {
+            return;
+        };
+        let Some(audio_info) = utils::get_audio_info(path, &source) else {
+            return;
         };
+        if audio_info.channels < 2 {
+            self.settings.split_channels = false;
+        }
+        // Mid/Side only changes the spectrogram, the whole-file analyses stay valid
+        if source.without_mid_side() != self.settings.source.without_mid_side() {
+            self.reset_cutoff();
+            self.reset_loudness();
+            self.reset_hires();
+        }
+        self.settings.source = source;
+        self.tab.audio_info = Some(audio_info);
+        self.reset_spectrum();
+        self.stop_playback();
+    }
 
-        let use_custom_legend =
-            self.settings.legend && (self.settings.custom_legend || self.settings.live_mode);
-        let mut thread_settings = self.settings.clone();
-
-        if use_custom_legend {
-            self.spectrogram_slice_position = 0;
-            let filename = std::path::Path::new(&input_path)
-                .file_name()
-                .and_then(|s| s.to_str())
-                .unwrap_or("Unknown File");
-            let ffmpeg_settings = format!(
-                "{}, {}, {}",
-                self.settings.win_func.to_string(),
-                self.settings.scale.to_string(),
-                self.settings.color_scheme.to_string()
-            );
-            let audio_info = self.audio_info.clone();
-
-            let legend_rgba = legend::draw_legend(
-                width,
-                height,
-                filename,
-                &ffmpeg_settings,
-                audio_info,
-                self.settings.saturation,
-                self.settings.color_scheme,
-                self.settings.split_channels,
-                self.settings.custom_legend_bg_color,
-                self.settings.custom_legend_text_color,
-                self.settings.custom_legend_line_color,
-            );
-            let legend_color_image = utils::rgba_image_to_color_image(&legend_rgba);
-
-            self.final_image = Some(legend_color_image.clone());
-            self.texture =
-                Some(ctx.load_texture("spectrogram", legend_color_image, Default::default()));
-
-            // Force ffmpeg legend off when using custom one
-            thread_settings.legend = false;
-        } else if self.settings.live_mode {
-            // In live mode, even without a legend, we need a canvas to draw on.
-            self.spectrogram_slice_position = 0;
-            let empty_canvas = ColorImage::new(
-                [width as usize, height as usize],
-                vec![Color32::BLACK; (width * height) as usize],
-            );
-            self.final_image = Some(empty_canvas.clone());
-            self.texture = Some(ctx.load_texture("spectrogram", empty_canvas, Default::default()));
-            thread_settings.legend = false;
-        } else {
-            self.final_image = None;
-            self.texture = None;
-        }
-
-        let ctx_clone = ctx.clone();
-        let cancel_token = Arc::new(AtomicBool::new(false));
-        self.generation_cancel_token = Some(cancel_token.clone());
-
-        thread::spawn(move || {
-            if thread_settings.live_mode {
-                utils::stream_spectrogram_frames(
-                    sender,
-                    &input_path,
-                    &thread_settings,
-                    width,
-                    height,
-                    cancel_token,
-                );
-            } else {
-                let image = utils::generate_spectrogram_in_memory(
-                    &input_path,
-                    &thread_settings,
-                    width,
-                    height,
-                    cancel_token,
-                );
-                if let Some(img) = image {
-                    sender.send(Some(img)).ok();
-                }
-            }
-            ctx_clone.request_repaint();
-        });
+    /// Resets the state tied to the previous file.
+    fn file_changed(&mut self) {
+        self.reset_cutoff();
+        self.reset_loudness();
+        self.reset_hires();
+        self.reset_spectrum();
+        self.clear_zoom();
+        self.stop_playback();
+    }
+
+    /// Forgets the cutoff of the previous file and stops its analysis.
+    fn reset_cutoff(&mut self) {
+        if let Some(token) = self.tab.cutoff_cancel_token.take() {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.tab.cutoff_receiver = None;
+        self.tab.cutoff_report = None;
+    }
+
+    /// Forgets the loudness of the previous file or stream and stops its measurement.
+    fn reset_loudness(&mut self) {
+        if let Some(token) = self.tab.loudness_cancel_token.take() {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.tab.loudness_receiver = None;
+        self.tab.loudness_report = None;
+    }
+
+    /// Forgets the resolution check of the previous file or stream and stops it.
+    fn reset_hires(&mut self) {
+        if let Some(token) = self.tab.hires_cancel_token.take() {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.tab.hires_receiver = None;
+        self.tab.hires_report = None;
+    }
+
+    /// Forgets the average spectrum of the previous file or stream and stops its measurement.
+    fn reset_spectrum(&mut self) {
+        if let Some(token) = self.tab.spectrum_cancel_token.take() {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.tab.spectrum_receiver = None;
+        self.tab.spectrum_request = None;
+        self.tab.spectrum = None;
+        self.tab.spectrum_plot = None;
     }
 }
 
 impl eframe::App for MyApp {
     fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
         let ctx = ui.ctx().clone();
+        // Space, arrows and keys that edit text belong to the focused widget, if there is one
+        let widget_focused = ctx.memory(|m| m.focused().is_some());
         ctx.input(|i| {
             if i.key_pressed(egui::Key::Escape) {
                 // https://github.com/emilk/egui/discussions/4103#discussioncomment-9225022
@@ -213,7 +244,40 @@ impl eframe::App for MyApp {
                 });
             }
 
-            if !self.is_generating {
+            if !widget_focused {
+                if i.key_pressed(egui::Key::Space) {
+                    self.trigger_play_pause = true;
+                }
+                if i.key_pressed(egui::Key::ArrowLeft) {
+                    self.trigger_seek_back = true;
+                }
+                if i.key_pressed(egui::Key::ArrowRight) {
+                    self.trigger_seek_forward = true;
+                }
+                if !self.tab.is_generating {
+                    if i.key_pressed(egui::Key::X) {
+                        self.settings.crosshair = !self.settings.crosshair;
+                    }
+                    if i.key_pressed(egui::Key::V) {
+                        self.trigger_compare = true;
+                    }
+                    if i.key_pressed(egui::Key::Backspace) {
+                        self.trigger_zoom_out = true;
+                    }
+                    if i.key_pressed(egui::Key::Home) {
+                        self.trigger_reset_zoom = true;
+                    }
+                }
+            }
+
+            if i.modifiers.ctrl && i.key_pressed(egui::Key::Tab) {
+                self.trigger_tab_offset = if i.modifiers.shift { -1 } else { 1 };
+            }
+            if i.modifiers.ctrl && i.key_pressed(egui::Key::W) {
+                self.trigger_close_tab = true;
+            }
+
+            if !self.tab.is_generating {
                 if i.key_pressed(egui::Key::F1) {
                     self.help_window_open = !self.help_window_open;
                 }
@@ -271,17 +335,30 @@ impl eframe::App for MyApp {
             }
         });
 
-        // Handle file drop
-        if !self.is_generating {
-            let dropped_files = ctx.input(|i| i.raw.dropped_files.clone());
-            if !dropped_files.is_empty() {
-                if let Some(path) = dropped_files.first().and_then(|f| f.path.as_ref()) {
-                    let path_str = path.to_string_lossy().to_string();
-                    self.audio_info = utils::get_audio_info(&path_str);
-                    self.input_path = Some(path_str);
-                    self.regenerate_spectrogram(&ctx);
-                }
-            }
+        self.handle_forwarded_files();
+
+        // Handle file drop, every file gets its own tab
+        let dropped_files = ctx.input(|i| i.raw.dropped_files.clone());
+        let dropped_paths: Vec<String> = dropped_files
+            .iter()
+            .filter_map(|f| f.path.as_ref())
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        if !dropped_paths.is_empty() {
+            self.open_files(dropped_paths);
+        }
+
+        if self.trigger_tab_offset != 0 {
+            self.cycle_tab(self.trigger_tab_offset);
+            self.trigger_tab_offset = 0;
+        }
+        if self.trigger_close_tab {
+            self.trigger_close_tab = false;
+            self.close_tab(self.active_tab);
+        }
+        if self.trigger_compare {
+            self.trigger_compare = false;
+            self.toggle_compare(&ctx);
         }
 
         let mut trigger_regeneration_due_to_resize = false;
@@ -291,8 +368,12 @@ impl eframe::App for MyApp {
 
             // Legend margins in physical pixels (from src/legend.rs)
             let margin_w = (legend::LEFT_MARGIN + legend::RIGHT_MARGIN) as f32;
-            let margin_h = (legend::TOP_MARGIN + legend::BOTTOM_MARGIN) as f32;
-            let top_bar_h = 39.0; // Height of the UI header in logical points
+            let margin_h =
+                (legend::TOP_MARGIN + legend::bottom_margin(self.settings.strips().len())) as f32;
+            let mut top_bar_h = 39.0 + STATUS_BAR_HEIGHT; // Height of the UI header and status bar in logical points
+            if self.tabs.len() > 1 {
+                top_bar_h += tab::TAB_BAR_HEIGHT;
+            }
 
             let new_width = ((inner_size.x * pixels_per_point) - margin_w).max(100.0) as u32;
             let new_height =
@@ -306,122 +387,59 @@ impl eframe::App for MyApp {
         }
 
         if trigger_regeneration_due_to_resize {
+            self.mark_background_tabs_stale();
             self.regenerate_spectrogram(&ctx);
         }
 
-        let use_custom_legend =
-            self.settings.legend && (self.settings.custom_legend || self.settings.live_mode);
-
-        if self.is_generating {
-            if let Some(receiver) = &self.image_receiver {
-                if self.settings.live_mode {
-                    // Live mode (always custom legend): receive slices and draw them
-                    for slice in receiver.try_iter() {
-                        if let Some(slice) = slice {
-                            if let Some(image) = self.final_image.as_mut() {
-                                let slice_width = slice.width();
-
-                                let (spec_width, x_offset, y_offset) = if use_custom_legend {
-                                    (
-                                        image.width()
-                                            - (legend::LEFT_MARGIN as usize
-                                                + legend::RIGHT_MARGIN as usize),
-                                        legend::LEFT_MARGIN as usize,
-                                        legend::TOP_MARGIN as usize,
-                                    )
-                                } else {
-                                    (image.width(), 0, 0)
-                                };
-
-                                if self.spectrogram_slice_position + slice_width <= spec_width {
-                                    for y in 0..slice.height() {
-                                        for x in 0..slice_width {
-                                            let dest_x =
-                                                self.spectrogram_slice_position + x + x_offset;
-                                            let dest_y = y + y_offset;
-                                            if dest_x < image.width() && dest_y < image.height() {
-                                                image[(dest_x, dest_y)] = slice[(x, y)];
-                                            }
-                                        }
-                                    }
-                                    if let Some(texture) = self.texture.as_mut() {
-                                        texture.set(image.clone(), Default::default());
-                                    }
-                                    self.spectrogram_slice_position += slice_width;
-                                }
-                            }
-                        }
-                    }
+        if self.trigger_zoom_out {
+            self.trigger_zoom_out = false;
+            self.zoom_out(&ctx);
+        }
+        if self.trigger_reset_zoom {
+            self.trigger_reset_zoom = false;
+            self.reset_zoom(&ctx);
+        }
+        self.apply_pending_zoom(&ctx);
 
-                    // A bit of a hack to check if the channel is disconnected
-                    if let Err(mpsc::TryRecvError::Disconnected) = receiver.try_recv() {
-                        self.is_generating = false;
-                        self.image_receiver = None;
+        if self.trigger_play_pause {
+            self.trigger_play_pause = false;
+            self.toggle_playback();
+        }
+        if self.trigger_seek_back {
+            self.trigger_seek_back = false;
+            self.seek_playback_by(-transport::SEEK_STEP);
+        }
+        if self.trigger_seek_forward {
+            self.trigger_seek_forward = false;
+            self.seek_playback_by(transport::SEEK_STEP);
+        }
 
-                        // Save window size after live spectrogram is ready
-                        if self.settings.save_window_size {
-                            let inner_size = ui.viewport_rect().size();
-                            self.settings.window_size = [inner_size.x, inner_size.y];
-                            self.settings.save();
-                        }
-                    }
-                } else {
-                    // Normal mode: receive the full spectrogram
-                    if let Ok(maybe_image) = receiver.try_recv() {
-                        self.is_generating = false;
-                        self.image_receiver = None;
-                        if let Some(new_spectrogram) = maybe_image {
-                            if use_custom_legend {
-                                // Composite onto custom legend
-                                if let Some(final_image) = self.final_image.as_mut() {
-                                    for y in 0..new_spectrogram.height() {
-                                        for x in 0..new_spectrogram.width() {
-                                            let dest_x = x + legend::LEFT_MARGIN as usize;
-                                            let dest_y = y + legend::TOP_MARGIN as usize;
-                                            if dest_x < final_image.width()
-                                                && dest_y < final_image.height()
-                                            {
-                                                final_image[(dest_x, dest_y)] =
-                                                    new_spectrogram[(x, y)];
-                                            }
-                                        }
-                                    }
-                                    self.texture = Some(ctx.load_texture(
-                                        "spectrogram",
-                                        final_image.clone(),
-                                        Default::default(),
-                                    ));
-                                }
-                            } else {
-                                // Display ffmpeg-generated image directly
-                                self.texture = Some(ctx.load_texture(
-                                    "spectrogram",
-                                    new_spectrogram.clone(),
-                                    Default::default(),
-                                ));
-                                self.final_image = Some(new_spectrogram);
-                            }
-
-                            // Save window size after spectrogram is ready
-                            if self.settings.save_window_size {
-                                let inner_size = ui.viewport_rect().size();
-                                self.settings.window_size = [inner_size.x, inner_size.y];
-                                self.settings.save();
-                            }
-                        }
-                    }
-                }
-                ctx.request_repaint();
+        let settings = self.tab_settings(self.active_tab);
+        if self.tab.poll_render(&ctx, &settings) {
+            // Save window size after spectrogram is ready
+            if self.settings.save_window_size {
+                let inner_size = ui.viewport_rect().size();
+                self.settings.window_size = [inner_size.x, inner_size.y];
+                self.settings.save();
             }
         }
-
-        if self.texture.is_none() && self.final_image.is_some() {
-            if let Some(image) = self.final_image.as_ref() {
-                self.texture =
-                    Some(ctx.load_texture("spectrogram", image.clone(), Default::default()));
+        for index in 0..self.tabs.len() {
+            if index != self.active_tab {
+                let settings = self.tab_settings(index);
+                self.tabs[index].poll_render(&ctx, &settings);
             }
         }
 
+        if self.tabs.len() > 1 {
+            egui::Panel::top("tab_bar")
+                .exact_size(tab::TAB_BAR_HEIGHT)
+                .show(ui, |ui| self.show_tab_bar(ui));
+        }
+
+        egui::Panel::bottom("status_bar")
+            .exact_size(STATUS_BAR_HEIGHT)
+            .show(ui, |ui| self.show_status_bar(ui));
+
         egui::CentralPanel::default()
             .frame(egui::Frame::NONE.fill(Color32::BLACK))
             .show(ui, |ui| {
@@ -442,17 +460,22 @@ impl eframe::App for MyApp {
                         });
                     });
 
-                if self.is_generating && !self.settings.live_mode {
+                if self.is_comparing() {
+                    self.show_compare_view(ui);
+                    return;
+                }
+
+                if self.tab.is_generating && !self.settings.live_mode {
                     ui.centered_and_justified(|ui| {
                         ui.spinner();
                         // ui.label("Generating...");
                     });
                 }
 
-                if let Some(texture) = &self.texture {
+                let texture = self.tab.texture.as_ref().map(|t| (t.id(), t.size_vec2()));
+                if let Some((texture_id, image_size_physical)) = texture {
                     let available_size = ui.available_size();
                     let pixels_per_point = ctx.pixels_per_point();
-                    let image_size_physical = texture.size_vec2();
                     let image_size_logical = image_size_physical / pixels_per_point;
 
                     let image_aspect = image_size_physical.x / image_size_physical.y;
@@ -466,17 +489,35 @@ impl eframe::App for MyApp {
                         egui::vec2(available_size.y * image_aspect, available_size.y)
                     };
 
-                    ui.centered_and_justified(|ui| {
+                    let display_size =
                         if self.settings.custom_resolution || self.settings.resize_with_window {
-                            ui.image((texture.id(), fit_size));
+                            fit_size
                         } else {
                             // Use logical size to avoid oversized images on HiDPI
-                            ui.image((texture.id(), image_size_logical));
+                            image_size_logical
+                        };
+                    ui.centered_and_justified(|ui| {
+                        let response = ui.add(
+                            egui::Image::new((texture_id, display_size))
+                                .sense(egui::Sense::click_and_drag()),
+                        );
+                        let image_rect =
+                            egui::Rect::from_center_size(response.rect.center(), display_size);
+                        let area = self.spectrogram_area(image_rect);
+                        if let Some(area) = &area {
+                            self.handle_zoom(ui, &response, area);
+                            self.handle_playhead(ui, &response, area);
+                            self.handle_channel_menu(ui, &response, area);
                         }
+                        self.handle_cursor(ui, &response, image_rect, area.as_ref());
                     });
-                } else if !self.is_generating {
+                } else {
+                    self.cursor_readout = None;
+                }
+
+                if self.tab.texture.is_none() && !self.tab.is_generating {
                     ui.centered_and_justified(|ui| {
-                        if self.input_path.is_some() {
+                        if self.tab.input_path.is_some() {
                             ui.label("Failed to generate or load spectrogram.");
                         } else {
                             ui.label("Open a file to begin.");
@@ -497,6 +538,56 @@ impl eframe::App for MyApp {
             window_help::show(&ctx, &mut self.help_window_open);
         }
 
+        if self.loudness_window_open {
+            if self.tab.difference.is_none() {
+                self.tab.start_loudness_measurement(&ctx);
+            }
+            let legend_changed = window_loudness::show(
+                &ctx,
+                &mut self.loudness_window_open,
+                self.tab.loudness_report.as_ref(),
+                self.tab.loudness_receiver.is_some(),
+                &mut self.settings,
+            );
+            if legend_changed {
+                self.mark_background_tabs_stale();
+                self.regenerate_spectrogram(&ctx);
+            }
+        }
+
+        if self.hires_window_open {
+            if self.tab.difference.is_none() {
+                self.tab.start_hires_detection(&ctx);
+            }
+            window_hires::show(
+                &ctx,
+                &mut self.hires_window_open,
+                self.tab.hires_report.as_ref(),
+                self.tab.hires_receiver.is_some(),
+            );
+        }
+
+        if self.spectrum_window_open {
+            if self.tab.difference.is_none() {
+                self.tab.start_spectrum_analysis(&ctx, &self.settings);
+                self.tab.update_spectrum_plot(&ctx, &self.settings);
+            }
+            let save_clicked = window_spectrum::show(
+                &ctx,
+                &mut self.spectrum_window_open,
+                self.tab.spectrum_plot.as_ref().map(|(_, texture)| texture),
+                self.tab.spectrum_receiver.is_some(),
+            );
+            if let (true, Some(spectrum), Some((plot, _)), Some(path)) = (
+                save_clicked,
+                &self.tab.spectrum,
+                &self.tab.spectrum_plot,
+                &self.tab.input_path,
+            ) {
+                utils::save_spectrum(spectrum, plot, path, self.settings.jpeg_quality);
+            }
+        }
+
         if self.legend_settings_window_open {
             let previous_bg = self.settings.custom_legend_bg_color;
             let previous_text = self.settings.custom_legend_text_color;
@@ -512,6 +603,7 @@ impl eframe::App for MyApp {
                 || previous_text != self.settings.custom_legend_text_color
                 || previous_line != self.settings.custom_legend_line_color
             {
+                self.mark_background_tabs_stale();
                 self.regenerate_spectrogram(&ctx);
             }
         }
@@ -0,0 +1,607 @@
+use eframe::egui::{self, Color32, ColorImage};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+use super::tab::Tab;
+use crate::cutoff;
+use crate::diff;
+use crate::hires;
+use crate::legend;
+use crate::loudness;
+use crate::settings::{AppSettings, Strip};
+use crate::spectrum::{self, SpectrumRequest};
+use crate::stereo;
+use crate::utils;
+use crate::waveform;
+
+/// Size of the frequency and level axes of the average spectrum plot.
+const SPECTRUM_PLOT_SIZE: [u32; 2] = [560, 280];
+
+impl Tab {
+    /// Renders the spectrogram of the tab in the background with `settings`.
+    pub fn start_render(
+        &mut self,
+        ctx: &egui::Context,
+        settings: &AppSettings,
+        width: u32,
+        height: u32,
+    ) {
+        let Some(input_path) = self.input_path.clone() else {
+            return;
+        };
+        self.stale = false;
+
+        if let Some(token) = &self.generation_cancel_token {
+            token.store(true, Ordering::Relaxed);
+        }
+
+        self.is_generating = true;
+
+        let (sender, receiver) = mpsc::channel();
+        self.image_receiver = Some(receiver);
+
+        let use_custom_legend = settings.use_custom_legend();
+        let mut thread_settings = settings.clone();
+
+        if use_custom_legend {
+            self.spectrogram_slice_position = 0;
+            let legend_color_image = utils::draw_legend_canvas(
+                &input_path,
+                settings,
+                self.audio_info.clone(),
+                width,
+                height,
+            );
+
+            self.final_image = Some(legend_color_image.clone());
+            self.texture =
+                Some(ctx.load_texture("spectrogram", legend_color_image, Default::default()));
+
+            // Force ffmpeg legend off when using custom one
+            thread_settings.legend = false;
+        } else if settings.live_mode {
+            // In live mode, even without a legend, we need a canvas to draw on.
+            self.spectrogram_slice_position = 0;
+            let empty_canvas = ColorImage::new(
+                [width as usize, height as usize],
+                vec![Color32::BLACK; (width * height) as usize],
+            );
+            self.final_image = Some(empty_canvas.clone());
+            self.texture = Some(ctx.load_texture("spectrogram", empty_canvas, Default::default()));
+            thread_settings.legend = false;
+        } else {
+            self.final_image = None;
+            self.texture = None;
+        }
+
+        let ctx_clone = ctx.clone();
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.generation_cancel_token = Some(cancel_token.clone());
+
+        thread::spawn(move || {
+            if thread_settings.live_mode {
+                utils::stream_spectrogram_frames(
+                    sender,
+                    &input_path,
+                    &thread_settings,
+                    width,
+                    height,
+                    cancel_token,
+                );
+            } else {
+                let image = utils::generate_spectrogram(
+                    &input_path,
+                    &thread_settings,
+                    width,
+                    height,
+                    cancel_token,
+                );
+                if let Some(img) = image {
+                    sender.send(Some(img)).ok();
+                }
+            }
+            ctx_clone.request_repaint();
+        });
+
+        if settings.detect_cutoff && self.cutoff_report.is_none() && settings.difference.is_none() {
+            self.start_cutoff_detection(ctx);
+        }
+        if (settings.loudness_legend || settings.clip_markers) && settings.difference.is_none() {
+            self.start_loudness_measurement(ctx);
+        }
+        self.start_waveform(ctx, settings, width);
+        self.start_phase_correlation(ctx, settings, width);
+    }
+
+    /// Reads the peak envelope of the rendered time range in the background,
+    /// if its strip is shown. Stops the reading of the previous render.
+    fn start_waveform(&mut self, ctx: &egui::Context, settings: &AppSettings, width: u32) {
+        if let Some(token) = self.waveform_cancel_token.take() {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.waveform_receiver = None;
+        self.waveform = None;
+        if !settings.strips().contains(&Strip::Waveform) {
+            return;
+        }
+        let (Some(input_path), Some(audio_info)) =
+            (self.input_path.clone(), self.audio_info.clone())
+        else {
+            return;
+        };
+        let time_range = settings.view.time_range(audio_info.duration);
+
+        let (sender, receiver) = mpsc::channel();
+        self.waveform_receiver = Some(receiver);
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.waveform_cancel_token = Some(cancel_token.clone());
+
+        let ctx_clone = ctx.clone();
+        thread::spawn(move || {
+            let envelopes = waveform::peak_envelope(
+                &input_path,
+                &audio_info,
+                time_range,
+                width as usize,
+                &cancel_token,
+            );
+            if !cancel_token.load(Ordering::Relaxed) {
+                sender.send(envelopes).ok();
+            }
+            ctx_clone.request_repaint();
+        });
+    }
+
+    /// Measures the phase correlation of the rendered time range in the background,
+    /// if its strip is shown. Stops the measurement of the previous render.
+    fn start_phase_correlation(&mut self, ctx: &egui::Context, settings: &AppSettings, width: u32) {
+        if let Some(token) = self.phase_correlation_cancel_token.take() {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.phase_correlation_receiver = None;
+        self.phase_correlation = None;
+        if !settings.strips().contains(&Strip::PhaseCorrelation) {
+            return;
+        }
+        let (Some(input_path), Some(audio_info)) =
+            (self.input_path.clone(), self.audio_info.clone())
+        else {
+            return;
+        };
+        let time_range = settings.view.time_range(audio_info.duration);
+
+        let (sender, receiver) = mpsc::channel();
+        self.phase_correlation_receiver = Some(receiver);
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.phase_correlation_cancel_token = Some(cancel_token.clone());
+
+        let ctx_clone = ctx.clone();
+        thread::spawn(move || {
+            let values = stereo::phase_correlation(
+                &input_path,
+                &audio_info,
+                time_range,
+                width as usize,
+                &cancel_token,
+            );
+            if !cancel_token.load(Ordering::Relaxed) {
+                sender.send(values).ok();
+            }
+            ctx_clone.request_repaint();
+        });
+    }
+
+    /// Measures the loudness of the file in the background, unless it's already known
+    /// or being measured.
+    pub fn start_loudness_measurement(&mut self, ctx: &egui::Context) {
+        if self.loudness_report.is_some() || self.loudness_receiver.is_some() {
+            return;
+        }
+        let (Some(input_path), Some(audio_info)) =
+            (self.input_path.clone(), self.audio_info.clone())
+        else {
+            return;
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        self.loudness_receiver = Some(receiver);
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.loudness_cancel_token = Some(cancel_token.clone());
+
+        let ctx_clone = ctx.clone();
+        thread::spawn(move || {
+            let report = utils::analysis_info(&input_path, &audio_info)
+                .and_then(|info| loudness::measure_loudness(&input_path, &info, &cancel_token));
+            if !cancel_token.load(Ordering::Relaxed) {
+                sender.send(report).ok();
+            }
+            ctx_clone.request_repaint();
+        });
+    }
+
+    /// Checks the resolution of the file in the background, unless it's already known
+    /// or being checked.
+    pub fn start_hires_detection(&mut self, ctx: &egui::Context) {
+        if self.hires_report.is_some() || self.hires_receiver.is_some() {
+            return;
+        }
+        let (Some(input_path), Some(audio_info)) =
+            (self.input_path.clone(), self.audio_info.clone())
+        else {
+            return;
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        self.hires_receiver = Some(receiver);
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.hires_cancel_token = Some(cancel_token.clone());
+
+        let ctx_clone = ctx.clone();
+        thread::spawn(move || {
+            let report = utils::analysis_info(&input_path, &audio_info)
+                .and_then(|info| hires::detect_hires(&input_path, &info, &cancel_token));
+            if !cancel_token.load(Ordering::Relaxed) {
+                sender.send(report).ok();
+            }
+            ctx_clone.request_repaint();
+        });
+    }
+
+    /// Measures the average spectrum of the rendered time range in the background, unless
+    /// it's already known or being measured with the same settings.
+    pub fn start_spectrum_analysis(&mut self, ctx: &egui::Context, settings: &AppSettings) {
+        let (Some(input_path), Some(audio_info)) =
+            (self.input_path.clone(), self.audio_info.clone())
+        else {
+            return;
+        };
+        let request = SpectrumRequest::new(settings, &audio_info);
+        if self.spectrum_request == Some(request) {
+            return;
+        }
+        if let Some(token) = self.spectrum_cancel_token.take() {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.spectrum = None;
+        self.spectrum_plot = None;
+        self.spectrum_request = Some(request);
+
+        let (sender, receiver) = mpsc::channel();
+        self.spectrum_receiver = Some(receiver);
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.spectrum_cancel_token = Some(cancel_token.clone());
+
+        let ctx_clone = ctx.clone();
+        thread::spawn(move || {
+            let spectrum =
+                spectrum::average_spectrum(&input_path, &audio_info, request, &cancel_token);
+            if !cancel_token.load(Ordering::Relaxed) {
+                sender.send(spectrum).ok();
+            }
+            ctx_clone.request_repaint();
+        });
+    }
+
+    /// Draws the plot of the average spectrum, if it's measured and not drawn yet.
+    pub fn update_spectrum_plot(&mut self, ctx: &egui::Context, settings: &AppSettings) {
+        if self.spectrum_plot.is_some() {
+            return;
+        }
+        let (Some(spectrum), Some(input_path)) = (&self.spectrum, &self.input_path) else {
+            return;
+        };
+        let plot = utils::rgba_image_to_color_image(&utils::draw_spectrum_plot(
+            input_path,
+            settings,
+            spectrum,
+            SPECTRUM_PLOT_SIZE[0],
+            SPECTRUM_PLOT_SIZE[1],
+        ));
+        let texture = ctx.load_texture("spectrum", plot.clone(), Default::default());
+        self.spectrum_plot = Some((plot, texture));
+    }
+
+    /// Estimates the delay between the files of the difference in the background, or resets
+    /// it if they are not aligned. Either way the difference is rendered again.
+    pub fn start_alignment(&mut self, ctx: &egui::Context) {
+        if let Some(token) = self.offset_cancel_token.take() {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.offset_receiver = None;
+        self.stale = true;
+        let (Some(input_path), Some(audio_info), Some(difference)) = (
+            self.input_path.clone(),
+            self.audio_info.clone(),
+            self.difference.as_mut(),
+        ) else {
+            return;
+        };
+        difference.offset = 0.0;
+        if !difference.align {
+            return;
+        }
+        let other_path = difference.path.clone();
+
+        let (sender, receiver) = mpsc::channel();
+        self.offset_receiver = Some(receiver);
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.offset_cancel_token = Some(cancel_token.clone());
+
+        let ctx_clone = ctx.clone();
+        thread::spawn(move || {
+            let offset = diff::estimate_offset(&input_path, &audio_info, &other_path);
+            if !cancel_token.load(Ordering::Relaxed) {
+                sender.send(offset).ok();
+            }
+            ctx_clone.request_repaint();
+        });
+    }
+
+    /// Analyzes the file in the background, unless it's already being analyzed.
+    fn start_cutoff_detection(&mut self, ctx: &egui::Context) {
+        if self.cutoff_receiver.is_some() {
+            return;
+        }
+        let (Some(input_path), Some(audio_info)) =
+            (self.input_path.clone(), self.audio_info.clone())
+        else {
+            return;
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        self.cutoff_receiver = Some(receiver);
+        let cancel_token = Arc::new(AtomicBool::new(false));
+        self.cutoff_cancel_token = Some(cancel_token.clone());
+
+        let ctx_clone = ctx.clone();
+        thread::spawn(move || {
+            let report = utils::analysis_info(&input_path, &audio_info)
+                .ok_or_else(|| "failed to read the channels".to_string())
+                .and_then(|info| cutoff::detect_cutoff(&input_path, &info, &cancel_token));
+            if !cancel_token.load(Ordering::Relaxed) {
+                let report = report.unwrap_or_else(|e| {
+                    eprintln!("Failed to detect the frequency cutoff: {}", e);
+                    None
+                });
+                sender.send(report).ok();
+            }
+            ctx_clone.request_repaint();
+        });
+    }
+
+    /// Receives the results of the background work, returns `true` once the render is finished.
+    pub fn poll_render(&mut self, ctx: &egui::Context, settings: &AppSettings) -> bool {
+        let use_custom_legend = settings.use_custom_legend();
+        let mut finished = false;
+
+        if let Some(receiver) = &self.offset_receiver {
+            if let Ok(offset) = receiver.try_recv() {
+                self.offset_receiver = None;
+                if let Some(difference) = self.difference.as_mut() {
+                    difference.offset = offset.unwrap_or(0.0);
+                }
+                self.stale = true;
+            }
+        }
+
+        if let Some(receiver) = &self.cutoff_receiver {
+            if let Ok(report) = receiver.try_recv() {
+                self.cutoff_receiver = None;
+                self.cutoff_report = report;
+                if !self.is_generating {
+                    self.apply_cutoff_marker(ctx, settings);
+                }
+            }
+        }
+
+        if let Some(receiver) = &self.loudness_receiver {
+            if let Ok(report) = receiver.try_recv() {
+                self.loudness_receiver = None;
+                self.loudness_report = report;
+                if !self.is_generating && self.draw_loudness_overlays(settings) {
+                    self.reload_texture(ctx);
+                }
+            }
+        }
+
+        if let Some(receiver) = &self.waveform_receiver {
+            if let Ok(envelopes) = receiver.try_recv() {
+                self.waveform_receiver = None;
+                self.waveform = envelopes;
+                if !self.is_generating && self.draw_waveform(settings) {
+                    self.reload_texture(ctx);
+                }
+            }
+        }
+
+        if let Some(receiver) = &self.phase_correlation_receiver {
+            if let Ok(values) = receiver.try_recv() {
+                self.phase_correlation_receiver = None;
+                self.phase_correlation = values;
+                if !self.is_generating && self.draw_phase_correlation(settings) {
+                    self.reload_texture(ctx);
+                }
+            }
+        }
+
+        if let Some(receiver) = &self.spectrum_receiver {
+            if let Ok(spectrum) = receiver.try_recv() {
+                self.spectrum_receiver = None;
+                self.spectrum = spectrum;
+                self.spectrum_plot = None;
+            }
+        }
+
+        if let Some(receiver) = &self.hires_receiver {
+            if let Ok(report) = receiver.try_recv() {
+                self.hires_receiver = None;
+                self.hires_report = report;
+            }
+        }
+
+        if self.is_generating {
+            if let Some(receiver) = &self.image_receiver {
+                if settings.live_mode {
+                    // Live mode (always custom legend): receive slices and draw them
+                    for slice in receiver.try_iter().flatten() {
+                        if let Some(image) = self.final_image.as_mut() {
+                            let slice_width = slice.width();
+
+                            let (spec_width, x_offset, y_offset) = if use_custom_legend {
+                                (
+                                    image.width()
+                                        - (legend::LEFT_MARGIN as usize
+                                            + legend::RIGHT_MARGIN as usize),
+                                    legend::LEFT_MARGIN as usize,
+                                    legend::TOP_MARGIN as usize,
+                                )
+                            } else {
+                                (image.width(), 0, 0)
+                            };
+
+                            if self.spectrogram_slice_position + slice_width <= spec_width {
+                                for y in 0..slice.height() {
+                                    for x in 0..slice_width {
+                                        let dest_x = self.spectrogram_slice_position + x + x_offset;
+                                        let dest_y = y + y_offset;
+                                        if dest_x < image.width() && dest_y < image.height() {
+                                            image[(dest_x, dest_y)] = slice[(x, y)];
+                                        }
+                                    }
+                                }
+                                if let Some(texture) = self.texture.as_mut() {
+                                    texture.set(image.clone(), Default::default());
+                                }
+                                self.spectrogram_slice_position += slice_width;
+                            }
+                        }
+                    }
+
+                    // A bit of a hack to check if the channel is disconnected
+                    if let Err(mpsc::TryRecvError::Disconnected) = receiver.try_recv() {
+                        self.is_generating = false;
+                        self.image_receiver = None;
+                        self.apply_overlays(ctx, settings);
+                        finished = true;
+                    }
+                } else {
+                    // Normal mode: receive the full spectrogram
+                    if let Ok(maybe_image) = receiver.try_recv() {
+                        self.is_generating = false;
+                        self.image_receiver = None;
+                        if let Some(new_spectrogram) = maybe_image {
+                            if use_custom_legend {
+                                // Composite onto custom legend
+                                if let Some(final_image) = self.final_image.as_mut() {
+                                    utils::composite_spectrogram(final_image, &new_spectrogram);
+                                    self.texture = Some(ctx.load_texture(
+                                        "spectrogram",
+                                        final_image.clone(),
+                                        Default::default(),
+                                    ));
+                                }
+                            } else {
+                                // Display ffmpeg-generated image directly
+                                self.texture = Some(ctx.load_texture(
+                                    "spectrogram",
+                                    new_spectrogram.clone(),
+                                    Default::default(),
+                                ));
+                                self.final_image = Some(new_spectrogram);
+                            }
+                            self.apply_overlays(ctx, settings);
+                            finished = true;
+                        }
+                    }
+                }
+                ctx.request_repaint();
+            }
+        }
+
+        if self.texture.is_none() && self.final_image.is_some() {
+            self.reload_texture(ctx);
+        }
+        finished
+    }
+
+    /// Draws the channel names and the cutoff and loudness overlays, if known, on the finished image.
+    fn apply_overlays(&mut self, ctx: &egui::Context, settings: &AppSettings) {
+        let (Some(audio_info), Some(image)) = (self.audio_info.as_ref(), self.final_image.as_mut())
+        else {
+            return;
+        };
+        utils::draw_channel_labels(image, settings, audio_info);
+        self.draw_cutoff_marker(settings);
+        self.draw_loudness_overlays(settings);
+        self.draw_waveform(settings);
+        self.draw_phase_correlation(settings);
+        self.reload_texture(ctx);
+    }
+
+    /// Draws the cutoff marker on the finished image, if the cutoff is known.
+    fn apply_cutoff_marker(&mut self, ctx: &egui::Context, settings: &AppSettings) {
+        if self.draw_cutoff_marker(settings) {
+            self.reload_texture(ctx);
+        }
+    }
+
+    fn draw_cutoff_marker(&mut self, settings: &AppSettings) -> bool {
+        if !settings.detect_cutoff {
+            return false;
+        }
+        let (Some(report), Some(audio_info), Some(image)) = (
+            self.cutoff_report.as_ref(),
+            self.audio_info.as_ref(),
+            self.final_image.as_mut(),
+        ) else {
+            return false;
+        };
+
+        utils::draw_cutoff_marker(image, settings, audio_info, report);
+        true
+    }
+
+    /// Draws the loudness summary and the clipping markers, if enabled and measured.
+    fn draw_loudness_overlays(&mut self, settings: &AppSettings) -> bool {
+        if !(settings.loudness_legend || settings.clip_markers) || settings.difference.is_some() {
+            return false;
+        }
+        let (Some(report), Some(audio_info), Some(image)) = (
+            self.loudness_report.as_ref(),
+            self.audio_info.as_ref(),
+            self.final_image.as_mut(),
+        ) else {
+            return false;
+        };
+        utils::draw_loudness_summary(image, settings, report);
+        utils::draw_clip_markers(image, settings, audio_info, &report.clipping);
+        true
+    }
+
+    fn draw_waveform(&mut self, settings: &AppSettings) -> bool {
+        let (Some(envelopes), Some(image)) = (self.waveform.as_ref(), self.final_image.as_mut())
+        else {
+            return false;
+        };
+        utils::draw_waveform(image, settings, envelopes);
+        true
+    }
+
+    fn draw_phase_correlation(&mut self, settings: &AppSettings) -> bool {
+        let (Some(values), Some(image)) =
+            (self.phase_correlation.as_ref(), self.final_image.as_mut())
+        else {
+            return false;
+        };
+        utils::draw_phase_correlation(image, settings, values);
+        true
+    }
+
+    fn reload_texture(&mut self, ctx: &egui::Context) {
+        if let Some(image) = &self.final_image {
+            self.texture = Some(ctx.load_texture("spectrogram", image.clone(), Default::default()));
+        }
+    }
+}
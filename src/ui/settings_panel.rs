@@ -1,39 +1,106 @@
 use eframe::egui;
 
 use super::MyApp;
-use crate::settings::{AppSettings, SpectogramWinFunc, SpectrogramColorScheme, SpectrogramScale};
+use crate::export::DataSource;
+use crate::settings::{
+    AppSettings, AudioSource, CompareLayout, FrequencyScale, SpectogramWinFunc,
+    SpectrogramColorScheme, SpectrogramEngine, SpectrogramScale, Strip, DB_LIMIT_BOUNDS,
+    DB_RANGE_BOUNDS, FFT_SIZES, MAX_OVERLAP,
+};
+use crate::utils::AudioStream;
 
 impl MyApp {
     pub(super) fn show_settings_panel(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
         let mut trigger_regeneration = false;
 
-        self.show_file_buttons(ui, &mut trigger_regeneration);
+        self.show_file_buttons(ui);
         self.show_settings_controls(ctx, ui, &mut trigger_regeneration);
 
-        if trigger_regeneration && !self.is_generating {
+        if trigger_regeneration && !self.tab.is_generating {
+            self.mark_background_tabs_stale();
+            self.regenerate_spectrogram(ctx);
+        } else if (self.tab.final_image.is_none() || self.tab.stale)
+            && !self.tab.is_generating
+            && self.tab.input_path.is_some()
+        {
+            // First time the tab is shown, or the settings changed while it was in the background
             self.regenerate_spectrogram(ctx);
         }
     }
 
-    fn show_file_buttons(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
-        ui.add_enabled_ui(!self.is_generating, |ui| {
+    fn show_file_buttons(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled_ui(!self.tab.is_generating, |ui| {
             let open_button_clicked = ui.button("Open File...").clicked();
             if open_button_clicked || self.trigger_open_file {
                 self.trigger_open_file = false;
-                if let Some(path) = rfd::FileDialog::new().pick_file() {
-                    self.input_path = Some(path.display().to_string());
-                    self.audio_info =
-                        crate::utils::get_audio_info(self.input_path.as_ref().unwrap());
-                    *trigger_regeneration = true;
+                if let Some(paths) = rfd::FileDialog::new().pick_files() {
+                    self.open_files(paths.iter().map(|path| path.display().to_string()));
                 }
             }
 
-            if self.final_image.is_some() {
-                let save_button_clicked = ui.button("Save As...").clicked();
-                if save_button_clicked || self.trigger_save_as {
+            if self.tab.final_image.is_some() {
+                let mut save_image = self.trigger_save_as;
+                let mut save_intensity = false;
+                // The levels of a difference are not exported
+                let has_levels = self.tab.difference.is_none();
+                ui.menu_button("Save As...", |ui| {
+                    if ui
+                        .button("Image or data...")
+                        .on_hover_text(
+                            "PNG, JPEG, WebP or TIFF image, or the levels as CSV, NumPy or raw f32, depending on the file type.",
+                        )
+                        .clicked()
+                    {
+                        save_image = true;
+                        ui.close();
+                    }
+                    if ui
+                        .add_enabled(has_levels, egui::Button::new("16-bit intensity PNG..."))
+                        .on_hover_text(
+                            "Grayscale levels before the palette is applied, without legend.",
+                        )
+                        .clicked()
+                    {
+                        save_intensity = true;
+                        ui.close();
+                    }
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("JPEG quality:");
+                        ui.add(
+                            egui::DragValue::new(&mut self.settings.jpeg_quality).range(1..=100),
+                        );
+                    });
+                });
+
+                if save_image || save_intensity {
                     self.trigger_save_as = false;
-                    if let Some(input_path) = &self.input_path {
-                        crate::utils::save_image(&self.final_image, input_path);
+                    if let Some(input_path) = &self.tab.input_path {
+                        let (width, height) = self.render_size();
+                        let data = self
+                            .tab
+                            .audio_info
+                            .clone()
+                            .filter(|_| has_levels)
+                            .map(|audio_info| DataSource {
+                                input_path: input_path.clone(),
+                                settings: self.tab_settings(self.active_tab),
+                                audio_info,
+                                width,
+                                height,
+                            });
+                        if save_intensity {
+                            if let Some(data) = data {
+                                crate::utils::save_intensity(input_path, data);
+                            }
+                        } else {
+                            crate::utils::save_image(
+                                &self.tab.final_image,
+                                input_path,
+                                data,
+                                self.settings.jpeg_quality,
+                            );
+                        }
                     }
                 }
             }
@@ -49,9 +116,10 @@ impl MyApp {
         if self.trigger_split_channel {
             self.trigger_split_channel = false;
             let has_multiple_channels = self
+                .tab
                 .audio_info
                 .as_ref()
-                .map_or(false, |info| info.channels > 1);
+                .is_some_and(|info| info.channels > 1);
             if has_multiple_channels {
                 self.settings.split_channels = !self.settings.split_channels;
                 *trigger_regeneration = true;
@@ -84,11 +152,7 @@ impl MyApp {
 
         ui.with_layout(egui::Layout::top_down(egui::Align::RIGHT), |ui| {
             ui.horizontal(|ui| {
-                if self.final_image.is_none() && !self.is_generating && self.input_path.is_some() {
-                    *trigger_regeneration = true;
-                }
-
-                ui.add_enabled_ui(!self.is_generating, |ui| {
+                ui.add_enabled_ui(!self.tab.is_generating, |ui| {
                     self.show_more_options_menu(ui, trigger_regeneration);
 
                     ui.add_space(4.0);
@@ -114,7 +178,7 @@ impl MyApp {
             .align(egui::RectAlign::BOTTOM_END)
             .close_behavior(egui::PopupCloseBehavior::CloseOnClickOutside)
             .show(|ui| {
-                ui.add_enabled_ui(!self.is_generating, |ui| {
+                ui.add_enabled_ui(!self.tab.is_generating, |ui| {
                     let mut dummy_true = true;
                     let mut dummy_false = false;
 
@@ -127,7 +191,7 @@ impl MyApp {
                     }
 
                     if self.settings.legend {
-                        if self.settings.live_mode {
+                        if self.settings.live_mode || self.settings.native_engine() {
                             ui.add_enabled(
                                 false,
                                 egui::Checkbox::new(&mut dummy_true, "Custom Legend"),
@@ -140,17 +204,14 @@ impl MyApp {
                             *trigger_regeneration = true;
                         }
 
-                        if self.settings.custom_legend
-                            || (self.settings.legend && self.settings.live_mode)
-                        {
-                            if ui
+                        if self.settings.use_custom_legend()
+                            && ui
                                 .button("Legend settings")
                                 .on_hover_text("Customize custom legend appearance.")
                                 .clicked()
-                            {
-                                self.legend_settings_window_open = true;
-                                ui.close();
-                            }
+                        {
+                            self.legend_settings_window_open = true;
+                            ui.close();
                         }
                         ui.separator();
                     }
@@ -184,10 +245,13 @@ impl MyApp {
 
                     ui.separator();
 
+                    self.show_source_controls(ui, trigger_regeneration);
+
                     let has_multiple_channels = self
+                        .tab
                         .audio_info
                         .as_ref()
-                        .map_or(false, |info| info.channels > 1);
+                        .is_some_and(|info| info.channels > 1);
 
                     if has_multiple_channels {
                         if ui
@@ -203,7 +267,38 @@ impl MyApp {
                         );
                     }
 
-                    if self.settings.live_mode || self.settings.custom_legend {
+                    if ui
+                        .add_enabled(
+                            self.settings.use_custom_legend(),
+                            egui::Checkbox::new(
+                                &mut self.settings.waveform,
+                                Strip::Waveform.as_str(),
+                            ),
+                        )
+                        .on_hover_text("Draw the peak waveform under the spectrogram, one lane per channel with split channels.")
+                        .changed()
+                    {
+                        *trigger_regeneration = true;
+                    }
+
+                    if ui
+                        .add_enabled(
+                            has_multiple_channels && self.settings.use_custom_legend(),
+                            egui::Checkbox::new(
+                                &mut self.settings.phase_correlation,
+                                Strip::PhaseCorrelation.as_str(),
+                            ),
+                        )
+                        .on_hover_text("Draw the phase correlation of the first two channels under the spectrogram: +1 is mono, 0 unrelated channels and -1 out of phase.")
+                        .changed()
+                    {
+                        *trigger_regeneration = true;
+                    }
+
+                    if self.settings.live_mode
+                        || self.settings.custom_legend
+                        || self.settings.native_engine()
+                    {
                         ui.add_enabled(false, egui::Checkbox::new(&mut dummy_false, "Horizontal"));
                     } else if ui
                         .checkbox(&mut self.settings.horizontal, "Horizontal")
@@ -212,6 +307,12 @@ impl MyApp {
                         *trigger_regeneration = true;
                     }
 
+                    ui.checkbox(&mut self.settings.crosshair, "Crosshair")
+                        .on_hover_text("Draw crosshair lines under the mouse cursor.");
+
+                    self.show_compare_controls(ui, trigger_regeneration);
+                    self.show_difference_controls(ui);
+
                     self.show_gain_drag(ui, trigger_regeneration);
                     self.show_saturation_drag(ui, trigger_regeneration);
 
@@ -225,6 +326,56 @@ impl MyApp {
                         *trigger_regeneration = true;
                     }
 
+                    ui.add_enabled_ui(!self.settings.live_mode, |ui| {
+                        self.show_engine_combo(ui, trigger_regeneration);
+                    });
+                    self.show_frequency_scale_combo(ui, trigger_regeneration);
+                    self.show_fft_controls(ui, trigger_regeneration);
+                    self.show_level_range_drags(ui, trigger_regeneration);
+                    self.show_frequency_band_controls(ui, trigger_regeneration);
+                    self.show_time_range_controls(ui);
+
+                    if ui
+                        .checkbox(&mut self.settings.detect_cutoff, "Detect lossy cutoff")
+                        .on_hover_text(
+                            "Estimate the frequency cutoff and mark it on the spectrogram.",
+                        )
+                        .changed()
+                    {
+                        *trigger_regeneration = true;
+                    }
+
+                    if ui
+                        .button("Loudness")
+                        .on_hover_text("Loudness (EBU R128), true peak and RMS of the file.")
+                        .clicked()
+                    {
+                        self.loudness_window_open = true;
+                        ui.close();
+                    }
+
+                    if ui
+                        .button("Hi-res check")
+                        .on_hover_text(
+                            "Check whether a high resolution file is upsampled or padded from a lower sample rate or bit depth.",
+                        )
+                        .clicked()
+                    {
+                        self.hires_window_open = true;
+                        ui.close();
+                    }
+
+                    if ui
+                        .button("Average spectrum")
+                        .on_hover_text(
+                            "Average and peak level of every frequency over the rendered part of the file.",
+                        )
+                        .clicked()
+                    {
+                        self.spectrum_window_open = true;
+                        ui.close();
+                    }
+
                     ui.separator();
 
                     if ui
@@ -236,7 +387,12 @@ impl MyApp {
 
                     if ui.button("Reset settings").clicked() {
                         // ui.close();
-                        self.settings = AppSettings::default();
+                        // The zoom and the picked stream belong to the open file
+                        self.settings = AppSettings {
+                            view: self.settings.view,
+                            source: self.settings.source.clone(),
+                            ..AppSettings::default()
+                        };
                         *trigger_regeneration = true;
                     }
 
@@ -258,6 +414,281 @@ impl MyApp {
             });
     }
 
+    /// Audio stream and channel pickers, only shown when there is something to pick.
+    fn show_source_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let mut source = self.settings.source.clone();
+
+        if self.tab.audio_streams.len() > 1 {
+            let stream_label = |index: usize, stream: &AudioStream| {
+                format!("#{}: {}", index + 1, stream.description())
+            };
+            egui::ComboBox::from_label("Stream")
+                .selected_text(format!("#{}", source.stream + 1))
+                .width(70.0)
+                .show_ui(ui, |ui| {
+                    for (index, stream) in self.tab.audio_streams.iter().enumerate() {
+                        if ui
+                            .selectable_label(source.stream == index, stream_label(index, stream))
+                            .clicked()
+                            && source.stream != index
+                        {
+                            source = AudioSource {
+                                stream: index,
+                                ..Default::default()
+                            };
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Audio stream of the file to analyze.");
+        }
+
+        if let Some(stream) = self.tab.audio_streams.get(source.stream) {
+            if stream.channels > 1 {
+                ui.menu_button("Channels", |ui| {
+                    for (channel, name) in stream.channel_names().iter().enumerate() {
+                        let mut checked =
+                            source.channels.is_empty() || source.channels.contains(&channel);
+                        if ui
+                            .checkbox(&mut checked, format!("{}: {}", channel + 1, name))
+                            .changed()
+                        {
+                            let mut selected = if source.channels.is_empty() {
+                                (0..stream.channels as usize).collect()
+                            } else {
+                                source.channels.clone()
+                            };
+                            if checked {
+                                selected.push(channel);
+                                selected.sort_unstable();
+                                selected.dedup();
+                            } else {
+                                selected.retain(|&c| c != channel);
+                            }
+                            // Nothing or everything selected both mean all channels
+                            if selected.is_empty() || selected.len() == stream.channels as usize {
+                                selected.clear();
+                            }
+                            source.channels = selected;
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Channels of the stream to analyze, all of them by default.");
+            }
+        }
+
+        let has_stereo = self
+            .tab
+            .audio_streams
+            .get(source.stream)
+            .is_some_and(|stream| stream.channels > 1)
+            && source.channels.len() != 1;
+        let show_mid_side = has_stereo || source.mid_side;
+        if show_mid_side
+            && ui
+                .checkbox(&mut source.mid_side, "Mid/Side")
+                .on_hover_text("Show the mid (L+R) and side (L-R) signals of the first two channels instead of the channels themselves.")
+                .changed()
+            && source.mid_side
+        {
+            self.settings.split_channels = true;
+        }
+
+        if source != self.settings.source {
+            self.set_source(source);
+            *trigger_regeneration = true;
+        }
+    }
+
+    fn show_compare_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let mut compare = self.compare;
+        ui.add_enabled_ui(self.compared_tabs().len() > 1, |ui| {
+            if ui
+                .checkbox(&mut compare, "Compare tabs")
+                .on_hover_text("Show the spectrograms of all tabs with the same zoom and cursor.")
+                .changed()
+            {
+                self.trigger_compare = true;
+            }
+        });
+
+        if self.compare {
+            let old_layout = self.settings.compare_layout;
+            egui::ComboBox::from_label("Layout")
+                .selected_text(self.settings.compare_layout.to_string())
+                .width(90.0)
+                .show_ui(ui, |ui| {
+                    for layout in CompareLayout::VALUES {
+                        ui.selectable_value(
+                            &mut self.settings.compare_layout,
+                            layout,
+                            layout.to_string(),
+                        );
+                    }
+                });
+            if self.settings.compare_layout != old_layout {
+                *trigger_regeneration = true;
+            }
+        }
+    }
+
+    fn show_engine_combo(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let old_engine = self.settings.engine;
+        egui::ComboBox::from_label("Engine")
+            .selected_text(self.settings.engine.to_string())
+            .width(70.0)
+            .show_ui(ui, |ui| {
+                for engine in SpectrogramEngine::VALUES {
+                    ui.selectable_value(&mut self.settings.engine, engine, engine.to_string());
+                }
+            })
+            .response
+            .on_hover_text(
+                "FFmpeg uses the showspectrumpic filter, Native computes the FFT in spek-rs.",
+            );
+        if self.settings.engine != old_engine {
+            *trigger_regeneration = true;
+        }
+    }
+
+    fn show_frequency_scale_combo(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let old_scale = self.settings.frequency_scale;
+        egui::ComboBox::from_label("Frequency axis")
+            .selected_text(self.settings.frequency_scale.to_string())
+            .width(70.0)
+            .show_ui(ui, |ui| {
+                for scale in FrequencyScale::VALUES {
+                    ui.selectable_value(
+                        &mut self.settings.frequency_scale,
+                        scale,
+                        scale.to_string(),
+                    );
+                }
+            })
+            .response
+            .on_hover_text(
+                "Spacing of the frequencies. Mel and Bark are always drawn by the native engine.",
+            );
+        if self.settings.frequency_scale != old_scale {
+            *trigger_regeneration = true;
+        }
+    }
+
+    /// FFT size and overlap, both only available in the native engine.
+    fn show_fft_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let fft_label = |size: Option<u32>| size.map_or("Auto".to_string(), |s| s.to_string());
+        let old_fft_size = self.settings.fft_size;
+        egui::ComboBox::from_label("FFT size")
+            .selected_text(fft_label(self.settings.fft_size))
+            .width(70.0)
+            .show_ui(ui, |ui| {
+                for size in std::iter::once(None).chain(FFT_SIZES.map(Some)) {
+                    ui.selectable_value(&mut self.settings.fft_size, size, fft_label(size));
+                }
+            })
+            .response
+            .on_hover_text(
+                "Larger sizes resolve frequencies finer but blur time. Auto follows the image height, other sizes use the native engine.",
+            );
+        if self.settings.fft_size != old_fft_size {
+            *trigger_regeneration = true;
+        }
+
+        let mut overlap = self.settings.overlap * 100.0;
+        let overlap_response = ui
+            .add(
+                egui::DragValue::new(&mut overlap)
+                    .speed(1.0)
+                    .range(0.0..=MAX_OVERLAP * 100.0)
+                    .prefix("Overlap: ")
+                    .suffix(" %"),
+            )
+            .on_hover_text(
+                "Overlap of the FFT frames averaged into one column of long files, uses the native engine.",
+            );
+        self.settings.overlap = overlap / 100.0;
+        if overlap_response.drag_stopped() || overlap_response.lost_focus() {
+            *trigger_regeneration = true;
+        }
+    }
+
+    fn show_level_range_drags(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let range_response = ui
+            .add(
+                egui::DragValue::new(&mut self.settings.db_range)
+                    .speed(1.0)
+                    .range(DB_RANGE_BOUNDS)
+                    .prefix("Range: ")
+                    .suffix(" dB"),
+            )
+            .on_hover_text("Dynamic range of the levels shown by the palette.");
+        let limit_response = ui
+            .add(
+                egui::DragValue::new(&mut self.settings.db_limit)
+                    .speed(1.0)
+                    .range(DB_LIMIT_BOUNDS)
+                    .prefix("Limit: ")
+                    .suffix(" dBFS"),
+            )
+            .on_hover_text("Level shown at the top of the palette.");
+        for response in [range_response, limit_response] {
+            if response.drag_stopped() || response.lost_focus() {
+                *trigger_regeneration = true;
+            }
+        }
+    }
+
+    fn show_frequency_band_controls(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
+        let nyquist = self
+            .tab
+            .audio_info
+            .as_ref()
+            .map_or(24000.0, |info| info.sample_rate as f32 / 2.0);
+        let mut limited =
+            self.settings.min_frequency.is_some() || self.settings.max_frequency.is_some();
+        if ui
+            .checkbox(&mut limited, "Limit frequencies")
+            .on_hover_text("Only show a band of frequencies, e.g. the sub-bass.")
+            .changed()
+        {
+            (self.settings.min_frequency, self.settings.max_frequency) = if limited {
+                (Some(0.0), Some(nyquist))
+            } else {
+                (None, None)
+            };
+            *trigger_regeneration = true;
+        }
+
+        if limited {
+            ui.horizontal(|ui| {
+                ui.add_space(18.0);
+                let mut min = self.settings.min_frequency.unwrap_or(0.0);
+                let mut max = self.settings.max_frequency.unwrap_or(nyquist);
+                let min_response = ui.add(
+                    egui::DragValue::new(&mut min)
+                        .range(0.0..=max - 1.0)
+                        .speed(10.0)
+                        .suffix(" Hz"),
+                );
+                ui.label("-");
+                let max_response = ui.add(
+                    egui::DragValue::new(&mut max)
+                        .range(min + 1.0..=f32::MAX)
+                        .speed(10.0)
+                        .suffix(" Hz"),
+                );
+                self.settings.min_frequency = Some(min);
+                self.settings.max_frequency = Some(max);
+                for response in [min_response, max_response] {
+                    if response.drag_stopped() || response.lost_focus() {
+                        *trigger_regeneration = true;
+                    }
+                }
+            });
+        }
+    }
+
     fn show_scale_combo(&mut self, ui: &mut egui::Ui, trigger_regeneration: &mut bool) {
         if self.trigger_scale_up {
             self.trigger_scale_up = false;
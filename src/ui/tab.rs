@@ -0,0 +1,286 @@
+use eframe::egui::{self, ColorImage};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+
+use super::MyApp;
+use crate::cutoff::CutoffReport;
+use crate::hires::HiResReport;
+use crate::loudness::LoudnessReport;
+use crate::playback::Player;
+use crate::settings::{AudioSource, Difference, ViewRange};
+use crate::spectrum::{AverageSpectrum, SpectrumRequest};
+use crate::utils;
+use crate::waveform::PeakEnvelope;
+
+/// Height of the tab bar in logical points, only shown with more than one tab.
+pub(super) const TAB_BAR_HEIGHT: f32 = 26.0;
+
+/// An open file with its own render state.
+#[derive(Default)]
+pub(super) struct Tab {
+    pub texture: Option<egui::TextureHandle>,
+    pub final_image: Option<ColorImage>,
+    pub input_path: Option<String>,
+    pub is_generating: bool,
+    pub image_receiver: Option<Receiver<Option<ColorImage>>>,
+    pub spectrogram_slice_position: usize,
+    pub audio_info: Option<utils::AudioInfo>,
+    pub audio_streams: Vec<utils::AudioStream>,
+    pub generation_cancel_token: Option<Arc<AtomicBool>>,
+    pub cutoff_report: Option<CutoffReport>,
+    pub cutoff_receiver: Option<Receiver<Option<CutoffReport>>>,
+    pub cutoff_cancel_token: Option<Arc<AtomicBool>>,
+    pub loudness_report: Option<LoudnessReport>,
+    pub loudness_receiver: Option<Receiver<Option<LoudnessReport>>>,
+    pub loudness_cancel_token: Option<Arc<AtomicBool>>,
+    pub hires_report: Option<HiResReport>,
+    pub hires_receiver: Option<Receiver<Option<HiResReport>>>,
+    pub hires_cancel_token: Option<Arc<AtomicBool>>,
+    /// Peak envelope of every channel in the rendered time range, one value per column.
+    pub waveform: Option<Vec<PeakEnvelope>>,
+    pub waveform_receiver: Option<Receiver<Option<Vec<PeakEnvelope>>>>,
+    pub waveform_cancel_token: Option<Arc<AtomicBool>>,
+    /// Phase correlation of the rendered time range, one value per column.
+    pub phase_correlation: Option<Vec<Option<f32>>>,
+    pub phase_correlation_receiver: Option<Receiver<Option<Vec<Option<f32>>>>>,
+    pub phase_correlation_cancel_token: Option<Arc<AtomicBool>>,
+    /// Average spectrum of the rendered time range and the request it's measured for.
+    pub spectrum: Option<AverageSpectrum>,
+    pub spectrum_request: Option<SpectrumRequest>,
+    pub spectrum_receiver: Option<Receiver<Option<AverageSpectrum>>>,
+    pub spectrum_cancel_token: Option<Arc<AtomicBool>>,
+    /// Plot of `spectrum`, drawn again after the settings changed.
+    pub spectrum_plot: Option<(ColorImage, egui::TextureHandle)>,
+    pub view_history: Vec<ViewRange>,
+    pub pending_view: Option<ViewRange>,
+    pub pending_view_since: f64,
+    pub player: Option<Player>,
+    /// Zoom and stream of the tab, kept in `settings.view` and `settings.source`
+    /// while the tab is active.
+    pub view: ViewRange,
+    pub source: AudioSource,
+    /// Second file the tab is compared against, shown as a difference spectrogram.
+    pub difference: Option<Difference>,
+    /// Delay of the second file being estimated, the difference is rendered once it's known.
+    pub offset_receiver: Option<Receiver<Option<f64>>>,
+    pub offset_cancel_token: Option<Arc<AtomicBool>>,
+    /// The settings changed since the spectrogram was rendered.
+    pub stale: bool,
+}
+
+impl Tab {
+    /// Loads the streams and audio info of `path`, the spectrogram is rendered once the tab is shown.
+    pub fn open(path: String) -> Self {
+        let source = AudioSource::default();
+        Self {
+            audio_streams: utils::get_audio_streams(&path).unwrap_or_default(),
+            audio_info: utils::get_audio_info(&path, &source),
+            input_path: Some(path),
+            source,
+            ..Default::default()
+        }
+    }
+
+    /// File name shown in the tab bar.
+    pub fn title(&self) -> String {
+        let file_name = |path: &String| {
+            Path::new(path)
+                .file_name()
+                .map_or(path.clone(), |name| name.to_string_lossy().to_string())
+        };
+        match (&self.input_path, &self.difference) {
+            (Some(path), Some(difference)) => {
+                format!("{} − {}", file_name(path), file_name(&difference.path))
+            }
+            (Some(path), None) => file_name(path),
+            (None, _) => "New tab".to_string(),
+        }
+    }
+
+    /// Stops the background work of the tab.
+    fn cancel(&mut self) {
+        for token in [
+            &self.generation_cancel_token,
+            &self.cutoff_cancel_token,
+            &self.loudness_cancel_token,
+            &self.hires_cancel_token,
+            &self.waveform_cancel_token,
+            &self.phase_correlation_cancel_token,
+            &self.spectrum_cancel_token,
+            &self.offset_cancel_token,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            token.store(true, Ordering::Relaxed);
+        }
+        self.player = None;
+    }
+}
+
+impl MyApp {
+    /// Opens `path` in a new tab and shows it, an empty tab is reused.
+    pub(super) fn open_file(&mut self, path: String) {
+        if self.tab.input_path.is_some() {
+            self.tabs.push(Tab::default());
+            self.switch_tab(self.tabs.len() - 1);
+        }
+        self.settings.source = AudioSource::default();
+        self.tab = Tab::open(path);
+        self.file_changed();
+    }
+
+    /// Opens every file in its own tab and shows the first one.
+    pub fn open_files(&mut self, paths: impl IntoIterator<Item = String>) {
+        let first = if self.tab.input_path.is_none() {
+            self.active_tab
+        } else {
+            self.tabs.len()
+        };
+        for path in paths {
+            self.open_file(path);
+        }
+        self.switch_tab(first);
+    }
+
+    /// Opens the files of later launches sent by the single instance listener.
+    pub fn receive_files(&mut self, receiver: Receiver<Vec<String>>) {
+        self.file_receiver = Some(receiver);
+    }
+
+    pub(super) fn handle_forwarded_files(&mut self) {
+        let Some(receiver) = &self.file_receiver else {
+            return;
+        };
+        let messages: Vec<Vec<String>> = receiver.try_iter().collect();
+        for paths in messages {
+            self.open_files(paths);
+        }
+    }
+
+    /// Shows the tab at `index`, the current tab keeps rendering in the background.
+    pub(super) fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return;
+        }
+        if let Some(player) = self.tab.player.as_mut() {
+            player.pause();
+        }
+        self.tab.view = self.settings.view;
+        self.tab.source = std::mem::take(&mut self.settings.source);
+        std::mem::swap(&mut self.tab, &mut self.tabs[self.active_tab]);
+        self.tab = std::mem::take(&mut self.tabs[index]);
+        self.active_tab = index;
+        // The compared tabs share the view
+        if !self.compare {
+            self.settings.view = self.tab.view;
+        }
+        self.settings.source = self.tab.source.clone();
+
+        self.cursor_readout = None;
+        self.zoom_drag_start = None;
+        self.channel_menu_lane = None;
+
+        // A render with outdated settings is replaced by a new one
+        if self.tab.stale && self.tab.is_generating {
+            if let Some(token) = self.tab.generation_cancel_token.take() {
+                token.store(true, Ordering::Relaxed);
+            }
+            self.tab.is_generating = false;
+            self.tab.image_receiver = None;
+        }
+    }
+
+    /// Moves `offset` tabs to the right, wrapping around.
+    pub(super) fn cycle_tab(&mut self, offset: isize) {
+        let count = self.tabs.len() as isize;
+        self.switch_tab((self.active_tab as isize + offset).rem_euclid(count) as usize);
+    }
+
+    pub(super) fn close_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() {
+            return;
+        }
+        if self.tabs.len() == 1 {
+            self.tab.cancel();
+            self.tab = Tab::default();
+            self.file_changed();
+            self.settings.source = AudioSource::default();
+            self.cursor_readout = None;
+            return;
+        }
+        if index == self.active_tab {
+            let neighbour = if index + 1 < self.tabs.len() {
+                index + 1
+            } else {
+                index - 1
+            };
+            self.switch_tab(neighbour);
+        }
+        self.tabs[index].cancel();
+        self.tabs.remove(index);
+        if self.active_tab > index {
+            self.active_tab -= 1;
+        }
+        // The range the compared files have in common may have changed
+        if self.compare {
+            self.tab.stale = true;
+        }
+    }
+
+    /// The other tabs are rendered again with the new settings when they are shown.
+    pub(super) fn mark_background_tabs_stale(&mut self) {
+        for (index, tab) in self.tabs.iter_mut().enumerate() {
+            if index != self.active_tab && tab.input_path.is_some() {
+                tab.stale = true;
+            }
+        }
+    }
+
+    pub(super) fn show_tab_bar(&mut self, ui: &mut egui::Ui) {
+        let mut switch_to = None;
+        let mut close = None;
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal_centered(|ui| {
+                for index in 0..self.tabs.len() {
+                    let tab = if index == self.active_tab {
+                        &self.tab
+                    } else {
+                        &self.tabs[index]
+                    };
+                    let title = if tab.is_generating {
+                        format!("{} …", tab.title())
+                    } else {
+                        tab.title()
+                    };
+                    let response = ui
+                        .selectable_label(index == self.active_tab, title)
+                        .on_hover_text(tab.input_path.as_deref().unwrap_or_default());
+                    if response.clicked() {
+                        switch_to = Some(index);
+                    }
+                    if response.middle_clicked() {
+                        close = Some(index);
+                    }
+                    if ui
+                        .small_button("×")
+                        .on_hover_text("Close tab (Ctrl + W)")
+                        .clicked()
+                    {
+                        close = Some(index);
+                    }
+                    ui.separator();
+                }
+            });
+        });
+
+        if let Some(index) = switch_to {
+            self.switch_tab(index);
+        }
+        if let Some(index) = close {
+            self.close_tab(index);
+        }
+    }
+}
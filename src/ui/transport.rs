@@ -0,0 +1,118 @@
+use eframe::egui::{self, Color32, Stroke};
+use std::time::Duration;
+
+use super::cursor::SpectrogramArea;
+use super::MyApp;
+use crate::playback::{self, Player};
+use crate::utils::format_time;
+
+/// Step of the arrow keys seeking, in seconds.
+pub(super) const SEEK_STEP: f64 = 5.0;
+
+impl MyApp {
+    /// Returns the player of the current file, creating it if needed.
+    fn player(&mut self) -> Option<&mut Player> {
+        if self.tab.player.is_none() {
+            let input_path = self.tab.input_path.as_ref()?;
+            let audio_info = self.tab.audio_info.as_ref()?;
+            self.tab.player = Some(Player::new(
+                input_path,
+                audio_info,
+                playback::default_sink(),
+            ));
+        }
+        self.tab.player.as_mut()
+    }
+
+    pub(super) fn toggle_playback(&mut self) {
+        if let Some(player) = self.player() {
+            player.toggle();
+        }
+    }
+
+    pub(super) fn seek_playback(&mut self, time: f64) {
+        if let Some(player) = self.player() {
+            player.seek(time);
+        }
+    }
+
+    /// Moves the playhead by `offset` seconds.
+    pub(super) fn seek_playback_by(&mut self, offset: f64) {
+        if let Some(player) = self.player() {
+            let position = player.position();
+            player.seek(position + offset);
+        }
+    }
+
+    /// Stops the playback of the previous file.
+    pub(super) fn stop_playback(&mut self) {
+        self.tab.player = None;
+    }
+
+    /// Seeks on click and draws the playhead over the spectrogram.
+    pub(super) fn handle_playhead(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        area: &SpectrogramArea,
+    ) {
+        if response.clicked() {
+            if let Some(pos) = response
+                .interact_pointer_pos()
+                .filter(|pos| area.rect.contains(*pos))
+            {
+                self.seek_playback(area.point_at(pos).time);
+            }
+        }
+
+        let Some(player) = self.tab.player.as_ref() else {
+            return;
+        };
+        if let Some(coordinate) = area.time_to_screen(player.position()) {
+            let stroke = Stroke::new(1.5, Color32::from_rgb(0, 255, 128));
+            let painter = ui.painter_at(area.rect.expand(1.0));
+            if area.horizontal {
+                painter.hline(area.rect.x_range(), coordinate, stroke);
+            } else {
+                painter.vline(coordinate, area.rect.y_range(), stroke);
+            }
+        }
+    }
+
+    /// Play/pause button and the playhead position, on the left side of the status bar.
+    pub(super) fn show_transport(&mut self, ui: &mut egui::Ui) {
+        if self.tab.input_path.is_none() || self.tab.audio_info.is_none() {
+            return;
+        }
+        let (playing, position, duration) = match self.tab.player.as_mut() {
+            Some(player) => (player.is_playing(), player.position(), player.duration()),
+            None => (
+                false,
+                0.0,
+                self.tab
+                    .audio_info
+                    .as_ref()
+                    .map_or(0.0, |info| info.duration),
+            ),
+        };
+
+        if playing {
+            ui.ctx().request_repaint_after(Duration::from_millis(30));
+        }
+
+        let label = if playing { "Pause" } else { "Play" };
+        if ui
+            .small_button(label)
+            .on_hover_text("Play or pause the audio (Space), click the spectrogram to seek")
+            .clicked()
+        {
+            self.toggle_playback();
+        }
+        ui.label(format!(
+            "{} / {}",
+            format_time(position),
+            format_time(duration)
+        ));
+        ui.separator();
+    }
+}
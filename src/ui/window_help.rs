@@ -28,6 +28,46 @@ pub fn show(ctx: &egui::Context, is_open: &mut bool) {
 
             ui.label("About Live Mode:");
             ui.label("Live mode generates the spectrogram in real-time, but the visual quality is lower than a normal generation. This is because it uses a different, less precise method to generate the image on the fly. This option is useful when you want to quickly preview the beginning of a file, for example, to check if a FLAC file is a genuine lossless file or an upscaled lossy file.");
+            ui.add_space(5.0);
+
+            ui.label("About lossy cutoff detection:");
+            ui.label("Lossy encoders like MP3 or AAC remove everything above a certain frequency, which shows up as a flat shelf at the top of the spectrogram. With \"Detect lossy cutoff\" enabled the whole file is analyzed, the detected cutoff is marked with a dashed line and the label shows the likely source and confidence. Files with only a few tones or very quiet content can't be judged reliably.");
+            ui.add_space(5.0);
+
+            ui.label("About loudness:");
+            ui.label("The loudness window measures the whole file after EBU R128. Integrated loudness is the loudness of the whole file in LUFS, the loudness range in LU shows how much it varies. The true peak also catches peaks between samples, which can clip after conversion to a lossy format even if the sample peak is below 0 dBFS. Runs of three or more full scale samples are reported as clipping, with \"Mark clipping\" both are marked under the time scale.");
+            ui.add_space(5.0);
+
+            ui.label("About the hi-res check:");
+            ui.label("Many files sold as 24-bit / 96 kHz are made from CD audio. The hi-res check finds the highest frequency with content: if it ends at 22.05 or 24 kHz, the file was most likely upsampled from 44.1 or 48 kHz. It also checks how many bits of the samples are used, a 24-bit file where the lowest 8 bits are always zero is 16-bit audio padded with zeros.");
+            ui.add_space(5.0);
+
+            ui.label("About playback:");
+            ui.label("Press Space or the Play button in the status bar to listen to the file. Clicking the spectrogram moves the playhead to that time, which is handy for checking what an artefact sounds like.");
+            ui.add_space(5.0);
+
+            ui.label("About comparing files:");
+            ui.label("Open the files to compare in tabs and enable \"Compare tabs\" (V). All of them are rendered with the same settings and time and frequency range, side by side or stacked. Zooming in one spectrogram zooms all of them and the cursor position is marked in each. Files of different length or sample rate are cut to the part they have in common.");
+            ui.add_space(5.0);
+
+            ui.label("About difference spectrograms:");
+            ui.label("\"Difference with\" opens a tab comparing the current file with another open one. The dB delta shows how much louder (red) or quieter (blue) the current file is in every time and frequency slot, black means no difference. The difference signal mode shows the spectrogram of the current file minus the other one instead. With \"Align\" the delay between the files, e.g. the encoder delay of an MP3, is found by cross-correlation and compensated.");
+            ui.add_space(5.0);
+
+            ui.label("About split channels:");
+            ui.label("With \"Split channels\" every channel of the stream is drawn in its own lane, labeled with the channel name. Right click a lane to hide that channel, or to show all channels again.");
+            ui.add_space(5.0);
+
+            ui.label("About the waveform:");
+            ui.label("\"Waveform\" draws the highest and lowest sample of every column under the spectrogram on the same time axis, with split channels one lane per channel. Like the other strips it needs the custom legend.");
+            ui.add_space(5.0);
+
+            ui.label("About the average spectrum:");
+            ui.label("The average spectrum shows the level of every frequency averaged over the rendered part of the file, which is handy for comparing the tonal balance of two masters. The peak hold curve is the highest level each frequency reached. It follows the window function, FFT size, frequency axis and level range of the spectrogram, and can be saved as an image or as a CSV table.");
+            ui.add_space(5.0);
+
+            ui.label("About mid/side and phase correlation:");
+            ui.label("\"Mid/Side\" shows the sum (L+R) and difference (L-R) of a stereo file instead of its channels. A side signal that is empty or only has content up to a low frequency points to mono or joint stereo encoding. The phase correlation strip under the spectrogram shows how similar both channels are: +1 is mono, around 0 wide or unrelated channels and below 0 out of phase content that partly cancels when played in mono.");
             ui.add_space(2.0);
         });
 }
@@ -0,0 +1,63 @@
+use eframe::egui;
+
+use crate::hires::{self, HiResReport};
+
+/// Result of the check for upsampled or padded high resolution files.
+pub fn show(ctx: &egui::Context, is_open: &mut bool, report: Option<&HiResReport>, checking: bool) {
+    egui::Window::new("Hi-res check")
+        .open(is_open)
+        .pivot(egui::Align2::CENTER_CENTER)
+        .default_pos(ctx.content_rect().center())
+        .resizable(false)
+        .collapsible(false)
+        .min_width(260.0)
+        .show(ctx, |ui| {
+            let Some(report) = report else {
+                ui.horizontal(|ui| {
+                    if checking {
+                        ui.spinner();
+                        ui.label("Checking...");
+                    } else {
+                        ui.label("No resolution check for this file.");
+                    }
+                });
+                return;
+            };
+
+            egui::Grid::new("hires_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("Sample rate:");
+                    ui.label(hires::format_rate(report.sample_rate));
+                    ui.end_row();
+                    ui.label("Content up to:");
+                    ui.label(report.bandwidth_hz.map_or("Nyquist".to_string(), |hz| {
+                        format!("{:.1} kHz", hz / 1000.0)
+                    }));
+                    ui.end_row();
+                    ui.label("Bit depth:");
+                    ui.label(if report.bits_per_sample > 0 {
+                        format!("{}-bit", report.bits_per_sample)
+                    } else {
+                        "-".to_string()
+                    });
+                    ui.end_row();
+                    ui.label("Bits used:");
+                    ui.label(
+                        report
+                            .effective_bits
+                            .map_or("-".to_string(), |bits| format!("{}-bit", bits)),
+                    );
+                    ui.end_row();
+                });
+
+            ui.separator();
+            let verdict = report.verdict();
+            if report.is_fake() {
+                ui.colored_label(ui.visuals().warn_fg_color, verdict);
+            } else {
+                ui.label(verdict);
+            }
+        });
+}
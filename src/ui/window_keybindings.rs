@@ -19,12 +19,21 @@ pub fn show(ctx: &egui::Context, is_open: &mut bool) {
                     let rows = [
                         ("Ctrl + O", "Open File"),
                         ("Ctrl + S", "Save As"),
+                        ("Ctrl + Tab", "Next Tab"),
+                        ("Ctrl + Shift + Tab", "Previous Tab"),
+                        ("Ctrl + W", "Close Tab"),
                         ("P,   Shift + P", "Cycle Color Palette"),
                         ("F,   Shift + F", "Cycle Window Function"),
                         ("A,   Shift + A", "Cycle Scale"),
                         ("G,   Shift + G", "Adjust Gain"),
                         ("T,   Shift + T", "Adjust Saturation"),
                         ("C", "Toggle Split Channels"),
+                        ("X", "Toggle Crosshair"),
+                        ("V", "Toggle Compare View"),
+                        ("Backspace", "Zoom Out"),
+                        ("Home", "Reset Zoom"),
+                        ("Space", "Play / Pause"),
+                        ("Left,   Right", "Seek 5 Seconds"),
                         ("ESC", "Close Application"),
                         ("F1", "Open Help"),
                         ("F2", "Open Keybindings"),
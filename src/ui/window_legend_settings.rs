@@ -20,25 +20,40 @@ pub fn show(ctx: &egui::Context, is_open: &mut bool, settings: &mut AppSettings)
         .show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 ui.add_space(10.0);
-                
+
                 egui::Grid::new("legend_color_grid")
                     .num_columns(2)
                     .spacing([40.0, 8.0])
                     .show(ui, |ui| {
                         ui.label("Background Color:");
-                        if egui::color_picker::color_edit_button_srgb(ui, &mut custom_legend_bg_color).changed() {
+                        if egui::color_picker::color_edit_button_srgb(
+                            ui,
+                            &mut custom_legend_bg_color,
+                        )
+                        .changed()
+                        {
                             changed = true;
                         }
                         ui.end_row();
 
                         ui.label("Text Color:");
-                        if egui::color_picker::color_edit_button_srgb(ui, &mut custom_legend_text_color).changed() {
+                        if egui::color_picker::color_edit_button_srgb(
+                            ui,
+                            &mut custom_legend_text_color,
+                        )
+                        .changed()
+                        {
                             changed = true;
                         }
                         ui.end_row();
 
                         ui.label("Line Color:");
-                        if egui::color_picker::color_edit_button_srgb(ui, &mut custom_legend_line_color).changed() {
+                        if egui::color_picker::color_edit_button_srgb(
+                            ui,
+                            &mut custom_legend_line_color,
+                        )
+                        .changed()
+                        {
                             changed = true;
                         }
                         ui.end_row();
@@ -0,0 +1,126 @@
+use eframe::egui;
+
+use crate::loudness::{format_db, LoudnessReport};
+use crate::settings::AppSettings;
+
+/// Loudness and level statistics of the shown file.
+/// Returns true if the legend has to be drawn again.
+pub fn show(
+    ctx: &egui::Context,
+    is_open: &mut bool,
+    report: Option<&LoudnessReport>,
+    measuring: bool,
+    settings: &mut AppSettings,
+) -> bool {
+    let mut changed = false;
+
+    egui::Window::new("Loudness")
+        .open(is_open)
+        .pivot(egui::Align2::CENTER_CENTER)
+        .default_pos(ctx.content_rect().center())
+        .resizable(false)
+        .collapsible(false)
+        .min_width(260.0)
+        .show(ctx, |ui| {
+            let Some(report) = report else {
+                ui.horizontal(|ui| {
+                    if measuring {
+                        ui.spinner();
+                        ui.label("Measuring...");
+                    } else {
+                        ui.label("No loudness measured for this file.");
+                    }
+                });
+                return;
+            };
+
+            let lufs = |value: Option<f32>, unit: &str| {
+                value.map_or("-".to_string(), |v| format!("{:.1} {}", v, unit))
+            };
+            egui::Grid::new("loudness_grid")
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("Integrated:");
+                    ui.label(lufs(report.integrated_lufs, "LUFS"));
+                    ui.end_row();
+                    ui.label("Loudness range:");
+                    ui.label(lufs(report.loudness_range_lu, "LU"));
+                    ui.end_row();
+                    ui.label("Short-term max:");
+                    ui.label(lufs(report.short_term_max_lufs, "LUFS"));
+                    ui.end_row();
+                    ui.label("Sample peak:");
+                    ui.label(format!("{} dBFS", format_db(report.sample_peak_dbfs)));
+                    ui.end_row();
+                    ui.label("True peak:");
+                    ui.label(format!("{} dBTP", format_db(report.true_peak_dbtp)));
+                    ui.end_row();
+                });
+
+            ui.separator();
+            egui::Grid::new("loudness_channels_grid")
+                .num_columns(3)
+                .spacing([40.0, 4.0])
+                .show(ui, |ui| {
+                    ui.strong("Channel");
+                    ui.strong("RMS");
+                    ui.strong("Peak");
+                    ui.end_row();
+                    for channel in &report.channels {
+                        ui.label(&channel.name);
+                        ui.label(format!("{} dBFS", format_db(channel.rms_dbfs)));
+                        ui.label(format!("{} dBFS", format_db(channel.peak_dbfs)));
+                        ui.end_row();
+                    }
+                });
+
+            ui.separator();
+            let clipping = &report.clipping;
+            ui.horizontal(|ui| {
+                ui.label("Clipping:");
+                if clipping.is_clean() {
+                    ui.label("none");
+                } else {
+                    ui.colored_label(ui.visuals().warn_fg_color, clipping.summary());
+                }
+            });
+            if !clipping.events.is_empty() {
+                egui::ScrollArea::vertical()
+                    .max_height(120.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        for event in &clipping.events {
+                            ui.label(event.describe());
+                        }
+                    });
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .checkbox(&mut settings.loudness_legend, "Show in legend")
+                    .on_hover_text("Print the loudness into the header of the custom legend.")
+                    .changed()
+                {
+                    changed = true;
+                }
+                if ui
+                    .checkbox(&mut settings.clip_markers, "Mark clipping")
+                    .on_hover_text(
+                        "Mark clipped samples (red) and true peak overs (orange) under the time scale of the custom legend.",
+                    )
+                    .changed()
+                {
+                    changed = true;
+                }
+                if ui.button("Copy as JSON").clicked() {
+                    if let Ok(json) = serde_json::to_string_pretty(report) {
+                        ui.ctx().copy_text(json);
+                    }
+                }
+            });
+        });
+
+    changed
+}
@@ -0,0 +1,47 @@
+use eframe::egui;
+
+/// Plot of the average spectrum of the rendered part of the file.
+/// Returns true if the plot should be saved.
+pub fn show(
+    ctx: &egui::Context,
+    is_open: &mut bool,
+    plot: Option<&egui::TextureHandle>,
+    measuring: bool,
+) -> bool {
+    let mut save_clicked = false;
+    egui::Window::new("Average spectrum")
+        .open(is_open)
+        .pivot(egui::Align2::CENTER_CENTER)
+        .default_pos(ctx.content_rect().center())
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            let Some(texture) = plot else {
+                ui.horizontal(|ui| {
+                    if measuring {
+                        ui.spinner();
+                        ui.label("Measuring...");
+                    } else {
+                        ui.label("No spectrum for this file.");
+                    }
+                });
+                return;
+            };
+
+            let pixels_per_point = ui.ctx().pixels_per_point();
+            let size = texture.size_vec2() / pixels_per_point;
+            ui.image((texture.id(), size));
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Save...")
+                    .on_hover_text("Save the plot as PNG or the levels of every frequency as CSV.")
+                    .clicked()
+                {
+                    save_clicked = true;
+                }
+                ui.label("Averaged over the rendered time range, with the window function, FFT size and overlap of the spectrogram.");
+            });
+        });
+    save_clicked
+}
@@ -0,0 +1,294 @@
+use eframe::egui::{self, Color32, Pos2, Rect, Stroke};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use super::cursor::SpectrogramArea;
+use super::MyApp;
+use crate::settings::ViewRange;
+use crate::utils;
+
+/// Shortest time range that can be zoomed into, in seconds.
+const MIN_TIME_SPAN: f64 = 0.01;
+/// Narrowest frequency range that can be zoomed into, in Hz.
+const MIN_FREQ_SPAN: f32 = 50.0;
+/// Mouse wheel zooming is applied after the wheel stops for this long.
+const WHEEL_DELAY: f64 = 0.35;
+/// Zoom factor of a single mouse wheel step.
+const WHEEL_ZOOM: f64 = 0.8;
+/// Part of the visible time range panned by a single mouse wheel step.
+const WHEEL_PAN: f64 = 0.1;
+
+impl MyApp {
+    /// Rubber band selection and mouse wheel zoom over the spectrogram.
+    pub(super) fn handle_zoom(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        area: &SpectrogramArea,
+    ) {
+        if self.tab.input_path.is_none() || self.tab.is_generating {
+            self.zoom_drag_start = None;
+            return;
+        }
+
+        if response.drag_started_by(egui::PointerButton::Primary) {
+            self.zoom_drag_start = response
+                .interact_pointer_pos()
+                .filter(|pos| area.rect.contains(*pos));
+        }
+
+        if let (Some(start), Some(current)) =
+            (self.zoom_drag_start, response.interact_pointer_pos())
+        {
+            let selection = Rect::from_two_pos(start, area.rect.clamp(current));
+            if response.drag_stopped() {
+                self.zoom_drag_start = None;
+                self.zoom_to_selection(ui.ctx(), area, selection);
+            } else {
+                let painter = ui.painter_at(area.rect);
+                painter.rect_filled(selection, 0.0, Color32::from_white_alpha(24));
+                painter.rect_stroke(
+                    selection,
+                    0.0,
+                    Stroke::new(1.0, Color32::WHITE),
+                    egui::StrokeKind::Inside,
+                );
+            }
+        }
+
+        if let Some(pointer) = response.hover_pos().filter(|pos| area.rect.contains(*pos)) {
+            let steps = ui.input(|i| {
+                i.events
+                    .iter()
+                    .filter_map(|event| match event {
+                        egui::Event::MouseWheel {
+                            delta, modifiers, ..
+                        } => Some(if modifiers.shift {
+                            egui::vec2(delta.y, 0.0)
+                        } else {
+                            *delta
+                        }),
+                        _ => None,
+                    })
+                    .fold(egui::Vec2::ZERO, |sum, delta| sum + delta.normalized())
+            });
+            if steps != egui::Vec2::ZERO {
+                self.wheel_zoom(ui, area, pointer, steps);
+            }
+        }
+    }
+
+    /// Zooms into the time and frequency ranges covered by `selection`.
+    /// A selection too thin in one direction keeps that axis unchanged.
+    fn zoom_to_selection(&mut self, ctx: &egui::Context, area: &SpectrogramArea, selection: Rect) {
+        if selection.width() < 4.0 && selection.height() < 4.0 {
+            return;
+        }
+        let a = area.point_at(selection.min);
+        let b = area.point_at(selection.max);
+
+        let (time_len, freq_len) = if area.horizontal {
+            (selection.height(), selection.width())
+        } else {
+            (selection.width(), selection.height())
+        };
+
+        let mut view = self.settings.view;
+        if time_len >= 4.0 {
+            let (start, end) = (a.time.min(b.time), a.time.max(b.time));
+            let center = (start + end) / 2.0;
+            let span = (end - start).max(MIN_TIME_SPAN) / 2.0;
+            view.time = Some((center - span, center + span));
+        }
+        // A selection spanning several channel lanes can't be mapped to one frequency range
+        if freq_len >= 4.0 && a.lane == b.lane {
+            let (min, max) = (a.frequency.min(b.frequency), a.frequency.max(b.frequency));
+            let center = (min + max) / 2.0;
+            let span = (max - min).max(MIN_FREQ_SPAN) / 2.0;
+            view.frequency = Some((center - span, center + span));
+        }
+        // The minimum spans can reach past the edges, the window is moved back inside
+        if let Some(info) = &self.tab.audio_info {
+            view = view.fitted(info.duration, info.sample_rate as f32 / 2.0);
+        }
+        self.set_view(ctx, view);
+    }
+
+    /// Vertical steps zoom the time axis around the pointer, horizontal ones pan it.
+    /// The view is re-rendered once the wheel stops.
+    fn wheel_zoom(
+        &mut self,
+        ui: &egui::Ui,
+        area: &SpectrogramArea,
+        pointer: Pos2,
+        steps: egui::Vec2,
+    ) {
+        let Some(duration) = self.tab.audio_info.as_ref().map(|info| info.duration) else {
+            return;
+        };
+        let mut view = self.tab.pending_view.unwrap_or(self.settings.view);
+        let (start, end) = view.time_range(duration);
+        let span = end - start;
+
+        // Keep the time under the pointer in place
+        let anchor_fraction = area.time_fraction(pointer);
+        let anchor = start + anchor_fraction * span;
+
+        let new_span =
+            (span * WHEEL_ZOOM.powf(steps.y as f64)).clamp(MIN_TIME_SPAN.min(duration), duration);
+        let mut new_start = anchor - anchor_fraction * new_span;
+        new_start -= steps.x as f64 * WHEEL_PAN * new_span;
+        let new_start = new_start.clamp(0.0, duration - new_span);
+
+        view.time = if new_span >= duration {
+            None
+        } else {
+            Some((new_start, new_start + new_span))
+        };
+        self.tab.pending_view = Some(view);
+        self.tab.pending_view_since = ui.input(|i| i.time);
+        ui.ctx()
+            .request_repaint_after(Duration::from_secs_f64(WHEEL_DELAY));
+    }
+
+    /// Applies the mouse wheel zoom once the wheel has been idle for a moment.
+    pub(super) fn apply_pending_zoom(&mut self, ctx: &egui::Context) {
+        let Some(view) = self.tab.pending_view else {
+            return;
+        };
+        let elapsed = ctx.input(|i| i.time) - self.tab.pending_view_since;
+        if elapsed < WHEEL_DELAY {
+            ctx.request_repaint_after(Duration::from_secs_f64(WHEEL_DELAY - elapsed));
+            return;
+        }
+        self.tab.pending_view = None;
+        if view != self.settings.view {
+            self.set_view(ctx, view);
+        }
+    }
+
+    /// Shows `view` and remembers the current one so it can be restored with `zoom_out`.
+    fn set_view(&mut self, ctx: &egui::Context, view: ViewRange) {
+        self.tab.view_history.push(self.settings.view);
+        self.settings.view = view;
+        self.regenerate_spectrogram(ctx);
+    }
+
+    /// Start and end of the shown part of the file, edited values are applied like the
+    /// mouse wheel zoom once they stop changing.
+    pub(super) fn show_time_range_controls(&mut self, ui: &mut egui::Ui) {
+        let Some(duration) = self.tab.audio_info.as_ref().map(|info| info.duration) else {
+            return;
+        };
+        let mut view = self.tab.pending_view.unwrap_or(self.settings.view);
+        let (mut start, mut end) = view.time_range(duration);
+        let changed = ui
+            .horizontal(|ui| {
+                ui.label("Time:");
+                let start_changed = ui
+                    .add(time_drag(&mut start, 0.0..=end - MIN_TIME_SPAN))
+                    .on_hover_text("Start of the shown part, in seconds or m:ss")
+                    .changed();
+                ui.label("-");
+                let end_changed = ui
+                    .add(time_drag(&mut end, start + MIN_TIME_SPAN..=duration))
+                    .on_hover_text("End of the shown part")
+                    .changed();
+                start_changed || end_changed
+            })
+            .inner;
+        if !changed {
+            return;
+        }
+
+        view.time = if start <= 0.0 && end >= duration {
+            None
+        } else {
+            Some((start, end))
+        };
+        self.tab.pending_view = Some(view);
+        self.tab.pending_view_since = ui.input(|i| i.time);
+        ui.ctx()
+            .request_repaint_after(Duration::from_secs_f64(WHEEL_DELAY));
+    }
+
+    /// Goes back to the previous view.
+    pub(super) fn zoom_out(&mut self, ctx: &egui::Context) {
+        self.tab.pending_view = None;
+        if let Some(view) = self.tab.view_history.pop() {
+            self.settings.view = view;
+            self.regenerate_spectrogram(ctx);
+        }
+    }
+
+    /// Goes back to the whole file.
+    pub(super) fn reset_zoom(&mut self, ctx: &egui::Context) {
+        self.tab.pending_view = None;
+        self.tab.view_history.clear();
+        if !self.settings.view.is_full() {
+            self.settings.view = ViewRange::default();
+            self.regenerate_spectrogram(ctx);
+        }
+    }
+
+    /// Forgets the zoom of the previous file.
+    pub(super) fn clear_zoom(&mut self) {
+        self.tab.pending_view = None;
+        self.tab.view_history.clear();
+        self.settings.view = ViewRange::default();
+    }
+
+    /// Visible range and buttons to step back out, on the right side of the status bar.
+    pub(super) fn show_zoom_controls(&mut self, ui: &mut egui::Ui) {
+        let view = self.tab.pending_view.unwrap_or(self.settings.view);
+        if view.is_full() {
+            return;
+        }
+        let Some(info) = self.tab.audio_info.as_ref() else {
+            return;
+        };
+        let (start, end) = view.time_range(info.duration);
+        let nyquist = info.sample_rate as f32 / 2.0;
+        let (min_freq, max_freq) = self
+            .settings
+            .band_view(view, nyquist)
+            .frequency_range(nyquist);
+        let range_text = format!(
+            "{} - {}, {:.0} - {:.0} Hz",
+            utils::format_time(start),
+            utils::format_time(end),
+            min_freq,
+            max_freq
+        );
+
+        let ctx = ui.ctx().clone();
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.add_enabled_ui(!self.tab.is_generating, |ui| {
+                if ui
+                    .small_button("Reset zoom")
+                    .on_hover_text("Show the whole file (Home)")
+                    .clicked()
+                {
+                    self.reset_zoom(&ctx);
+                }
+                if ui
+                    .small_button("Zoom out")
+                    .on_hover_text("Go back to the previous view (Backspace)")
+                    .clicked()
+                {
+                    self.zoom_out(&ctx);
+                }
+            });
+            ui.weak(range_text);
+        });
+    }
+}
+
+/// Drag value of a time in seconds, shown and typed as m:ss.
+fn time_drag(value: &mut f64, range: RangeInclusive<f64>) -> egui::DragValue<'_> {
+    egui::DragValue::new(value)
+        .range(range)
+        .speed(0.1)
+        .custom_formatter(|seconds, _| utils::format_time(seconds))
+        .custom_parser(utils::parse_time)
+}
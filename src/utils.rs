@@ -1,5 +1,14 @@
-use crate::settings::AppSettings;
-use dirs;
+use crate::clipping::ClippingReport;
+use crate::cutoff::{self, CutoffReport};
+use crate::diff;
+use crate::export::{self, DataFormat, DataSource, ImageFormat};
+use crate::legend;
+use crate::loudness::{self, LoudnessReport};
+use crate::settings::{AppSettings, AudioSource, DifferenceMode, FrequencyScale, Strip};
+use crate::spectrum::AverageSpectrum;
+use crate::stereo;
+use crate::stft;
+use crate::waveform::{self, PeakEnvelope};
 use eframe::egui::ColorImage;
 use ffmpeg_sidecar::command::{ffmpeg_is_installed, FfmpegCommand};
 use ffmpeg_sidecar::ffprobe::ffprobe_path;
@@ -19,7 +28,93 @@ pub struct AudioInfo {
     pub sample_rate: u32,
     pub format: String,
     pub bits_per_sample: u32,
+    /// Number of analyzed channels, only the selected ones if the source picks channels.
     pub channels: u32,
+    /// Names of the analyzed channels, e.g. "FL" or "LFE".
+    pub channel_names: Vec<String>,
+    /// Stream and channels this information describes.
+    pub source: AudioSource,
+}
+
+/// Audio stream of a file as listed by ffprobe.
+#[derive(Clone, Debug)]
+pub struct AudioStream {
+    pub codec: String,
+    pub channels: u32,
+    pub channel_layout: Option<String>,
+    pub sample_rate: u32,
+    pub language: Option<String>,
+    pub title: Option<String>,
+}
+
+impl AudioStream {
+    /// Short description, e.g. "aac, 5.1(side), 48000 Hz, eng, Commentary".
+    pub fn description(&self) -> String {
+        let mut parts = vec![
+            self.codec.clone(),
+            self.channel_layout
+                .clone()
+                .unwrap_or_else(|| format!("{} channels", self.channels)),
+            format!("{} Hz", self.sample_rate),
+        ];
+        parts.extend(self.language.clone());
+        parts.extend(self.title.clone());
+        parts.join(", ")
+    }
+
+    pub fn channel_names(&self) -> Vec<String> {
+        channel_names(self.channel_layout.as_deref(), self.channels)
+    }
+}
+
+/// Channel order of ffmpeg's named channel layouts.
+const CHANNEL_LAYOUTS: [(&str, &str); 27] = [
+    ("mono", "FC"),
+    ("stereo", "FL+FR"),
+    ("2.1", "FL+FR+LFE"),
+    ("3.0", "FL+FR+FC"),
+    ("3.0(back)", "FL+FR+BC"),
+    ("4.0", "FL+FR+FC+BC"),
+    ("quad", "FL+FR+BL+BR"),
+    ("quad(side)", "FL+FR+SL+SR"),
+    ("3.1", "FL+FR+FC+LFE"),
+    ("5.0", "FL+FR+FC+BL+BR"),
+    ("5.0(side)", "FL+FR+FC+SL+SR"),
+    ("4.1", "FL+FR+FC+LFE+BC"),
+    ("5.1", "FL+FR+FC+LFE+BL+BR"),
+    ("5.1(side)", "FL+FR+FC+LFE+SL+SR"),
+    ("6.0", "FL+FR+FC+BC+SL+SR"),
+    ("6.0(front)", "FL+FR+FLC+FRC+SL+SR"),
+    ("hexagonal", "FL+FR+FC+BL+BR+BC"),
+    ("6.1", "FL+FR+FC+LFE+BC+SL+SR"),
+    ("6.1(back)", "FL+FR+FC+LFE+BL+BR+BC"),
+    ("6.1(front)", "FL+FR+LFE+FLC+FRC+SL+SR"),
+    ("7.0", "FL+FR+FC+BL+BR+SL+SR"),
+    ("7.0(front)", "FL+FR+FC+FLC+FRC+SL+SR"),
+    ("7.1", "FL+FR+FC+LFE+BL+BR+SL+SR"),
+    ("7.1(wide)", "FL+FR+FC+LFE+BL+BR+FLC+FRC"),
+    ("7.1(wide-side)", "FL+FR+FC+LFE+FLC+FRC+SL+SR"),
+    ("octagonal", "FL+FR+FC+BL+BR+BC+SL+SR"),
+    ("downmix", "DL+DR"),
+];
+
+/// Names of the channels of a layout reported by ffprobe, e.g. "5.1" or "FL+FR+LFE".
+/// Unknown layouts fall back to "Ch 1", "Ch 2", ...
+pub fn channel_names(layout: Option<&str>, channels: u32) -> Vec<String> {
+    let layout = layout.map(|layout| {
+        CHANNEL_LAYOUTS
+            .iter()
+            .find(|(name, _)| *name == layout)
+            .map_or(layout, |(_, channels)| channels)
+    });
+    let names: Vec<String> = layout
+        .map(|layout| layout.split('+').map(str::to_string).collect())
+        .unwrap_or_default();
+    if names.len() == channels as usize {
+        names
+    } else {
+        (1..=channels).map(|c| format!("Ch {}", c)).collect()
+    }
 }
 
 /// Converts an `image::RgbaImage` to an `eframe::egui::ColorImage`.
@@ -29,29 +124,35 @@ pub fn rgba_image_to_color_image(rgba_image: &RgbaImage) -> ColorImage {
     ColorImage::from_rgba_unmultiplied(size, pixels)
 }
 
-/// Retrieves audio information (duration, sample rate, format, and bit depth) using ffprobe.
-pub fn get_audio_info(input_path: &str) -> Option<AudioInfo> {
+fn ffprobe_command() -> Command {
     let ffprobe = match ffmpeg_is_installed() {
         true => ffprobe_path(),
         false => get_ffmpeg_paths().ffprobe,
     };
 
+    #[allow(unused_mut)]
     let mut command = Command::new(ffprobe);
+    #[cfg(windows)]
+    command.creation_flags(0x08000000); // CREATE_NO_WINDOW
+    command
+}
+
+/// Retrieves audio information (duration, sample rate, format, and bit depth) of the
+/// selected stream and channels using ffprobe.
+pub fn get_audio_info(input_path: &str, source: &AudioSource) -> Option<AudioInfo> {
+    let mut command = ffprobe_command();
     command.args([
         "-v",
         "error",
         "-select_streams",
-        "a:0",
+        &format!("a:{}", source.stream),
         "-show_entries",
-        "stream=duration,sample_rate,bits_per_sample,bits_per_raw_sample,codec_name,channels:format=format_name",
+        "stream=duration,sample_rate,bits_per_sample,bits_per_raw_sample,codec_name,channels,channel_layout:format=format_name,duration",
         "-of",
         "default=noprint_wrappers=1",
         input_path,
     ]);
 
-    #[cfg(windows)]
-    command.creation_flags(0x08000000); // CREATE_NO_WINDOW
-
     let output = command.output().ok()?;
 
     if !output.status.success() {
@@ -67,18 +168,21 @@ pub fn get_audio_info(input_path: &str) -> Option<AudioInfo> {
     let mut bits_per_sample = None;
     let mut bits_per_raw_sample = None;
     let mut channels = None;
+    let mut channel_layout = None;
 
     for line in output_str.lines() {
         let parts: Vec<&str> = line.split('=').collect();
         if parts.len() == 2 {
             match parts[0] {
-                "duration" => duration = parts[1].parse::<f64>().ok(),
+                // Streams in containers like MKV have no duration, the format one is used then
+                "duration" => duration = duration.or(parts[1].parse::<f64>().ok()),
                 "sample_rate" => sample_rate = parts[1].parse::<u32>().ok(),
                 "format_name" => format_name = Some(parts[1].to_string()),
                 "codec_name" => codec_name = Some(parts[1].to_string()),
                 "bits_per_sample" => bits_per_sample = parts[1].parse::<u32>().ok(),
                 "bits_per_raw_sample" => bits_per_raw_sample = parts[1].parse::<u32>().ok(),
                 "channels" => channels = parts[1].parse::<u32>().ok(),
+                "channel_layout" => channel_layout = Some(parts[1].to_string()),
                 _ => {}
             }
         }
@@ -101,16 +205,117 @@ pub fn get_audio_info(input_path: &str) -> Option<AudioInfo> {
         codec_name
     };
 
-    match (duration, sample_rate, format, channels) {
-        (Some(d), Some(s), Some(f), Some(c)) => Some(AudioInfo {
-            duration: d,
-            sample_rate: s,
-            format: f,
-            bits_per_sample: final_bits,
-            channels: c,
-        }),
-        _ => None,
+    let (Some(d), Some(s), Some(f), Some(c)) = (duration, sample_rate, format, channels) else {
+        return None;
+    };
+
+    let mut channel_names = channel_names(channel_layout.as_deref(), c);
+    if !source.channels.is_empty() {
+        if let Some(channel) = source
+            .channels
+            .iter()
+            .find(|&&channel| channel >= c as usize)
+        {
+            eprintln!(
+                "Channel {} does not exist, the stream has {} channels.",
+                channel + 1,
+                c
+            );
+            return None;
+        }
+        channel_names = source
+            .channels
+            .iter()
+            .map(|&channel| channel_names[channel].clone())
+            .collect();
+    }
+    if source.mid_side {
+        if channel_names.len() < 2 {
+            eprintln!("Mid/side needs at least two channels.");
+            return None;
+        }
+        channel_names = vec!["Mid".to_string(), "Side".to_string()];
+    }
+
+    Some(AudioInfo {
+        duration: d,
+        sample_rate: s,
+        format: f,
+        bits_per_sample: final_bits,
+        channels: channel_names.len() as u32,
+        channel_names,
+        source: source.clone(),
+    })
+}
+
+/// Information of the channels the whole-file analyses measure. Mid and side are only shown
+/// in the spectrogram, loudness, clipping, cutoff and resolution use the channels as they are.
+pub fn analysis_info(input_path: &str, audio_info: &AudioInfo) -> Option<AudioInfo> {
+    if !audio_info.source.mid_side {
+        return Some(audio_info.clone());
     }
+    get_audio_info(input_path, &audio_info.source.without_mid_side())
+}
+
+/// Lists all audio streams of a file using ffprobe.
+pub fn get_audio_streams(input_path: &str) -> Option<Vec<AudioStream>> {
+    let mut command = ffprobe_command();
+    command.args([
+        "-v",
+        "error",
+        "-select_streams",
+        "a",
+        "-show_entries",
+        "stream=index,codec_name,channels,channel_layout,sample_rate:stream_tags=language,title",
+        "-of",
+        "default=noprint_wrappers=1",
+        input_path,
+    ]);
+
+    let output = command.output().ok()?;
+
+    if !output.status.success() {
+        eprintln!("ffprobe error: {}", String::from_utf8_lossy(&output.stderr));
+        return None;
+    }
+
+    let output_str = String::from_utf8_lossy(&output.stdout);
+    let mut streams = Vec::new();
+    for line in output_str.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        // Every stream starts with its index
+        if key == "index" {
+            streams.push(AudioStream {
+                codec: String::new(),
+                channels: 0,
+                channel_layout: None,
+                sample_rate: 0,
+                language: None,
+                title: None,
+            });
+            continue;
+        }
+        let Some(stream) = streams.last_mut() else {
+            continue;
+        };
+        let value = value.trim();
+        match key {
+            "codec_name" => stream.codec = value.to_string(),
+            "channels" => stream.channels = value.parse().unwrap_or(0),
+            "channel_layout" if !value.is_empty() && value != "unknown" => {
+                stream.channel_layout = Some(value.to_string())
+            }
+            "sample_rate" => stream.sample_rate = value.parse().unwrap_or(0),
+            "TAG:language" if !value.is_empty() && value != "und" => {
+                stream.language = Some(value.to_string())
+            }
+            "TAG:title" if !value.is_empty() => stream.title = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    Some(streams)
 }
 
 /// Generates a spectrogram by calling ffmpeg and captures the output image from stdout.
@@ -123,7 +328,7 @@ pub fn generate_spectrogram_in_memory(
 ) -> Option<ColorImage> {
     let start = Instant::now();
     println!("Generating spectrogram for: {}", input_path,);
-    println!("{:#?}", settings);
+    log::debug!("{:#?}", settings);
 
     let mode = if settings.split_channels {
         "separate"
@@ -137,8 +342,17 @@ pub fn generate_spectrogram_in_memory(
         "vertical"
     };
 
+    // ffmpeg renders the same clamped range as the legend and the cursor show
+    let (duration, nyquist) = (!settings.view.is_full())
+        .then(|| get_audio_info(input_path, &settings.source))
+        .flatten()
+        .map_or((f64::INFINITY, f32::INFINITY), |info| {
+            (info.duration, info.sample_rate as f32 / 2.0)
+        });
+
     let lavfi_filter = format!(
-        "showspectrumpic=s={}x{}:legend={}:color={}:win_func={}:scale={}:gain={}:saturation={}:mode={}:orientation={}",
+        "{}showspectrumpic=s={}x{}:legend={}:color={}:win_func={}:scale={}:gain={}:saturation={}:mode={}:orientation={}{}{}{}",
+        settings.source.filter_input(),
         width,
         height,
         settings.legend,
@@ -148,18 +362,16 @@ pub fn generate_spectrogram_in_memory(
         settings.gain,
         settings.saturation,
         mode,
-        orientation
+        orientation,
+        settings.level_filter_args(),
+        settings.frequency_scale.ffmpeg_filter_args(),
+        settings.view.ffmpeg_filter_args(nyquist)
     );
 
-    let mut cmd_builder = match ffmpeg_is_installed() {
-        true => FfmpegCommand::new(),
-        false => FfmpegCommand::new_with_path(get_ffmpeg_paths().ffmpeg),
-    };
-
+    let mut cmd_builder = ffmpeg_command();
+    cmd_builder.args(["-hide_banner", "-loglevel", "error"]);
+    cmd_builder.args(settings.view.ffmpeg_input_args(duration));
     cmd_builder.args([
-        "-hide_banner",
-        "-loglevel",
-        "error",
         "-i",
         input_path,
         "-lavfi",
@@ -242,6 +454,385 @@ pub fn generate_spectrogram_in_memory(
     Some(color_image)
 }
 
+/// Renders a complete spectrogram image the same way the GUI does, including the custom legend.
+pub fn render_spectrogram(
+    input_path: &str,
+    settings: &AppSettings,
+    audio_info: Option<AudioInfo>,
+    width: u32,
+    height: u32,
+    cancel_token: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) -> Option<ColorImage> {
+    let mut image = if settings.use_custom_legend() {
+        let mut canvas =
+            draw_legend_canvas(input_path, settings, audio_info.clone(), width, height);
+        let mut thread_settings = settings.clone();
+        // Force ffmpeg legend off when using custom one
+        thread_settings.legend = false;
+        let spectrogram = generate_spectrogram(
+            input_path,
+            &thread_settings,
+            width,
+            height,
+            cancel_token.clone(),
+        )?;
+        composite_spectrogram(&mut canvas, &spectrogram);
+        canvas
+    } else {
+        generate_spectrogram(input_path, settings, width, height, cancel_token.clone())?
+    };
+
+    if let Some(info) = &audio_info {
+        draw_channel_labels(&mut image, settings, info);
+    }
+    let analyzed_info = audio_info
+        .as_ref()
+        .and_then(|info| analysis_info(input_path, info));
+    if settings.detect_cutoff && settings.difference.is_none() {
+        if let (Some(info), Some(analyzed)) = (&audio_info, &analyzed_info) {
+            match cutoff::detect_cutoff(input_path, analyzed, &cancel_token) {
+                Ok(Some(report)) => draw_cutoff_marker(&mut image, settings, info, &report),
+                Ok(None) => {}
+                Err(e) => eprintln!("Failed to detect the frequency cutoff: {}", e),
+            }
+        }
+    }
+    if (settings.loudness_legend || settings.clip_markers) && settings.difference.is_none() {
+        if let (Some(info), Some(analyzed)) = (&audio_info, &analyzed_info) {
+            match loudness::measure_loudness(input_path, analyzed, &cancel_token) {
+                Some(report) => {
+                    draw_loudness_summary(&mut image, settings, &report);
+                    draw_clip_markers(&mut image, settings, info, &report.clipping);
+                }
+                None => eprintln!("Failed to measure the loudness."),
+            }
+        }
+    }
+    if settings.strips().contains(&Strip::Waveform) {
+        if let Some(info) = &audio_info {
+            let time_range = settings.view.time_range(info.duration);
+            match waveform::peak_envelope(
+                input_path,
+                info,
+                time_range,
+                width as usize,
+                &cancel_token,
+            ) {
+                Some(envelopes) => draw_waveform(&mut image, settings, &envelopes),
+                None => eprintln!("Failed to read the waveform."),
+            }
+        }
+    }
+    if settings.strips().contains(&Strip::PhaseCorrelation) {
+        if let Some(info) = &audio_info {
+            let time_range = settings.view.time_range(info.duration);
+            match stereo::phase_correlation(
+                input_path,
+                info,
+                time_range,
+                width as usize,
+                &cancel_token,
+            ) {
+                Some(values) => draw_phase_correlation(&mut image, settings, &values),
+                None => eprintln!("Failed to measure the phase correlation."),
+            }
+        }
+    }
+    Some(image)
+}
+
+/// Top left corner and size of the spectrogram inside a finished image.
+/// Returns `None` for the ffmpeg legend, as its layout is unknown.
+fn spectrogram_bounds(image: &RgbaImage, settings: &AppSettings) -> Option<((u32, u32), u32, u32)> {
+    let (origin, margin_w, margin_h) = if settings.use_custom_legend() {
+        (
+            (legend::LEFT_MARGIN, legend::TOP_MARGIN),
+            legend::LEFT_MARGIN + legend::RIGHT_MARGIN,
+            legend::TOP_MARGIN + legend::bottom_margin(settings.strips().len()),
+        )
+    } else if !settings.legend && !settings.horizontal {
+        ((0, 0), 0, 0)
+    } else {
+        return None;
+    };
+    Some((
+        origin,
+        image.width().saturating_sub(margin_w),
+        image.height().saturating_sub(margin_h),
+    ))
+}
+
+/// Number of channel lanes the spectrogram is split into.
+pub fn channel_lanes(settings: &AppSettings, audio_info: &AudioInfo) -> u32 {
+    if settings.split_channels {
+        audio_info.channels.max(1)
+    } else {
+        1
+    }
+}
+
+/// Draws the detected cutoff over a finished spectrogram image.
+/// Does nothing for the ffmpeg legend.
+pub fn draw_cutoff_marker(
+    image: &mut ColorImage,
+    settings: &AppSettings,
+    audio_info: &AudioInfo,
+    report: &CutoffReport,
+) {
+    let Some(mut rgba_image) = color_image_to_rgba_image(image) else {
+        return;
+    };
+    let Some((origin, spec_width, spec_height)) = spectrogram_bounds(&rgba_image, settings) else {
+        return;
+    };
+    legend::draw_cutoff_marker(
+        &mut rgba_image,
+        origin,
+        spec_width,
+        spec_height,
+        channel_lanes(settings, audio_info),
+        settings.frequency_axis(audio_info.sample_rate as f32 / 2.0),
+        report,
+        settings.use_custom_legend(),
+    );
+    *image = rgba_image_to_color_image(&rgba_image);
+}
+
+/// Writes the loudness summary into the header of a finished image.
+/// Does nothing without the custom legend.
+pub fn draw_loudness_summary(
+    image: &mut ColorImage,
+    settings: &AppSettings,
+    report: &LoudnessReport,
+) {
+    if !settings.loudness_legend || !settings.use_custom_legend() {
+        return;
+    }
+    let Some(mut rgba_image) = color_image_to_rgba_image(image) else {
+        return;
+    };
+    legend::draw_header_note(
+        &mut rgba_image,
+        &report.summary(),
+        settings.custom_legend_bg_color,
+        settings.custom_legend_text_color,
+    );
+    *image = rgba_image_to_color_image(&rgba_image);
+}
+
+/// Marks the clipping events under the time scale of a finished image.
+/// Does nothing without the custom legend.
+pub fn draw_clip_markers(
+    image: &mut ColorImage,
+    settings: &AppSettings,
+    audio_info: &AudioInfo,
+    report: &ClippingReport,
+) {
+    if !settings.clip_markers || !settings.use_custom_legend() {
+        return;
+    }
+    let Some(mut rgba_image) = color_image_to_rgba_image(image) else {
+        return;
+    };
+    let Some((origin, spec_width, spec_height)) = spectrogram_bounds(&rgba_image, settings) else {
+        return;
+    };
+    legend::draw_clip_markers(
+        &mut rgba_image,
+        origin,
+        spec_width,
+        spec_height,
+        settings.view.time_range(audio_info.duration),
+        &report.events,
+    );
+    *image = rgba_image_to_color_image(&rgba_image);
+}
+
+/// Plots the peak waveform into its strip under a finished spectrogram.
+/// Does nothing if the strip is not shown.
+pub fn draw_waveform(image: &mut ColorImage, settings: &AppSettings, envelopes: &[PeakEnvelope]) {
+    let Some(index) = settings
+        .strips()
+        .iter()
+        .position(|&strip| strip == Strip::Waveform)
+    else {
+        return;
+    };
+    let Some(mut rgba_image) = color_image_to_rgba_image(image) else {
+        return;
+    };
+    let Some((origin, spec_width, spec_height)) = spectrogram_bounds(&rgba_image, settings) else {
+        return;
+    };
+    legend::draw_waveform(
+        &mut rgba_image,
+        origin.0,
+        legend::strip_top(spec_height, index),
+        spec_width,
+        envelopes,
+        settings.split_channels && envelopes.len() > 1,
+        settings.custom_legend_line_color,
+    );
+    *image = rgba_image_to_color_image(&rgba_image);
+}
+
+/// Plots the phase correlation into its strip under a finished spectrogram.
+/// Does nothing if the strip is not shown.
+pub fn draw_phase_correlation(
+    image: &mut ColorImage,
+    settings: &AppSettings,
+    values: &[Option<f32>],
+) {
+    let Some(index) = settings
+        .strips()
+        .iter()
+        .position(|&strip| strip == Strip::PhaseCorrelation)
+    else {
+        return;
+    };
+    let Some(mut rgba_image) = color_image_to_rgba_image(image) else {
+        return;
+    };
+    let Some((origin, spec_width, spec_height)) = spectrogram_bounds(&rgba_image, settings) else {
+        return;
+    };
+    legend::draw_phase_correlation(
+        &mut rgba_image,
+        origin.0,
+        legend::strip_top(spec_height, index),
+        spec_width,
+        values,
+        settings.custom_legend_line_color,
+    );
+    *image = rgba_image_to_color_image(&rgba_image);
+}
+
+/// Writes the channel names into the lanes of a finished split channel spectrogram.
+/// Does nothing for the ffmpeg legend.
+pub fn draw_channel_labels(image: &mut ColorImage, settings: &AppSettings, audio_info: &AudioInfo) {
+    if channel_lanes(settings, audio_info) < 2 {
+        return;
+    }
+    let Some(mut rgba_image) = color_image_to_rgba_image(image) else {
+        return;
+    };
+    let Some((origin, spec_width, spec_height)) = spectrogram_bounds(&rgba_image, settings) else {
+        return;
+    };
+    legend::draw_channel_labels(
+        &mut rgba_image,
+        origin,
+        spec_width,
+        spec_height,
+        &audio_info.channel_names,
+    );
+    *image = rgba_image_to_color_image(&rgba_image);
+}
+
+/// Generates the spectrogram image (without the custom legend) using the selected engine,
+/// or the difference to a second file if one is set.
+pub fn generate_spectrogram(
+    input_path: &str,
+    settings: &AppSettings,
+    width: u32,
+    height: u32,
+    cancel_token: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) -> Option<ColorImage> {
+    if settings.difference.is_some() {
+        return diff::generate_difference(input_path, settings, width, height, cancel_token);
+    }
+    if settings.native_engine() {
+        stft::generate_spectrogram(input_path, settings, width, height, cancel_token)
+    } else {
+        generate_spectrogram_in_memory(input_path, settings, width, height, cancel_token)
+    }
+}
+
+/// Draws the custom legend template for the given file and settings.
+pub fn draw_legend_canvas(
+    input_path: &str,
+    settings: &AppSettings,
+    audio_info: Option<AudioInfo>,
+    width: u32,
+    height: u32,
+) -> ColorImage {
+    let file_name = |path: &str| {
+        Path::new(path)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Unknown File")
+            .to_string()
+    };
+    let mut filename = file_name(input_path);
+    let mut ffmpeg_settings = format!(
+        "{}, {}, {}",
+        settings.win_func, settings.scale, settings.color_scheme
+    );
+    if settings.frequency_scale != FrequencyScale::Linear {
+        ffmpeg_settings.push_str(&format!(", {} axis", settings.frequency_scale));
+    }
+    if let Some(fft_size) = settings.fft_size {
+        ffmpeg_settings.push_str(&format!(", FFT {}", fft_size));
+    }
+    if settings.overlap > 0.0 {
+        ffmpeg_settings.push_str(&format!(", {:.0}% overlap", settings.overlap * 100.0));
+    }
+    if settings.native_engine() {
+        ffmpeg_settings.push_str(", Native");
+    }
+
+    let mut delta_range = None;
+    if let Some(difference) = &settings.difference {
+        filename = format!("{} − {}", filename, file_name(&difference.path));
+        ffmpeg_settings = match difference.mode {
+            DifferenceMode::Delta => {
+                delta_range = Some(difference.range_db);
+                format!("{}, {}", settings.win_func, difference.mode)
+            }
+            DifferenceMode::Signal => format!(
+                "{}, {}, {}, {}",
+                settings.win_func, settings.scale, settings.color_scheme, difference.mode
+            ),
+        };
+        if difference.offset != 0.0 {
+            ffmpeg_settings.push_str(&format!(", offset {:+.1} ms", difference.offset * 1000.0));
+        }
+    }
+
+    let legend_rgba = legend::draw_legend(
+        width,
+        height,
+        &filename,
+        &ffmpeg_settings,
+        audio_info,
+        settings.view,
+        settings.frequency_scale,
+        settings.saturation,
+        settings.color_scheme,
+        (settings.db_limit - settings.db_range, settings.db_limit),
+        delta_range,
+        settings.split_channels,
+        &settings.strips(),
+        settings.custom_legend_bg_color,
+        settings.custom_legend_text_color,
+        settings.custom_legend_line_color,
+    );
+    rgba_image_to_color_image(&legend_rgba)
+}
+
+/// Copies a spectrogram into the empty area of a custom legend template.
+pub fn composite_spectrogram(canvas: &mut ColorImage, spectrogram: &ColorImage) {
+    for y in 0..spectrogram.height() {
+        for x in 0..spectrogram.width() {
+            let dest_x = x + legend::LEFT_MARGIN as usize;
+            let dest_y = y + legend::TOP_MARGIN as usize;
+            if dest_x < canvas.width() && dest_y < canvas.height() {
+                canvas[(dest_x, dest_y)] = spectrogram[(x, y)];
+            }
+        }
+    }
+}
+
 pub fn stream_spectrogram_frames(
     sender: Sender<Option<ColorImage>>,
     input_path: &str,
@@ -252,17 +843,19 @@ pub fn stream_spectrogram_frames(
 ) {
     let start = Instant::now();
     println!("Generating spectrogram for: {}", input_path,);
-    println!("{:#?}", settings);
+    log::debug!("{:#?}", settings);
 
-    let duration = match get_audio_info(input_path) {
-        Some(info) if info.duration > 0.0 => info.duration,
+    let audio_info = match get_audio_info(input_path, &settings.source) {
+        Some(info) if info.duration > 0.0 => info,
         _ => {
             eprintln!("Failed to get valid audio duration.");
             return;
         }
     };
+    let nyquist = audio_info.sample_rate as f32 / 2.0;
+    let (view_start, view_end) = settings.view.time_range(audio_info.duration);
 
-    let fps = width as f64 / duration;
+    let fps = width as f64 / (view_end - view_start);
     let mode = if settings.split_channels {
         "separate"
     } else {
@@ -276,7 +869,8 @@ pub fn stream_spectrogram_frames(
     let temp_width = 10;
 
     let lavfi_filter = format!(
-        "showspectrum=s={}x{}:legend=0:color={}:win_func={}:scale={}:gain={}:saturation={}:mode={}:orientation={}:slide=scroll",
+        "{}showspectrum=s={}x{}:legend=0:color={}:win_func={}:scale={}:gain={}:saturation={}:mode={}:orientation={}:slide=scroll{}{}{}",
+        settings.source.filter_input(),
         temp_width,
         height,
         settings.color_scheme.as_str(),
@@ -286,13 +880,15 @@ pub fn stream_spectrogram_frames(
         settings.saturation,
         mode,
         "vertical", // orientation
+        settings.level_filter_args(),
+        settings.frequency_scale.ffmpeg_filter_args(),
+        settings.view.ffmpeg_filter_args(nyquist)
     );
 
     let mut cmd_builder = FfmpegCommand::new();
+    cmd_builder.args(["-hide_banner", "-loglevel", "error"]);
+    cmd_builder.args(settings.view.ffmpeg_input_args(audio_info.duration));
     cmd_builder.args([
-        "-hide_banner",
-        "-loglevel",
-        "error",
         "-i",
         input_path,
         "-lavfi",
@@ -377,6 +973,26 @@ pub fn stream_spectrogram_frames(
     println!("Spectrogram generated in {:?}.", start.elapsed());
 }
 
+/// Formats seconds as `m:ss.mmm`.
+pub fn format_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0);
+    let minutes = (seconds / 60.0).floor();
+    format!("{}:{:06.3}", minutes as u64, seconds - minutes * 60.0)
+}
+
+/// Parses a time given as seconds, `m:ss` or `h:mm:ss`, seconds may have a fraction.
+pub fn parse_time(text: &str) -> Option<f64> {
+    let parts: Vec<&str> = text.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0.0;
+    for part in parts {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    (seconds.is_finite() && seconds >= 0.0).then_some(seconds)
+}
+
 pub fn cycle_option<T: PartialEq + Clone>(current: T, values: &[T], up: bool) -> T {
     let current_index = values.iter().position(|c| c == &current).unwrap_or(0);
     let new_index = if up {
@@ -391,7 +1007,15 @@ pub fn cycle_option<T: PartialEq + Clone>(current: T, values: &[T], up: bool) ->
     values[new_index].clone()
 }
 
-pub fn save_image(image: &Option<ColorImage>, input_path: &String) {
+/// Asks where to save the spectrogram, in the `ImageFormat` of the chosen extension with
+/// `jpeg_quality` for JPEG. With `data`, the levels behind the image can also be saved in
+/// one of the `DataFormat`s, they are computed in the background.
+pub fn save_image(
+    image: &Option<ColorImage>,
+    input_path: &String,
+    data: Option<DataSource>,
+    jpeg_quality: u8,
+) {
     if let Some(image) = image {
         let input_filename = Path::new(input_path)
             .file_stem()
@@ -399,16 +1023,29 @@ pub fn save_image(image: &Option<ColorImage>, input_path: &String) {
             .unwrap_or("spectrogram")
             .replace("/", "-");
 
-        if let Some(path) = rfd::FileDialog::new()
-            .add_filter("PNG Image", &["png"])
-            .set_file_name(&format!("{}.png", input_filename))
+        let mut dialog = rfd::FileDialog::new();
+        for format in ImageFormat::COLOR {
+            dialog = dialog.add_filter(format.to_string(), format.extensions());
+        }
+        if data.is_some() {
+            for format in DataFormat::VALUES {
+                dialog = dialog.add_filter(format.to_string(), &[format.as_str()]);
+            }
+        }
+        if let Some(path) = dialog
+            .set_file_name(format!("{}.png", input_filename))
             .save_file()
         {
-            let pixels: Vec<u8> = image.pixels.iter().flat_map(|p| p.to_array()).collect();
-            if let Some(rgba_image) =
-                image::RgbaImage::from_raw(image.width() as u32, image.height() as u32, pixels)
-            {
-                if let Err(e) = rgba_image.save(&path) {
+            if let (Some(data), Some(_)) = (data, DataFormat::from_path(&path)) {
+                std::thread::spawn(move || match data.export(&path) {
+                    Ok(_) => println!("Data saved to {:?}", path),
+                    Err(e) => eprintln!("Failed to export data: {}", e),
+                });
+                return;
+            }
+            let format = ImageFormat::from_path(&path).unwrap_or(ImageFormat::Png);
+            if let Some(rgba_image) = color_image_to_rgba_image(image) {
+                if let Err(e) = export::save_image(&rgba_image, &path, format, jpeg_quality) {
                     eprintln!("Failed to save image: {}", e);
                 } else {
                     println!("Image saved to {:?}", path);
@@ -418,6 +1055,111 @@ pub fn save_image(image: &Option<ColorImage>, input_path: &String) {
     }
 }
 
+/// Asks where to save the levels of `data` as 16-bit grayscale PNG, they are computed
+/// in the background.
+pub fn save_intensity(input_path: &str, data: DataSource) {
+    let input_filename = Path::new(input_path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("spectrogram")
+        .replace("/", "-");
+
+    if let Some(path) = rfd::FileDialog::new()
+        .add_filter(ImageFormat::GrayPng16.to_string(), &["png"])
+        .set_file_name(format!("{}-16bit.png", input_filename))
+        .save_file()
+    {
+        std::thread::spawn(move || match data.save_intensity(&path) {
+            Ok(()) => println!("Image saved to {:?}", path),
+            Err(e) => eprintln!("Failed to save image: {}", e),
+        });
+    }
+}
+
+/// Plot of the average spectrum of `input_path` with the frequency axis, level range
+/// and legend colors of `settings`.
+pub fn draw_spectrum_plot(
+    input_path: &str,
+    settings: &AppSettings,
+    spectrum: &AverageSpectrum,
+    width: u32,
+    height: u32,
+) -> RgbaImage {
+    let filename = Path::new(input_path)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Unknown File");
+    let request = &spectrum.request;
+    let mut details = format!(
+        "{} Hz, {} - {}, {}, FFT {}",
+        spectrum.sample_rate,
+        format_time(request.time_range.0),
+        format_time(request.time_range.1),
+        request.win_func,
+        request.fft_size
+    );
+    if request.overlap > 0.0 {
+        details.push_str(&format!(", {:.0}% overlap", request.overlap * 100.0));
+    }
+    legend::draw_spectrum_plot(
+        spectrum,
+        filename,
+        &details,
+        settings.frequency_axis(spectrum.sample_rate as f32 / 2.0),
+        (settings.db_limit - settings.db_range, settings.db_limit),
+        width,
+        height,
+        settings.custom_legend_bg_color,
+        settings.custom_legend_text_color,
+        settings.custom_legend_line_color,
+    )
+}
+
+/// Asks where to save the average spectrum and writes the plot in the `ImageFormat` of the
+/// chosen extension with `jpeg_quality` for JPEG, or the levels as CSV.
+pub fn save_spectrum(
+    spectrum: &AverageSpectrum,
+    plot: &ColorImage,
+    input_path: &str,
+    jpeg_quality: u8,
+) {
+    let input_filename = Path::new(input_path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("spectrum")
+        .replace("/", "-");
+
+    let mut dialog = rfd::FileDialog::new();
+    for format in ImageFormat::COLOR {
+        dialog = dialog.add_filter(format.to_string(), format.extensions());
+    }
+    let Some(path) = dialog
+        .add_filter(DataFormat::Csv.to_string(), &[DataFormat::Csv.as_str()])
+        .set_file_name(format!("{}-spectrum.png", input_filename))
+        .save_file()
+    else {
+        return;
+    };
+    let result = if DataFormat::from_path(&path) == Some(DataFormat::Csv) {
+        spectrum.write_csv(&path).map_err(|e| e.to_string())
+    } else {
+        let format = ImageFormat::from_path(&path).unwrap_or(ImageFormat::Png);
+        color_image_to_rgba_image(plot)
+            .ok_or("failed to convert the plot".to_string())
+            .and_then(|image| export::save_image(&image, &path, format, jpeg_quality))
+    };
+    match result {
+        Ok(()) => println!("Spectrum saved to {:?}", path),
+        Err(e) => eprintln!("Failed to save spectrum: {}", e),
+    }
+}
+
+/// Converts an `eframe::egui::ColorImage` back to an `image::RgbaImage`.
+pub fn color_image_to_rgba_image(image: &ColorImage) -> Option<RgbaImage> {
+    let pixels: Vec<u8> = image.pixels.iter().flat_map(|p| p.to_array()).collect();
+    RgbaImage::from_raw(image.width() as u32, image.height() as u32, pixels)
+}
+
 pub struct FfmpegPaths {
     pub directory: PathBuf,
     pub ffmpeg: PathBuf,
@@ -446,3 +1188,19 @@ pub fn get_ffmpeg_paths() -> FfmpegPaths {
         ffprobe,
     }
 }
+
+/// Returns true if ffmpeg is available either on PATH or in the app data directory.
+pub fn ffmpeg_available() -> bool {
+    if ffmpeg_is_installed() {
+        return true;
+    }
+    let ff_paths = get_ffmpeg_paths();
+    ff_paths.ffmpeg.exists() && ff_paths.ffprobe.exists()
+}
+
+pub fn ffmpeg_command() -> FfmpegCommand {
+    match ffmpeg_is_installed() {
+        true => FfmpegCommand::new(),
+        false => FfmpegCommand::new_with_path(get_ffmpeg_paths().ffmpeg),
+    }
+}
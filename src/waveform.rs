@@ -0,0 +1,44 @@
+use std::sync::atomic::AtomicBool;
+
+use crate::pcm::PcmDecoder;
+use crate::utils::AudioInfo;
+
+/// Lowest and highest sample of one channel in every column.
+pub type PeakEnvelope = Vec<(f32, f32)>;
+
+/// Lowest and highest sample of every channel in each of `columns` evenly spaced time slices
+/// of `time_range`, indexed by channel and then by column.
+/// Returns `None` if decoding failed or was cancelled.
+pub fn peak_envelope(
+    input_path: &str,
+    audio_info: &AudioInfo,
+    time_range: (f64, f64),
+    columns: usize,
+    cancel_token: &AtomicBool,
+) -> Option<Vec<PeakEnvelope>> {
+    if columns == 0 {
+        return None;
+    }
+    let decoder = PcmDecoder::spawn(
+        input_path,
+        &audio_info.source,
+        audio_info.channels,
+        audio_info.sample_rate,
+        Some(time_range),
+    )?;
+    let total = ((time_range.1 - time_range.0) * audio_info.sample_rate as f64) as usize;
+
+    let mut envelopes = vec![vec![(0.0f32, 0.0f32); columns]; decoder.channels];
+    let finished =
+        decoder.for_each_frame_in_columns(total, columns, cancel_token, |column, chunk| {
+            for (envelope, &sample) in envelopes.iter_mut().zip(chunk) {
+                let (min, max) = &mut envelope[column];
+                *min = min.min(sample);
+                *max = max.max(sample);
+            }
+        });
+    if !finished {
+        return None;
+    }
+    Some(envelopes)
+}